@@ -0,0 +1,248 @@
+//! DATEV Buchungsstapel in the EXTF format, which accounting offices import
+//! into DATEV Kanzlei-Rechnungswesen.
+
+use super::{format_cents, AccountingConfig, Posting};
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// The leading columns of the Buchungsstapel layout, which are all this
+/// export fills. DATEV accepts files that leave out the trailing ones.
+const COLUMNS: [&str; 14] = [
+    "Umsatz (ohne Soll/Haben-Kz)",
+    "Soll/Haben-Kennzeichen",
+    "WKZ Umsatz",
+    "Kurs",
+    "Basis-Umsatz",
+    "WKZ Basis-Umsatz",
+    "Konto",
+    "Gegenkonto (ohne BU-Schlüssel)",
+    "BU-Schlüssel",
+    "Belegdatum",
+    "Belegfeld 1",
+    "Belegfeld 2",
+    "Skonto",
+    "Buchungstext",
+];
+
+/// Latin letters Windows-1252 lacks, which are common in names of
+/// recipients, with the letter DATEV gets instead.
+const TRANSLITERATIONS: [(char, char); 44] = [
+    ('Ă', 'A'),
+    ('ă', 'a'),
+    ('Ą', 'A'),
+    ('ą', 'a'),
+    ('Ć', 'C'),
+    ('ć', 'c'),
+    ('Č', 'C'),
+    ('č', 'c'),
+    ('Ď', 'D'),
+    ('ď', 'd'),
+    ('Đ', 'D'),
+    ('đ', 'd'),
+    ('Ę', 'E'),
+    ('ę', 'e'),
+    ('Ě', 'E'),
+    ('ě', 'e'),
+    ('Ğ', 'G'),
+    ('ğ', 'g'),
+    ('İ', 'I'),
+    ('ı', 'i'),
+    ('Ł', 'L'),
+    ('ł', 'l'),
+    ('Ń', 'N'),
+    ('ń', 'n'),
+    ('Ň', 'N'),
+    ('ň', 'n'),
+    ('Ő', 'O'),
+    ('ő', 'o'),
+    ('Ř', 'R'),
+    ('ř', 'r'),
+    ('Ś', 'S'),
+    ('ś', 's'),
+    ('Ş', 'S'),
+    ('ş', 's'),
+    ('Ț', 'T'),
+    ('ț', 't'),
+    ('Ů', 'U'),
+    ('ů', 'u'),
+    ('Ű', 'U'),
+    ('ű', 'u'),
+    ('Ź', 'Z'),
+    ('ź', 'z'),
+    ('Ż', 'Z'),
+    ('ż', 'z'),
+];
+
+/// `c` if Windows-1252 has it, else its transliteration or `?` like
+/// Windows uses for characters missing in a code page.
+fn windows_1252_char(c: char) -> char {
+    let mut buf = [0; 4];
+    let (_, _, unmappable) =
+        encoding_rs::WINDOWS_1252.encode(c.encode_utf8(&mut buf));
+    if !unmappable {
+        return c;
+    }
+    TRANSLITERATIONS
+        .iter()
+        .find(|(from, _)| *from == c)
+        .map_or('?', |(_, to)| *to)
+}
+
+/// Quotes a text field. DATEV rejects text longer than `max` characters,
+/// and line breaks would end the booking.
+fn text(value: &str, max: usize) -> String {
+    let value: String = value
+        .chars()
+        .take(max)
+        .map(|c| {
+            if c.is_control() {
+                ' '
+            } else {
+                windows_1252_char(c)
+            }
+        })
+        .collect();
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+fn date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+/// The first line, telling DATEV whose books and which period the file is
+/// for.
+fn header(
+    config: &AccountingConfig,
+    from: NaiveDate,
+    until: NaiveDate,
+    created: NaiveDateTime,
+) -> Vec<String> {
+    let mut fields = vec![
+        text("EXTF", 4),
+        "700".to_string(),
+        "21".to_string(),
+        text("Buchungsstapel", 14),
+        "13".to_string(),
+        created.format("%Y%m%d%H%M%S%3f").to_string(),
+        String::new(),
+        text("", 2),
+        text("expensebills", 25),
+        text("", 25),
+        config.consultant_number.to_string(),
+        config.client_number.to_string(),
+        date(config.fiscal_year_of(from)),
+        config.account_length.to_string(),
+        date(from),
+        date(until),
+        text(&format!("Belege {} bis {}", from, until), 30),
+        text("", 2),
+        "1".to_string(),
+        "0".to_string(),
+        "0".to_string(),
+        text("EUR", 3),
+    ];
+    // reserved and optional fields up to the 31 of the header
+    fields.resize(31, String::new());
+    fields
+}
+
+fn row(config: &AccountingConfig, posting: &Posting) -> Vec<String> {
+    let booking_text = match &posting.recipient {
+        Some(recipient) => format!("{}, {}", recipient, posting.receipt_name),
+        None => posting.receipt_name.clone(),
+    };
+    // amounts of receipts are never negative, every bill is a debit
+    vec![
+        format_cents(posting.amount, ','),
+        text("S", 1),
+        text("EUR", 3),
+        String::new(),
+        String::new(),
+        String::new(),
+        posting.account.clone(),
+        config.bank_account.clone(),
+        text("", 4),
+        posting.date.format("%d%m").to_string(),
+        text(&posting.receipt_id.simple().to_string(), 36),
+        text("", 12),
+        String::new(),
+        text(&booking_text, 60),
+    ]
+}
+
+/// Books every posting from the bank to its expense account. All postings
+/// have to be in the fiscal year `from` is in, as DATEV dates bookings by
+/// day and month only. The file is encoded in Windows-1252 like DATEV
+/// expects, texts are cut down to its characters before.
+pub fn buchungsstapel(
+    config: &AccountingConfig,
+    postings: &[Posting],
+    from: NaiveDate,
+    until: NaiveDate,
+    created: NaiveDateTime,
+) -> Vec<u8> {
+    let mut lines = vec![
+        header(config, from, until, created).join(";"),
+        COLUMNS.map(|column| text(column, 40)).join(";"),
+    ];
+    lines.extend(postings.iter().map(|posting| row(config, posting).join(";")));
+    let mut file = lines.join("\r\n");
+    file.push_str("\r\n");
+    encoding_rs::WINDOWS_1252.encode(&file).0.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn books_from_bank_to_expense_account() {
+        let config = AccountingConfig {
+            consultant_number: 1001,
+            client_number: 42,
+            ..Default::default()
+        };
+        let posting = Posting {
+            receipt_id: uuid::Uuid::parse_str(
+                "8a9c2f31-0000-4000-8000-000000000000",
+            )
+            .unwrap(),
+            receipt_name: "strom-august.pdf".to_string(),
+            date: NaiveDate::from_ymd(2022, 8, 5),
+            amount: 12_050,
+            recipient: Some("Stadtwerke \"Süd\"".to_string()),
+            account: "4240".to_string(),
+            category_path: vec!["Energy".to_string()],
+        };
+        let abroad = Posting {
+            recipient: Some("Łódź Energia ✓".to_string()),
+            receipt_name: "strom\naugust.pdf".to_string(),
+            ..posting.clone()
+        };
+
+        let file = buchungsstapel(
+            &config,
+            &[posting, abroad],
+            NaiveDate::from_ymd(2022, 1, 1),
+            NaiveDate::from_ymd(2022, 12, 31),
+            NaiveDate::from_ymd(2022, 9, 1).and_hms(12, 0, 0),
+        );
+        let (file, _, errors) = encoding_rs::WINDOWS_1252.decode(&file);
+        assert!(!errors);
+        let lines: Vec<&str> = file.split("\r\n").collect();
+
+        assert!(lines[0].starts_with(
+            "\"EXTF\";700;21;\"Buchungsstapel\";13;20220901120000000;;\"\";\
+             \"expensebills\";\"\";1001;42;20220101;4;20220101;20221231;"
+        ));
+        assert_eq!(lines[0].split(';').count(), 31);
+        assert!(lines[1].starts_with("\"Umsatz (ohne Soll/Haben-Kz)\";"));
+        assert_eq!(
+            lines[2],
+            "120,50;\"S\";\"EUR\";;;;4240;1200;\"\";0508;\
+             \"8a9c2f31000040008000000000000000\";\"\";;\
+             \"Stadtwerke \"\"Süd\"\", strom-august.pdf\""
+        );
+        assert!(lines[3].ends_with(";\"Lódz Energia ?, strom august.pdf\""));
+        assert_eq!(lines[4], "");
+    }
+}
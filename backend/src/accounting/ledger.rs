@@ -0,0 +1,98 @@
+//! Plain-text journal readable by ledger and hledger.
+
+use super::{format_cents, AccountingConfig, Posting};
+
+/// Account names must not contain two spaces in a row or tabs, which
+/// separate the account from the amount.
+fn account_part(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").replace(':', "-")
+}
+
+/// Texts from uploads may contain line breaks, which would start new
+/// postings.
+fn one_line(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_control() {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn expense_account(config: &AccountingConfig, posting: &Posting) -> String {
+    let mut account = config.ledger_expense_account.clone();
+    for name in &posting.category_path {
+        account.push(':');
+        account.push_str(&account_part(name));
+    }
+    account
+}
+
+/// One transaction per posting, moving the amount from the bank to the
+/// expense account named after the category path.
+pub fn journal(config: &AccountingConfig, postings: &[Posting]) -> String {
+    let mut journal = String::new();
+    for posting in postings {
+        let payee = posting.recipient.as_deref().unwrap_or("Unknown");
+        journal.push_str(&format!(
+            "{} {} | {}\n    ; receipt: {}\n    {}  EUR {}\n    {}\n\n",
+            posting.date.format("%Y-%m-%d"),
+            one_line(payee),
+            one_line(&posting.receipt_name),
+            posting.receipt_id,
+            expense_account(config, posting),
+            format_cents(posting.amount, '.'),
+            config.ledger_bank_account,
+        ));
+    }
+    journal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn transactions_move_money_from_bank_to_category() {
+        let posting = Posting {
+            receipt_id: uuid::Uuid::nil(),
+            receipt_name: "strom-august.pdf".to_string(),
+            date: NaiveDate::from_ymd(2022, 8, 5),
+            amount: 12_050,
+            recipient: Some("Stadtwerke".to_string()),
+            account: "4240".to_string(),
+            category_path: vec!["Home".to_string(), "Power:  Gas".to_string()],
+        };
+        assert_eq!(
+            journal(&AccountingConfig::default(), &[posting]),
+            "2022-08-05 Stadtwerke | strom-august.pdf\n    \
+             ; receipt: 00000000-0000-0000-0000-000000000000\n    \
+             Expenses:Home:Power- Gas  EUR 120.50\n    \
+             Assets:Bank\n\n"
+        );
+    }
+
+    #[test]
+    fn line_breaks_cannot_add_postings() {
+        let posting = Posting {
+            receipt_id: uuid::Uuid::nil(),
+            receipt_name: "x\n    Assets:Cash  EUR 1000\r\n".to_string(),
+            date: NaiveDate::from_ymd(2022, 8, 5),
+            amount: 100,
+            recipient: Some("Evil\nCorp".to_string()),
+            account: "4240".to_string(),
+            category_path: Vec::new(),
+        };
+        let journal = journal(&AccountingConfig::default(), &[posting]);
+        assert!(journal.starts_with(
+            "2022-08-05 Evil Corp | x     Assets:Cash  EUR 1000\n    ;"
+        ));
+        assert_eq!(journal.lines().count(), 5);
+    }
+}
@@ -0,0 +1,232 @@
+use chrono::{Datelike, NaiveDate};
+use entity::category::{self, Model as Category};
+use entity::receipt::{self, ReceiptState};
+use entity::recipient;
+use rocket::serde::Deserialize;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use std::collections::HashMap;
+
+pub mod datev;
+pub mod ledger;
+
+/// Accounts payed receipts are booked on, read from the `accounting` table
+/// of the Rocket figment. The defaults follow the German SKR03.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountingConfig {
+    /// Beraternummer of the DATEV header.
+    #[serde(default)]
+    pub consultant_number: u32,
+    /// Mandantennummer of the DATEV header.
+    #[serde(default)]
+    pub client_number: u32,
+    /// Month the fiscal year starts in.
+    #[serde(default = "default_fiscal_year_start")]
+    pub fiscal_year_start: u32,
+    /// Digits of the general ledger accounts.
+    #[serde(default = "default_account_length")]
+    pub account_length: u8,
+    /// Bank account receipts are payed from.
+    #[serde(default = "default_bank_account")]
+    pub bank_account: String,
+    /// Expense account of receipts without a mapped category.
+    #[serde(default = "default_expense_account")]
+    pub expense_account: String,
+    /// Expense account per category name. Categories without one use the
+    /// account of their closest mapped ancestor.
+    #[serde(default)]
+    pub accounts: HashMap<String, String>,
+    /// Ledger journal account receipts are payed from.
+    #[serde(default = "default_ledger_bank_account")]
+    pub ledger_bank_account: String,
+    /// Ledger journal account the category path is appended to.
+    #[serde(default = "default_ledger_expense_account")]
+    pub ledger_expense_account: String,
+}
+
+fn default_fiscal_year_start() -> u32 {
+    1
+}
+
+fn default_account_length() -> u8 {
+    4
+}
+
+fn default_bank_account() -> String {
+    "1200".to_string()
+}
+
+fn default_expense_account() -> String {
+    "4900".to_string()
+}
+
+fn default_ledger_bank_account() -> String {
+    "Assets:Bank".to_string()
+}
+
+fn default_ledger_expense_account() -> String {
+    "Expenses".to_string()
+}
+
+impl Default for AccountingConfig {
+    fn default() -> Self {
+        AccountingConfig {
+            consultant_number: 0,
+            client_number: 0,
+            fiscal_year_start: default_fiscal_year_start(),
+            account_length: default_account_length(),
+            bank_account: default_bank_account(),
+            expense_account: default_expense_account(),
+            accounts: HashMap::new(),
+            ledger_bank_account: default_ledger_bank_account(),
+            ledger_expense_account: default_ledger_expense_account(),
+        }
+    }
+}
+
+impl AccountingConfig {
+    /// First day of the fiscal year `date` is in.
+    pub fn fiscal_year_of(&self, date: NaiveDate) -> NaiveDate {
+        let start = self.fiscal_year_start.clamp(1, 12);
+        let year = if date.month() >= start {
+            date.year()
+        } else {
+            date.year() - 1
+        };
+        NaiveDate::from_ymd(year, start, 1)
+    }
+}
+
+/// A payed receipt booked from the bank to an expense account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub receipt_id: uuid::Uuid,
+    pub receipt_name: String,
+    /// Day the receipt was payed.
+    pub date: NaiveDate,
+    /// Amount in cents, never negative.
+    pub amount: i64,
+    pub recipient: Option<String>,
+    /// DATEV expense account.
+    pub account: String,
+    /// Names of the category and its ancestors, outermost first.
+    pub category_path: Vec<String>,
+}
+
+/// Formats `cents` with two decimals after `separator`.
+pub fn format_cents(cents: i64, separator: char) -> String {
+    let sign = if cents < 0 {
+        "-"
+    } else {
+        ""
+    };
+    let cents = cents.unsigned_abs();
+    format!("{}{}{}{:02}", sign, cents / 100, separator, cents % 100)
+}
+
+/// Names from `id` up to the top-level category, outermost first.
+fn category_path(
+    categories: &HashMap<uuid::Uuid, Category>,
+    id: Option<uuid::Uuid>,
+) -> Vec<&Category> {
+    let mut path = Vec::new();
+    let mut current = id;
+    // a path is never longer than there are categories, even with cycles
+    for _ in 0..categories.len() {
+        match current.and_then(|id| categories.get(&id)) {
+            Some(category) => {
+                path.push(category);
+                current = category.parent_id;
+            },
+            None => break,
+        }
+    }
+    path.reverse();
+    path
+}
+
+/// Collects the receipts payed between `from` and `until` that have an
+/// amount, in the order they were payed.
+pub async fn postings(
+    db: &DatabaseConnection,
+    config: &AccountingConfig,
+    from: NaiveDate,
+    until: NaiveDate,
+) -> Result<Vec<Posting>, DbErr> {
+    let categories: HashMap<_, _> = category::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|category| (category.id, category))
+        .collect();
+    let receipts = receipt::Entity::find()
+        .filter(
+            receipt::Column::State
+                .is_in([ReceiptState::Payed, ReceiptState::Done]),
+        )
+        .filter(receipt::Column::PaymentDate.between(from, until))
+        .filter(receipt::Column::Amount.is_not_null())
+        .order_by_asc(receipt::Column::PaymentDate)
+        .order_by_asc(receipt::Column::Name)
+        .find_also_related(recipient::Entity)
+        .all(db)
+        .await?;
+
+    Ok(receipts
+        .into_iter()
+        .filter_map(|(receipt, recipient)| {
+            let path = category_path(&categories, receipt.category_id);
+            let account = path
+                .iter()
+                .rev()
+                .find_map(|category| config.accounts.get(&category.name))
+                .unwrap_or(&config.expense_account)
+                .clone();
+            Some(Posting {
+                receipt_id: receipt.id,
+                date: receipt.payment_date?,
+                amount: receipt.amount?,
+                recipient: recipient
+                    .map(|recipient| recipient.name)
+                    .filter(|name| !name.trim().is_empty()),
+                account,
+                category_path: path
+                    .into_iter()
+                    .map(|category| category.name.clone())
+                    .collect(),
+                receipt_name: receipt.name,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cents_are_formatted_with_two_decimals() {
+        assert_eq!(format_cents(12345, ','), "123,45");
+        assert_eq!(format_cents(5, '.'), "0.05");
+        assert_eq!(format_cents(-1_000, '.'), "-10.00");
+    }
+
+    #[test]
+    fn fiscal_years_start_in_configured_month() {
+        let config = AccountingConfig {
+            fiscal_year_start: 7,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.fiscal_year_of(NaiveDate::from_ymd(2022, 3, 1)),
+            NaiveDate::from_ymd(2021, 7, 1)
+        );
+        assert_eq!(
+            config.fiscal_year_of(NaiveDate::from_ymd(2022, 7, 1)),
+            NaiveDate::from_ymd(2022, 7, 1)
+        );
+    }
+}
@@ -0,0 +1,491 @@
+//! Backups of a whole installation, the SQL database together with the
+//! receipt files in sled, as one ZIP archive:
+//!
+//! - `manifest.json` with the format version and the migrations the rows
+//!   follow
+//! - `db/<table>.json` with all rows of a table
+//! - `files/<hash>` with every file as it is stored, so encrypted files
+//!   stay encrypted and need the same keys on restore
+
+use crate::files::FileStore;
+use crate::migrations::Migrator;
+use crate::v1::receipts::sled_to_anyhow;
+use anyhow::{bail, Context};
+use chrono::{Local, NaiveDateTime};
+use entity::{
+    allocation, category, comment, expected_bill, receipt, receipt_tag,
+    recipient, recurring_bill, rule, tag,
+};
+use rocket::serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseBackend,
+    DatabaseConnection, DatabaseTransaction, EntityName, EntityTrait,
+    IdenStatic, IntoActiveModel, Iterable, PaginatorTrait, QueryFilter,
+    Statement, TransactionTrait,
+};
+use sea_orm_migration::{MigrationName, MigratorTrait};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Read, Seek, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Version of the archive layout, raised whenever it changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Rows inserted per statement on restore, which keeps the statements
+/// below the bind parameter limit of SQLite.
+const INSERT_CHUNK: usize = 100;
+
+/// Receipt texts are left out of the serialized receipts, so they get a
+/// file of their own.
+const RECEIPT_TEXTS: &str = "receipt_texts";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Manifest {
+    pub format: u32,
+    pub created_at: NaiveDateTime,
+    /// Migrations applied to the database the rows were read from. Restoring
+    /// loads the rows at exactly these and runs later ones afterwards.
+    pub migrations: Vec<String>,
+    /// Number of rows per table.
+    pub tables: BTreeMap<String, usize>,
+    pub files: usize,
+}
+
+fn table_name<E: EntityTrait>() -> String {
+    E::default().table_name().to_string()
+}
+
+fn table_path(table: &str) -> String {
+    format!("db/{}.json", table)
+}
+
+async fn migration_names(
+    db: &DatabaseConnection,
+) -> anyhow::Result<Vec<String>> {
+    Ok(Migrator::get_applied_migrations(db)
+        .await?
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect())
+}
+
+/// Reads all rows of `E` and writes them to the archive.
+async fn dump<E: EntityTrait, W: Write + Seek>(
+    txn: &DatabaseTransaction,
+    zip: &mut ZipWriter<W>,
+    manifest: &mut Manifest,
+) -> anyhow::Result<Vec<E::Model>>
+where
+    E::Model: Serialize,
+{
+    let rows = E::find().all(txn).await?;
+    let table = table_name::<E>();
+    zip.start_file(table_path(&table), FileOptions::default())?;
+    serde_json::to_writer(&mut *zip, &rows)?;
+    manifest.tables.insert(table, rows.len());
+    Ok(rows)
+}
+
+/// Writes a snapshot of the database and every file in `files_db` to
+/// `writer`.
+///
+/// sled lets only one process open the files db, so the server has to be
+/// stopped for a backup, see [`crate::open_files_db`]. The rows are read in
+/// one transaction and files are only ever added to the files db, so
+/// reading them afterwards still finds every file the rows refer to.
+pub async fn backup<W: Write + Seek>(
+    db: &DatabaseConnection,
+    files_db: &FileStore,
+    writer: W,
+) -> anyhow::Result<Manifest> {
+    if !Migrator::get_pending_migrations(db).await?.is_empty() {
+        bail!("the database has pending migrations, apply them first");
+    }
+    let mut manifest = Manifest {
+        format: FORMAT_VERSION,
+        created_at: Local::now().naive_local(),
+        migrations: migration_names(db).await?,
+        tables: BTreeMap::new(),
+        files: 0,
+    };
+    let mut zip = ZipWriter::new(writer);
+
+    let txn = db.begin().await?;
+    if db.get_database_backend() == DatabaseBackend::Postgres {
+        // every query of the transaction sees the same snapshot
+        txn.execute(Statement::from_string(
+            DatabaseBackend::Postgres,
+            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY"
+                .to_owned(),
+        ))
+        .await?;
+    }
+    dump::<category::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    let receipts =
+        dump::<receipt::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<recipient::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<recurring_bill::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<expected_bill::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<rule::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<tag::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<receipt_tag::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<comment::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    dump::<allocation::Entity, _>(&txn, &mut zip, &mut manifest).await?;
+    txn.commit().await?;
+
+    let texts: BTreeMap<_, _> = receipts
+        .iter()
+        .filter_map(|receipt| {
+            Some((receipt.id, receipt.content_text.as_ref()?))
+        })
+        .collect();
+    zip.start_file(table_path(RECEIPT_TEXTS), FileOptions::default())?;
+    serde_json::to_writer(&mut zip, &texts)?;
+
+    for receipt in &receipts {
+        if !files_db.contains(&receipt.file_hash)? {
+            bail!(
+                "file {} of receipt {} is missing",
+                receipt.file_hash,
+                receipt.id
+            );
+        }
+    }
+    // files are compressed already more often than not
+    let stored =
+        FileOptions::default().compression_method(CompressionMethod::Stored);
+    for entry in files_db.raw().iter() {
+        let (hash, content) = entry.map_err(sled_to_anyhow)?;
+        zip.start_file(
+            format!("files/{}", String::from_utf8_lossy(&hash)),
+            stored,
+        )?;
+        zip.write_all(&content)?;
+        manifest.files += 1;
+    }
+
+    zip.start_file("manifest.json", FileOptions::default())?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+    zip.finish()?;
+    Ok(manifest)
+}
+
+fn read_json<T: DeserializeOwned, R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    path: &str,
+) -> anyhow::Result<T> {
+    let file = archive
+        .by_name(path)
+        .with_context(|| format!("{} is missing from the backup", path))?;
+    serde_json::from_reader(file).with_context(|| format!("reading {}", path))
+}
+
+/// Rows of a table in the archive, with the names of the columns they have
+/// values for. Backups of older versions lack the tables and columns later
+/// migrations added.
+struct Rows<E: EntityTrait> {
+    models: Vec<E::Model>,
+    columns: HashSet<String>,
+}
+
+fn read_rows<E: EntityTrait, R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    manifest: &Manifest,
+) -> anyhow::Result<Rows<E>>
+where
+    E::Model: DeserializeOwned,
+{
+    let table = table_name::<E>();
+    if !manifest.tables.contains_key(&table) {
+        return Ok(Rows {
+            models: Vec::new(),
+            columns: HashSet::new(),
+        });
+    }
+    let path = table_path(&table);
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> =
+        read_json(archive, &path)?;
+    // every row was written from the same model
+    let columns = rows
+        .first()
+        .map(|row| row.keys().cloned().collect())
+        .unwrap_or_default();
+    let models: Vec<E::Model> = rows
+        .into_iter()
+        .map(|row| serde_json::from_value(row.into()))
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("reading {}", path))?;
+    Ok(Rows {
+        models,
+        columns,
+    })
+}
+
+/// Inserts `models`, leaving out the columns that are not in `columns`, as
+/// the database does not have the ones missing from the backup yet either.
+async fn insert_rows<A: ActiveModelTrait + Send>(
+    txn: &DatabaseTransaction,
+    models: Vec<<A::Entity as EntityTrait>::Model>,
+    columns: &HashSet<String>,
+) -> Result<(), sea_orm::DbErr>
+where
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+{
+    let mut rows = models
+        .into_iter()
+        .map(|model| {
+            let mut row = model.into_active_model();
+            for column in <A::Entity as EntityTrait>::Column::iter() {
+                if !columns.contains(column.as_str()) {
+                    row.not_set(column);
+                }
+            }
+            row
+        })
+        .peekable();
+    while rows.peek().is_some() {
+        let chunk: Vec<A> = rows.by_ref().take(INSERT_CHUNK).collect();
+        A::Entity::insert_many(chunk).exec(txn).await?;
+    }
+    Ok(())
+}
+
+/// Inserts all rows of the entity of `A` from the archive.
+async fn load<A, R>(
+    txn: &DatabaseTransaction,
+    archive: &mut ZipArchive<R>,
+    manifest: &Manifest,
+) -> anyhow::Result<()>
+where
+    A: ActiveModelTrait + Send,
+    <A::Entity as EntityTrait>::Model: DeserializeOwned + IntoActiveModel<A>,
+    R: Read + Seek,
+{
+    let rows = read_rows::<A::Entity, R>(archive, manifest)?;
+    insert_rows::<A>(txn, rows.models, &rows.columns).await?;
+    Ok(())
+}
+
+/// Orders categories so that every parent comes before its children.
+fn parents_first(categories: Vec<category::Model>) -> Vec<category::Model> {
+    let parents: HashMap<_, _> = categories
+        .iter()
+        .map(|category| (category.id, category.parent_id))
+        .collect();
+    let depth_of = |category: &category::Model| {
+        let mut depth = 0;
+        let mut parent = category.parent_id;
+        // bounded in case the parents form a cycle
+        while let Some(id) = parent.filter(|_| depth < parents.len()) {
+            depth += 1;
+            parent = parents.get(&id).copied().flatten();
+        }
+        depth
+    };
+    let mut categories = categories;
+    categories.sort_by_cached_key(depth_of);
+    categories
+}
+
+/// Fails unless neither the database nor the files db hold anything yet.
+/// The tables are only looked at if the database is `migrated` at all, as
+/// there are none before.
+async fn check_empty(
+    db: &DatabaseConnection,
+    files_db: &FileStore,
+    migrated: bool,
+) -> anyhow::Result<()> {
+    let rows = if migrated {
+        vec![
+            category::Entity::find().count(db).await?,
+            receipt::Entity::find().count(db).await?,
+            recurring_bill::Entity::find().count(db).await?,
+            rule::Entity::find().count(db).await?,
+            tag::Entity::find().count(db).await?,
+        ]
+    } else {
+        Vec::new()
+    };
+    if rows.iter().any(|&count| count > 0) || !files_db.raw().is_empty() {
+        bail!("backups can only be restored into an empty installation");
+    }
+    Ok(())
+}
+
+/// Copies the files of the archive into `files_db`.
+fn restore_files<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    files_db: &FileStore,
+) -> anyhow::Result<()> {
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let hash = match file.name().strip_prefix("files/") {
+            Some(hash) => hash.to_string(),
+            None => continue,
+        };
+        let mut content = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut content)?;
+        files_db
+            .raw()
+            .insert(hash.as_bytes(), content)
+            .map_err(sled_to_anyhow)?;
+    }
+    Ok(())
+}
+
+/// Fails if the file of any receipt is missing or does not match its
+/// `file_hash`.
+fn verify_files(
+    files_db: &FileStore,
+    receipts: &[receipt::Model],
+) -> anyhow::Result<()> {
+    let mut damaged = Vec::new();
+    for receipt in receipts {
+        let hash = match files_db.get(&receipt.file_hash) {
+            Ok(Some(content)) => sha256::digest_bytes(&content),
+            Ok(None) => String::from("missing"),
+            Err(err) => err.to_string(),
+        };
+        if hash != receipt.file_hash {
+            damaged.push(format!(
+                "{} ({}: {})",
+                receipt.id, receipt.file_hash, hash
+            ));
+        }
+    }
+    if !damaged.is_empty() {
+        bail!(
+            "files of {} receipts do not match their hash: {}",
+            damaged.len(),
+            damaged.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Restores a backup written by [`backup`] into an empty installation. The
+/// database is migrated to the migrations of the backup, then the rows are
+/// loaded and the migrations of later versions run on them. Nothing is kept
+/// if any receipt file does not match its hash.
+pub async fn restore<R: Read + Seek>(
+    db: &DatabaseConnection,
+    files_db: &FileStore,
+    reader: R,
+) -> anyhow::Result<Manifest> {
+    let mut archive = ZipArchive::new(reader)?;
+    let manifest: Manifest = read_json(&mut archive, "manifest.json")?;
+    if manifest.format != FORMAT_VERSION {
+        bail!(
+            "backup has format {}, this version reads format {}",
+            manifest.format,
+            FORMAT_VERSION
+        );
+    }
+
+    let known: Vec<String> = Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect();
+    if !known.starts_with(&manifest.migrations) {
+        bail!(
+            "backup was made at migration {}, which this version does not \
+             know",
+            manifest.migrations.last().map_or("none", String::as_str)
+        );
+    }
+    let applied = migration_names(db).await?;
+    if !manifest.migrations.starts_with(&applied) {
+        bail!(
+            "the database is at migration {}, past the backup made at {}, \
+             restore into a new database",
+            applied.last().map_or("none", String::as_str),
+            manifest.migrations.last().map_or("none", String::as_str)
+        );
+    }
+    check_empty(db, files_db, !applied.is_empty()).await?;
+    let steps = manifest.migrations.len() - applied.len();
+    if steps > 0 {
+        Migrator::up(db, Some(steps as u32)).await?;
+    }
+
+    let txn = db.begin().await?;
+    let categories = read_rows::<category::Entity, _>(&mut archive, &manifest)?;
+    insert_rows::<category::ActiveModel>(
+        &txn,
+        parents_first(categories.models),
+        &categories.columns,
+    )
+    .await?;
+    let receipts = read_rows::<receipt::Entity, _>(&mut archive, &manifest)?;
+    insert_rows::<receipt::ActiveModel>(
+        &txn,
+        receipts.models.clone(),
+        &receipts.columns,
+    )
+    .await?;
+    let texts: HashMap<uuid::Uuid, String> =
+        read_json(&mut archive, &table_path(RECEIPT_TEXTS))?;
+    for (id, text) in texts {
+        receipt::Entity::update_many()
+            .col_expr(receipt::Column::ContentText, Expr::value(text))
+            .filter(receipt::Column::Id.eq(id))
+            .exec(&txn)
+            .await?;
+    }
+    load::<recipient::ActiveModel, _>(&txn, &mut archive, &manifest).await?;
+    load::<recurring_bill::ActiveModel, _>(&txn, &mut archive, &manifest)
+        .await?;
+    load::<expected_bill::ActiveModel, _>(&txn, &mut archive, &manifest)
+        .await?;
+    load::<rule::ActiveModel, _>(&txn, &mut archive, &manifest).await?;
+    load::<tag::ActiveModel, _>(&txn, &mut archive, &manifest).await?;
+    load::<receipt_tag::ActiveModel, _>(&txn, &mut archive, &manifest).await?;
+    load::<comment::ActiveModel, _>(&txn, &mut archive, &manifest).await?;
+    load::<allocation::ActiveModel, _>(&txn, &mut archive, &manifest).await?;
+
+    let restored = restore_files(&mut archive, files_db)
+        .and_then(|()| verify_files(files_db, &receipts.models));
+    if let Err(err) = restored {
+        // the installation was empty, so this only drops the restored files
+        files_db.raw().clear().map_err(sled_to_anyhow)?;
+        txn.rollback().await?;
+        return Err(err);
+    }
+    txn.commit().await?;
+    files_db.flush()?;
+    Migrator::up(db, None).await?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(id: u128, parent: Option<u128>) -> category::Model {
+        category::Model {
+            id: uuid::Uuid::from_u128(id),
+            name: id.to_string(),
+            parent_id: parent.map(uuid::Uuid::from_u128),
+            monthly_budget: None,
+        }
+    }
+
+    #[test]
+    fn parents_are_restored_before_their_children() {
+        let categories = vec![
+            category(3, Some(2)),
+            category(2, Some(1)),
+            category(1, None),
+            category(4, Some(5)),
+            category(5, Some(4)),
+        ];
+        let ids: Vec<_> = parents_first(categories)
+            .iter()
+            .map(|category| category.id.as_u128())
+            .collect();
+        assert_eq!(&ids[..3], &[1, 2, 3]);
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::{anyhow, Context};
+use backend::backup::Manifest;
+use backend::migrations::Migrator;
+use backend::notifications::Notifier;
+use backend::pool::SeaOrmPool;
+use backend::uploads::{Rejection, UploadConfig, UploadPolicy};
+use backend::v1::receipts::{create_inbox_receipt, find_by_file_hash};
+use clap::{Parser, Subcommand};
+use entity::{receipt, recipient};
+use rocket::serde::json::json;
+use rocket::serde::Serialize;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use sea_orm_migration::MigratorTrait;
+use sea_orm_rocket::Pool;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Maintenance tasks for an expensebills installation. Reads the same
+/// Rocket.toml and ROCKET_* environment as the server. Commands using the
+/// receipt files need the server to be stopped.
+#[derive(Parser, Debug)]
+#[clap(name = "expensebills-admin", version, about)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage database migrations
+    Migrate {
+        #[clap(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Import every file in a directory that passes the upload checks as a
+    /// receipt into the Inbox, except files stored already
+    Import {
+        dir: PathBuf,
+    },
+    /// Export all receipts and recipients as JSON
+    Export {
+        /// File to write to, stdout if not given
+        #[clap(long, short = 'f')]
+        file: Option<PathBuf>,
+    },
+    /// Write the database and all receipt files to one backup archive, with
+    /// the server stopped
+    Backup {
+        file: PathBuf,
+    },
+    /// Restore a backup archive into an empty installation
+    Restore {
+        file: PathBuf,
+    },
+    /// Manage the encryption of stored receipt files
+    Files {
+        #[clap(subcommand)]
+        command: FilesCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum FilesCommand {
+    /// Show how many files are encrypted with which key
+    Status,
+    /// Encrypt all files with the primary key, rewrapping those of other
+    /// keys, or decrypt them if no primary key is configured. The server
+    /// has to be stopped
+    Encrypt,
+    /// Print a new random key for the encryption config
+    NewKey,
+}
+
+#[derive(Subcommand, Debug)]
+enum MigrateCommand {
+    /// Apply pending migrations
+    Up {
+        /// Number of migrations to apply, all if not given
+        #[clap(long, short = 'n')]
+        steps: Option<u32>,
+    },
+    /// Roll back applied migrations
+    Down {
+        /// Number of migrations to roll back
+        #[clap(long, short = 'n', default_value = "1")]
+        steps: u32,
+    },
+    /// Show applied and pending migrations
+    Status,
+    /// Drop all tables and apply all migrations again
+    Fresh,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Export {
+    receipts: Vec<receipt::Model>,
+    recipients: Vec<recipient::Model>,
+}
+
+async fn migrate(
+    db: &DatabaseConnection,
+    command: MigrateCommand,
+) -> anyhow::Result<()> {
+    match command {
+        MigrateCommand::Up {
+            steps,
+        } => Migrator::up(db, steps).await?,
+        MigrateCommand::Down {
+            steps,
+        } => Migrator::down(db, Some(steps)).await?,
+        MigrateCommand::Status => {
+            for migration in Migrator::get_applied_migrations(db).await? {
+                println!("applied  {}", migration.name());
+            }
+            for migration in Migrator::get_pending_migrations(db).await? {
+                println!("pending  {}", migration.name());
+            }
+        },
+        MigrateCommand::Fresh => Migrator::fresh(db).await?,
+    }
+    Ok(())
+}
+
+async fn import(db: &DatabaseConnection, dir: &Path) -> anyhow::Result<()> {
+    let figment = rocket::Config::figment();
+    let files_db = backend::open_files_db(&figment)?;
+    // nobody listens to events of the server here
+    let notifier = Notifier::new();
+    // the same checks the server runs on uploads
+    let config = figment
+        .extract_inner::<UploadConfig>("uploads")
+        .or_else(|err| {
+            if err.missing() {
+                Ok(Default::default())
+            } else {
+                Err(err)
+            }
+        })
+        .context("uploads config")?;
+    let policy = UploadPolicy::new(config);
+
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?
+    {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let content = std::fs::read(&path)?;
+        match policy.check(&content).await {
+            Ok(_) => {},
+            Err(Rejection::ScanFailed(err)) => {
+                return Err(err.context(format!("scanning {}", path.display())))
+            },
+            Err(rejection) => {
+                eprintln!("skipping {}: {}", name, rejection);
+                continue;
+            },
+        }
+        // imported by an earlier run
+        let hash = sha256::digest_bytes(&content);
+        if let Some(receipt) = find_by_file_hash(db, &hash).await? {
+            println!("{}  {} (stored already)", receipt.id, name);
+            continue;
+        }
+        let metadata = json!({
+            "source": "import",
+            "path": path.to_string_lossy(),
+        });
+        let receipt = create_inbox_receipt(
+            db,
+            &files_db,
+            &notifier,
+            &name,
+            content,
+            Some(metadata),
+        )
+        .await
+        .map_err(|err| anyhow!("importing {}: {}", path.display(), err))?;
+        println!("{}  {}", receipt.id, name);
+    }
+    files_db.flush()?;
+    Ok(())
+}
+
+async fn export(
+    db: &DatabaseConnection,
+    file: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let export = Export {
+        receipts: receipt::Entity::find().all(db).await?,
+        recipients: recipient::Entity::find().all(db).await?,
+    };
+    let json = serde_json::to_string_pretty(&export)?;
+    match file {
+        Some(path) => std::fs::write(&path, json)
+            .with_context(|| format!("writing {}", path.display()))?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+fn files(command: FilesCommand) -> anyhow::Result<()> {
+    if let FilesCommand::NewKey = command {
+        println!("{}", backend::files::generate_key());
+        return Ok(());
+    }
+    let files_db = backend::open_files_db(&rocket::Config::figment())?;
+    if let FilesCommand::Encrypt = command {
+        let changed = files_db.reseal_all()?;
+        println!("{} files changed", changed);
+    }
+    for (key, files) in files_db.key_usage()? {
+        let key = key.unwrap_or_else(|| "(unencrypted)".to_string());
+        println!("{:>8}  {}", files, key);
+    }
+    Ok(())
+}
+
+fn print_manifest(manifest: &Manifest) {
+    for (table, rows) in &manifest.tables {
+        println!("{:>8}  {}", rows, table);
+    }
+    println!("{:>8}  files", manifest.files);
+}
+
+async fn backup(db: &DatabaseConnection, file: &Path) -> anyhow::Result<()> {
+    let files_db = backend::open_files_db(&rocket::Config::figment())?;
+    let archive = File::create(file)
+        .with_context(|| format!("creating {}", file.display()))?;
+    let manifest = backend::backup::backup(db, &files_db, archive).await?;
+    print_manifest(&manifest);
+    Ok(())
+}
+
+async fn restore(db: &DatabaseConnection, file: &Path) -> anyhow::Result<()> {
+    let files_db = backend::open_files_db(&rocket::Config::figment())?;
+    let archive = File::open(file)
+        .with_context(|| format!("opening {}", file.display()))?;
+    let manifest = backend::backup::restore(db, &files_db, archive).await?;
+    print_manifest(&manifest);
+    println!("backup of {} restored, all files verified", manifest.created_at);
+    Ok(())
+}
+
+#[rocket::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let figment = rocket::Config::figment();
+    let pool = SeaOrmPool::init(&figment.focus("databases.sea_orm")).await?;
+    let db = &pool.conn;
+
+    match cli.command {
+        Command::Migrate {
+            command,
+        } => migrate(db, command).await,
+        Command::Import {
+            dir,
+        } => import(db, &dir).await,
+        Command::Export {
+            file,
+        } => export(db, file).await,
+        Command::Backup {
+            file,
+        } => backup(db, &file).await,
+        Command::Restore {
+            file,
+        } => restore(db, &file).await,
+        Command::Files {
+            command,
+        } => files(command),
+    }
+}
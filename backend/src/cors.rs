@@ -1,9 +1,90 @@
-use rocket::http::Header;
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::{Request, Response};
+use rocket::http::{Header, Method, Status};
+use rocket::serde::Deserialize;
+use rocket::{Request, Response, Route};
 
+/// CORS policy, read from the `cors` table of the Rocket figment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CorsConfig {
+    /// Origins allowed to call the API. `*` allows any origin but then
+    /// credentials are never allowed.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts of other origins may read.
+    #[serde(default = "default_exposed_headers")]
+    pub exposed_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// Seconds a browser may cache a preflight answer.
+    #[serde(default = "default_max_age")]
+    pub max_age: u64,
+}
 
-pub struct Cors;
+fn default_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+        .iter()
+        .map(|m| m.to_string())
+        .collect()
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    ["Accept", "Content-Type", "If-Match"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+}
+
+fn default_exposed_headers() -> Vec<String> {
+    vec!["ETag".to_string()]
+}
+
+fn default_max_age() -> u64 {
+    3600
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_allowed_methods(),
+            allowed_headers: default_allowed_headers(),
+            exposed_headers: default_exposed_headers(),
+            allow_credentials: false,
+            max_age: default_max_age(),
+        }
+    }
+}
+
+impl CorsConfig {
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|o| o == "*")
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        self.allows_any_origin()
+            || self
+                .allowed_origins
+                .iter()
+                .any(|o| o.trim_end_matches('/') == origin)
+    }
+}
+
+pub struct Cors {
+    config: CorsConfig,
+}
+
+impl Cors {
+    pub fn new(config: CorsConfig) -> Self {
+        Cors {
+            config,
+        }
+    }
+}
 
 #[rocket::async_trait]
 impl Fairing for Cors {
@@ -16,18 +97,192 @@ impl Fairing for Cors {
 
     async fn on_response<'r>(
         &self,
-        _request: &'r Request<'_>,
+        request: &'r Request<'_>,
         response: &mut Response<'r>,
     ) {
-        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
-        response.set_header(Header::new(
-            "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, OPTIONS",
-        ));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
-        response.set_header(Header::new(
-            "Access-Control-Allow-Credentials",
-            "true",
-        ));
-    }
-}
\ No newline at end of file
+        let preflight = request.method() == Method::Options;
+        let origin = match request.headers().get_one("Origin") {
+            Some(origin) => origin,
+            None => return,
+        };
+
+        response.adjoin_header(Header::new("Vary", "Origin"));
+
+        if !self.config.allows_origin(origin) {
+            if preflight {
+                response.set_status(Status::Forbidden);
+            }
+            return;
+        }
+
+        if self.config.allows_any_origin() {
+            response
+                .set_header(Header::new("Access-Control-Allow-Origin", "*"));
+        } else {
+            response.set_header(Header::new(
+                "Access-Control-Allow-Origin",
+                origin.to_string(),
+            ));
+            if self.config.allow_credentials {
+                response.set_header(Header::new(
+                    "Access-Control-Allow-Credentials",
+                    "true",
+                ));
+            }
+        }
+
+        if !preflight && !self.config.exposed_headers.is_empty() {
+            response.set_header(Header::new(
+                "Access-Control-Expose-Headers",
+                self.config.exposed_headers.join(", "),
+            ));
+        }
+
+        if preflight {
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                self.config.allowed_methods.join(", "),
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                self.config.allowed_headers.join(", "),
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Max-Age",
+                self.config.max_age.to_string(),
+            ));
+        }
+    }
+}
+
+/// Answers every preflight request. The CORS headers themselves are added by
+/// the [`Cors`] fairing, which also rejects origins that are not allowed.
+#[options("/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![preflight]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[get("/ping")]
+    fn ping() -> &'static str {
+        "pong"
+    }
+
+    fn client(config: CorsConfig) -> Client {
+        let rocket = rocket::build()
+            .attach(Cors::new(config))
+            .mount("/", routes())
+            .mount("/", routes![ping]);
+        Client::tracked(rocket).expect("valid rocket instance")
+    }
+
+    fn config(origins: &[&str], allow_credentials: bool) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+            allow_credentials,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn allowed_origin_is_echoed() {
+        let client = client(config(&["http://localhost:8080"], true));
+        let response = client
+            .get("/ping")
+            .header(Header::new("Origin", "http://localhost:8080"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Origin"),
+            Some("http://localhost:8080")
+        );
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Credentials"),
+            Some("true")
+        );
+        assert_eq!(headers.get_one("Vary"), Some("Origin"));
+        assert_eq!(
+            headers.get_one("Access-Control-Expose-Headers"),
+            Some("ETag")
+        );
+    }
+
+    #[test]
+    fn rejected_origin_gets_no_cors_headers() {
+        let client = client(config(&["http://localhost:8080"], true));
+        let response = client
+            .get("/ping")
+            .header(Header::new("Origin", "http://evil.example"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        let headers = response.headers();
+        assert!(headers.get_one("Access-Control-Allow-Origin").is_none());
+        assert!(headers.get_one("Access-Control-Allow-Credentials").is_none());
+    }
+
+    #[test]
+    fn preflight_for_allowed_origin() {
+        let client = client(config(&["http://localhost:8080"], false));
+        let response = client
+            .options("/api/v1/receipts/upload")
+            .header(Header::new("Origin", "http://localhost:8080"))
+            .header(Header::new("Access-Control-Request-Method", "POST"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::NoContent);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Origin"),
+            Some("http://localhost:8080")
+        );
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Methods"),
+            Some("GET, POST, PUT, PATCH, DELETE, OPTIONS")
+        );
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Headers"),
+            Some("Accept, Content-Type, If-Match")
+        );
+        assert!(headers.get_one("Access-Control-Allow-Credentials").is_none());
+    }
+
+    #[test]
+    fn preflight_for_rejected_origin() {
+        let client = client(config(&["http://localhost:8080"], false));
+        let response = client
+            .options("/api/v1/receipts/upload")
+            .header(Header::new("Origin", "http://evil.example"))
+            .header(Header::new("Access-Control-Request-Method", "POST"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(response
+            .headers()
+            .get_one("Access-Control-Allow-Origin")
+            .is_none());
+    }
+
+    #[test]
+    fn wildcard_never_allows_credentials() {
+        let client = client(config(&["*"], true));
+        let response = client
+            .get("/ping")
+            .header(Header::new("Origin", "http://anywhere.example"))
+            .dispatch();
+
+        let headers = response.headers();
+        assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some("*"));
+        assert!(headers.get_one("Access-Control-Allow-Credentials").is_none());
+    }
+}
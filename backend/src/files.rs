@@ -0,0 +1,481 @@
+//! Receipt files in sled, keyed by the sha256 hash of their content.
+//!
+//! Files are encrypted at rest once a primary key is configured. Every file
+//! is sealed with a random key of its own, and that file key is stored next
+//! to it, wrapped with the primary key. Rotating to a new primary key only
+//! rewraps these file keys. Files stored before encryption was turned on
+//! stay readable and are encrypted by `expensebills-admin files encrypt`.
+
+use crate::v1::receipts::sled_to_anyhow;
+use anyhow::{anyhow, bail, Context};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rocket::serde::Deserialize;
+use sled_extensions::Db;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Start of an encrypted file, followed by the length and id of the key,
+/// the wrapped file key and the sealed content.
+const MAGIC: &[u8; 4] = b"ebx1";
+const NONCE_LEN: usize = 24;
+/// Nonce, the 32 bytes of the file key and the tag.
+const WRAPPED_KEY_LEN: usize = NONCE_LEN + 32 + 16;
+/// Tree caching the previews of files by the hash of the file.
+const PREVIEWS: &str = "previews";
+
+/// Keys to encrypt the files with, read from the `encryption` table of the
+/// Rocket figment. Keys are 32 bytes written as 64 hex digits.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct EncryptionConfig {
+    /// Id of the key new files are encrypted with. Files are stored
+    /// unencrypted without one.
+    #[serde(default)]
+    pub primary: Option<String>,
+    /// Keys by id.
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+    /// Files holding a key by id, to keep keys out of Rocket.toml.
+    #[serde(default)]
+    pub key_files: HashMap<String, PathBuf>,
+}
+
+/// The configured keys. Keys other than the primary one are only used to
+/// read files encrypted before a rotation.
+#[derive(Clone)]
+pub struct Keyring {
+    primary: Option<String>,
+    keys: HashMap<String, Key>,
+}
+
+fn parse_key(hex_key: &str) -> anyhow::Result<Key> {
+    let bytes = hex::decode(hex_key.trim())?;
+    if bytes.len() != 32 {
+        bail!("keys have 32 bytes, not {}", bytes.len());
+    }
+    Ok(*Key::from_slice(&bytes))
+}
+
+/// A new random key in the format of the config.
+pub fn generate_key() -> String {
+    hex::encode(XChaCha20Poly1305::generate_key(&mut OsRng))
+}
+
+fn seal(key: &Key, plain: &[u8], hash: &str) -> Vec<u8> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    // binding the hash keeps files from being swapped undetected
+    let payload = Payload {
+        msg: plain,
+        aad: hash.as_bytes(),
+    };
+    let sealed = XChaCha20Poly1305::new(key)
+        .encrypt(&nonce, payload)
+        .expect("content fits into one message");
+    [nonce.as_slice(), sealed.as_slice()].concat()
+}
+
+fn open(key: &Key, sealed: &[u8], hash: &str) -> anyhow::Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        bail!("file {} is truncated", hash);
+    }
+    let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: sealed,
+        aad: hash.as_bytes(),
+    };
+    XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow!("file {} cannot be decrypted", hash))
+}
+
+/// A file as it is stored in sled.
+enum Stored<'a> {
+    Plain(&'a [u8]),
+    Sealed {
+        key_id: &'a str,
+        wrapped_key: &'a [u8],
+        content: &'a [u8],
+    },
+}
+
+impl<'a> Stored<'a> {
+    fn parse(stored: &'a [u8]) -> Self {
+        let sealed = || {
+            let (&len, rest) = stored.strip_prefix(MAGIC)?.split_first()?;
+            let len = usize::from(len);
+            if rest.len() < len + WRAPPED_KEY_LEN {
+                return None;
+            }
+            let (key_id, rest) = rest.split_at(len);
+            let (wrapped_key, content) = rest.split_at(WRAPPED_KEY_LEN);
+            Some(Stored::Sealed {
+                key_id: std::str::from_utf8(key_id).ok()?,
+                wrapped_key,
+                content,
+            })
+        };
+        sealed().unwrap_or(Stored::Plain(stored))
+    }
+}
+
+fn envelope(key_id: &str, wrapped_key: &[u8], content: &[u8]) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(
+        MAGIC.len() + 1 + key_id.len() + wrapped_key.len() + content.len(),
+    );
+    stored.extend_from_slice(MAGIC);
+    stored.push(key_id.len() as u8);
+    stored.extend_from_slice(key_id.as_bytes());
+    stored.extend_from_slice(wrapped_key);
+    stored.extend_from_slice(content);
+    stored
+}
+
+impl Keyring {
+    pub fn from_config(config: &EncryptionConfig) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        for (id, key) in &config.keys {
+            let key = parse_key(key).with_context(|| format!("key {}", id))?;
+            keys.insert(id.clone(), key);
+        }
+        for (id, path) in &config.key_files {
+            let key = std::fs::read_to_string(path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            let key = parse_key(&key).with_context(|| format!("key {}", id))?;
+            keys.insert(id.clone(), key);
+        }
+        if let Some(id) = keys.keys().find(|id| id.len() > u8::MAX as usize) {
+            bail!("key id {} is longer than 255 bytes", id);
+        }
+        if let Some(primary) = &config.primary {
+            if !keys.contains_key(primary) {
+                bail!("primary key {} is not configured", primary);
+            }
+        }
+        Ok(Keyring {
+            primary: config.primary.clone(),
+            keys,
+        })
+    }
+
+    fn primary(&self) -> Option<(&str, &Key)> {
+        let id = self.primary.as_deref()?;
+        Some((id, self.keys.get(id)?))
+    }
+
+    fn encrypt(&self, hash: &str, plain: &[u8]) -> Vec<u8> {
+        match self.primary() {
+            Some((key_id, key)) => {
+                let file_key = XChaCha20Poly1305::generate_key(&mut OsRng);
+                envelope(
+                    key_id,
+                    &seal(key, &file_key, hash),
+                    &seal(&file_key, plain, hash),
+                )
+            },
+            None => plain.to_vec(),
+        }
+    }
+
+    fn file_key(
+        &self,
+        hash: &str,
+        key_id: &str,
+        wrapped_key: &[u8],
+    ) -> anyhow::Result<Key> {
+        let key = self.keys.get(key_id).ok_or_else(|| {
+            anyhow!("key {} of file {} is not configured", key_id, hash)
+        })?;
+        // the tag guarantees an unwrapped key has the right length
+        Ok(*Key::from_slice(&open(key, wrapped_key, hash)?))
+    }
+
+    fn decrypt(&self, hash: &str, stored: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (key_id, wrapped_key, content) = match Stored::parse(stored) {
+            Stored::Plain(plain) => return Ok(plain.to_vec()),
+            Stored::Sealed {
+                key_id,
+                wrapped_key,
+                content,
+            } => (key_id, wrapped_key, content),
+        };
+        let opened = self
+            .file_key(hash, key_id, wrapped_key)
+            .and_then(|file_key| open(&file_key, content, hash));
+        match opened {
+            Ok(plain) => Ok(plain),
+            // a plain file that happens to start like an encrypted one
+            Err(_) if sha256::digest_bytes(stored) == hash => {
+                Ok(stored.to_vec())
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    /// `stored` encrypted with the primary key, or decrypted without one.
+    /// `None` if it is already.
+    fn reseal(
+        &self,
+        hash: &str,
+        stored: &[u8],
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        match (Stored::parse(stored), self.primary()) {
+            (Stored::Plain(_), None) => return Ok(None),
+            (
+                Stored::Sealed {
+                    key_id,
+                    wrapped_key,
+                    content,
+                },
+                Some((primary_id, primary_key)),
+            ) => {
+                if key_id == primary_id {
+                    return Ok(None);
+                }
+                // rotation only rewraps the file key
+                if let Ok(file_key) = self.file_key(hash, key_id, wrapped_key) {
+                    let wrapped_key = seal(primary_key, &file_key, hash);
+                    return Ok(Some(envelope(
+                        primary_id,
+                        &wrapped_key,
+                        content,
+                    )));
+                }
+            },
+            _ => {},
+        }
+        Ok(Some(self.encrypt(hash, &self.decrypt(hash, stored)?)))
+    }
+}
+
+/// The files db together with the keys its files are encrypted with.
+#[derive(Clone)]
+pub struct FileStore {
+    db: Db,
+    keyring: Arc<Keyring>,
+}
+
+impl FileStore {
+    pub fn new(db: Db, keyring: Keyring) -> Self {
+        FileStore {
+            db,
+            keyring: Arc::new(keyring),
+        }
+    }
+
+    /// Stores `content` under its `hash`, encrypted if there is a primary
+    /// key.
+    pub fn insert(&self, hash: &str, content: &[u8]) -> anyhow::Result<()> {
+        let stored = self.keyring.encrypt(hash, content);
+        self.db.insert(hash.as_bytes(), stored).map_err(sled_to_anyhow)?;
+        Ok(())
+    }
+
+    /// The decrypted content of the file with `hash`.
+    pub fn get(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.db.get(hash.as_bytes()).map_err(sled_to_anyhow)? {
+            Some(stored) => Ok(Some(self.keyring.decrypt(hash, &stored)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn previews(&self) -> anyhow::Result<sled::Tree> {
+        self.db.open_tree(PREVIEWS).map_err(sled_to_anyhow)
+    }
+
+    /// The cached preview of the file with `hash`.
+    pub fn get_preview(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.previews()?.get(hash.as_bytes()).map_err(sled_to_anyhow)? {
+            Some(stored) => {
+                let aad = format!("{}:{}", PREVIEWS, hash);
+                Ok(Some(self.keyring.decrypt(&aad, &stored)?))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Caches the preview of the file with `hash`. Previews show the
+    /// content, so they are encrypted like the files.
+    pub fn insert_preview(
+        &self,
+        hash: &str,
+        preview: &[u8],
+    ) -> anyhow::Result<()> {
+        let aad = format!("{}:{}", PREVIEWS, hash);
+        let stored = self.keyring.encrypt(&aad, preview);
+        self.previews()?
+            .insert(hash.as_bytes(), stored)
+            .map_err(sled_to_anyhow)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, hash: &str) -> anyhow::Result<bool> {
+        self.db.contains_key(hash.as_bytes()).map_err(sled_to_anyhow)
+    }
+
+    /// The sled db with the files as they are stored, e.g. encrypted.
+    pub fn raw(&self) -> &Db {
+        &self.db
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.db.flush().map_err(sled_to_anyhow)?;
+        Ok(())
+    }
+
+    /// Number of files per key they are encrypted with, `None` counting the
+    /// unencrypted ones.
+    pub fn key_usage(&self) -> anyhow::Result<BTreeMap<Option<String>, usize>> {
+        let mut usage = BTreeMap::new();
+        for entry in self.db.iter() {
+            let (_, stored) = entry.map_err(sled_to_anyhow)?;
+            let key_id = match Stored::parse(&stored) {
+                Stored::Plain(_) => None,
+                Stored::Sealed {
+                    key_id,
+                    ..
+                } => Some(key_id.to_string()),
+            };
+            *usage.entry(key_id).or_default() += 1;
+        }
+        Ok(usage)
+    }
+
+    /// Brings every file to the primary key: encrypts unencrypted files and
+    /// rewraps the ones of other keys, which can be removed afterwards.
+    /// Without a primary key all files are decrypted instead. The cached
+    /// previews are dropped and rendered again on demand. Returns the
+    /// number of changed files.
+    ///
+    /// Runs offline from `expensebills-admin`, which can only open the files
+    /// db while the server is stopped.
+    pub fn reseal_all(&self) -> anyhow::Result<usize> {
+        self.previews()?.clear().map_err(sled_to_anyhow)?;
+        let mut changed = 0;
+        for entry in self.db.iter() {
+            let (hash, stored) = entry.map_err(sled_to_anyhow)?;
+            let hash = String::from_utf8_lossy(&hash);
+            if let Some(resealed) = self.keyring.reseal(&hash, &stored)? {
+                self.db
+                    .insert(hash.as_bytes(), resealed)
+                    .map_err(sled_to_anyhow)?;
+                changed += 1;
+            }
+        }
+        self.flush()?;
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(primary: Option<&str>, ids: &[&str]) -> Keyring {
+        let keys = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.to_string(), hex::encode([i as u8 + 1; 32])))
+            .collect();
+        Keyring::from_config(&EncryptionConfig {
+            primary: primary.map(str::to_string),
+            keys,
+            key_files: HashMap::new(),
+        })
+        .unwrap()
+    }
+
+    fn store(keyring: Keyring) -> FileStore {
+        let db = sled_extensions::Config::default()
+            .temporary(true)
+            .open()
+            .expect("temporary sled db");
+        FileStore::new(db, keyring)
+    }
+
+    #[test]
+    fn files_are_encrypted_with_the_primary_key() {
+        let files = store(keyring(Some("2022"), &["2022"]));
+        let hash = sha256::digest_bytes(b"IBAN DE89370400440532013000");
+        files.insert(&hash, b"IBAN DE89370400440532013000").unwrap();
+
+        let stored = files.raw().get(hash.as_bytes()).unwrap().unwrap();
+        assert!(stored.starts_with(b"ebx1\x042022"));
+        assert!(!stored.windows(4).any(|part| part == b"IBAN"));
+        assert_eq!(
+            files.get(&hash).unwrap().unwrap(),
+            b"IBAN DE89370400440532013000"
+        );
+        assert_eq!(files.get("other").unwrap(), None);
+    }
+
+    #[test]
+    fn swapped_files_are_detected() {
+        let files = store(keyring(Some("2022"), &["2022"]));
+        files.insert("a", b"first").unwrap();
+        let stored = files.raw().get(b"a").unwrap().unwrap();
+        files.raw().insert(b"b", stored).unwrap();
+        assert!(files.get("b").is_err());
+    }
+
+    #[test]
+    fn previews_are_cached_encrypted() {
+        let files = store(keyring(Some("2022"), &["2022"]));
+        assert_eq!(files.get_preview("a").unwrap(), None);
+        files.insert_preview("a", b"jpeg").unwrap();
+        assert_eq!(files.get_preview("a").unwrap().unwrap(), b"jpeg");
+        assert!(files.get("a").unwrap().is_none());
+
+        files.reseal_all().unwrap();
+        assert_eq!(files.get_preview("a").unwrap(), None);
+    }
+
+    #[test]
+    fn rotation_rewraps_files_of_old_keys() {
+        let old = store(keyring(Some("old"), &["old"]));
+        let hash = sha256::digest_bytes(b"salary");
+        old.insert(&hash, b"salary").unwrap();
+        old.raw().insert(b"plain", &b"stored before encryption"[..]).unwrap();
+
+        let files = FileStore::new(
+            old.raw().clone(),
+            keyring(Some("new"), &["old", "new"]),
+        );
+        assert_eq!(files.get(&hash).unwrap().unwrap(), b"salary");
+        assert_eq!(files.reseal_all().unwrap(), 2);
+        assert_eq!(files.reseal_all().unwrap(), 0);
+
+        let usage = files.key_usage().unwrap();
+        assert_eq!(usage.get(&Some("new".to_string())), Some(&2));
+        let files =
+            FileStore::new(files.raw().clone(), keyring(Some("new"), &["new"]));
+        assert_eq!(files.get(&hash).unwrap().unwrap(), b"salary");
+        assert_eq!(
+            files.get("plain").unwrap().unwrap(),
+            b"stored before encryption"
+        );
+
+        let files =
+            FileStore::new(files.raw().clone(), keyring(None, &["new"]));
+        assert_eq!(files.reseal_all().unwrap(), 2);
+        assert_eq!(
+            files.raw().get(&hash).unwrap().unwrap().to_vec(),
+            b"salary"
+        );
+    }
+
+    #[test]
+    fn primary_key_has_to_be_configured() {
+        let config = EncryptionConfig {
+            primary: Some("missing".to_string()),
+            ..Default::default()
+        };
+        assert!(Keyring::from_config(&config).is_err());
+        let config = EncryptionConfig {
+            keys: HashMap::from([("short".to_string(), "abcd".to_string())]),
+            ..Default::default()
+        };
+        assert!(Keyring::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use super::IngestContext;
+use crate::uploads::Rejection;
+use crate::v1::receipts::{create_inbox_receipt, find_by_file_hash};
+use log::{error, info, warn};
+use rocket::fairing::AdHoc;
+use rocket::serde::json::json;
+use rocket::serde::Deserialize;
+use rocket::tokio::{fs, time};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Configuration of the watched folder ingestion, read from the
+/// `folder_ingest` table of the Rocket figment.
+///
+/// Folders are polled instead of relying on file system notifications as
+/// those are not delivered reliably for network shares.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FolderIngestConfig {
+    pub directories: Vec<PathBuf>,
+    /// Subfolder of each watched directory imported files are moved to.
+    #[serde(default = "default_archive_dir")]
+    pub archive_dir: String,
+    /// Subfolder of each watched directory files that failed are moved to.
+    #[serde(default = "default_error_dir")]
+    pub error_dir: String,
+    /// Seconds between two scans of the directories.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    /// Seconds a file must be left untouched before it is imported, so files
+    /// still being written by the scanner are not picked up.
+    #[serde(default = "default_settle_time")]
+    pub settle_time: u64,
+}
+
+fn default_archive_dir() -> String {
+    "archive".to_string()
+}
+
+fn default_error_dir() -> String {
+    "error".to_string()
+}
+
+fn default_poll_interval() -> u64 {
+    10
+}
+
+fn default_settle_time() -> u64 {
+    5
+}
+
+/// Returns a path for `file_name` inside `dir` that does not exist yet.
+fn unique_destination(dir: &Path, file_name: &str) -> PathBuf {
+    let candidate = dir.join(file_name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let mut counter = 1;
+    loop {
+        let candidate = dir.join(format!("{}-{}", counter, file_name));
+        if !candidate.exists() {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+async fn move_into(
+    file: &Path,
+    dir: &Path,
+    file_name: &str,
+) -> std::io::Result<()> {
+    fs::create_dir_all(dir).await?;
+    fs::rename(file, unique_destination(dir, file_name)).await
+}
+
+async fn is_settled(
+    path: &Path,
+    settle_time: Duration,
+) -> std::io::Result<bool> {
+    let modified = fs::metadata(path).await?.modified()?;
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .map(|age| age >= settle_time)
+        .unwrap_or(false))
+}
+
+async fn import_file(
+    ctx: &IngestContext,
+    path: &Path,
+    file_name: &str,
+) -> anyhow::Result<()> {
+    let content = fs::read(path).await?;
+    ctx.uploads.check(&content).await?;
+    // imported before, but moving it away failed
+    let hash = sha256::digest_bytes(&content);
+    if let Some(receipt) = find_by_file_hash(&ctx.sql_db, &hash).await? {
+        info!("{} is stored as receipt {}", path.display(), receipt.id);
+        return Ok(());
+    }
+    let metadata = json!({
+        "source": "folder",
+        "path": path.to_string_lossy(),
+    });
+    let receipt = create_inbox_receipt(
+        &ctx.sql_db,
+        &ctx.files_db,
+        &ctx.notifier,
+        file_name,
+        content,
+        Some(metadata),
+    )
+    .await?;
+    info!("created receipt {} from {}", receipt.id, path.display());
+    Ok(())
+}
+
+fn is_scan_failure(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref(), Some(Rejection::ScanFailed(_)))
+}
+
+async fn scan_directory(
+    config: &FolderIngestConfig,
+    ctx: &IngestContext,
+    dir: &Path,
+) -> std::io::Result<()> {
+    let settle_time = Duration::from_secs(config.settle_time);
+    let archive_dir = dir.join(&config.archive_dir);
+    let error_dir = dir.join(&config.error_dir);
+
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with('.') || !entry.file_type().await?.is_file() {
+            continue;
+        }
+        if !is_settled(&path, settle_time).await? {
+            continue;
+        }
+
+        let destination = match import_file(ctx, &path, &file_name).await {
+            Ok(()) => &archive_dir,
+            // left in place and retried with the next scan
+            Err(err) if is_scan_failure(&err) => {
+                warn!("could not scan {}: {:#}", path.display(), err);
+                continue;
+            },
+            Err(err) => {
+                error!("could not import {}: {}", path.display(), err);
+                &error_dir
+            },
+        };
+        // the next scan tries again, without importing the file twice
+        if let Err(err) = move_into(&path, destination, &file_name).await {
+            error!(
+                "could not move {} to {}: {}",
+                path.display(),
+                destination.display(),
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Starts the watched folder worker on liftoff if there is a
+/// `folder_ingest` config.
+pub fn fairing(config: Option<FolderIngestConfig>) -> AdHoc {
+    AdHoc::on_liftoff("Watched folder ingestion", |rocket| {
+        Box::pin(async move {
+            let config = match config {
+                Some(config) => config,
+                None => return,
+            };
+            let ctx = match IngestContext::from_rocket(rocket) {
+                Some(ctx) => ctx,
+                None => {
+                    error!(
+                        "folder ingestion needs the databases to be attached"
+                    );
+                    return;
+                },
+            };
+
+            rocket::tokio::spawn(async move {
+                let mut interval =
+                    time::interval(Duration::from_secs(config.poll_interval));
+                loop {
+                    interval.tick().await;
+                    for dir in &config.directories {
+                        if let Err(err) =
+                            scan_directory(&config, &ctx, dir).await
+                        {
+                            warn!("could not scan {}: {}", dir.display(), err);
+                        }
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destination_never_overwrites() {
+        let dir = std::env::temp_dir()
+            .join(format!("expensebills-archive-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(unique_destination(&dir, "scan.pdf"), dir.join("scan.pdf"));
+        std::fs::write(dir.join("scan.pdf"), b"first").unwrap();
+        assert_eq!(
+            unique_destination(&dir, "scan.pdf"),
+            dir.join("1-scan.pdf")
+        );
+        std::fs::write(dir.join("1-scan.pdf"), b"second").unwrap();
+        assert_eq!(
+            unique_destination(&dir, "scan.pdf"),
+            dir.join("2-scan.pdf")
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_failed_scans_are_retried() {
+        let failed = Rejection::ScanFailed(anyhow::anyhow!("clamd is down"));
+        assert!(is_scan_failure(&failed.into()));
+        let infected = Rejection::Infected("Eicar".to_string());
+        assert!(!is_scan_failure(&infected.into()));
+        assert!(!is_scan_failure(&anyhow::anyhow!("disk full")));
+    }
+}
@@ -0,0 +1,448 @@
+use super::IngestContext;
+use crate::uploads::Rejection;
+use crate::v1::receipts::{create_inbox_receipt, find_by_file_hash};
+use anyhow::anyhow;
+use log::{error, info, warn};
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+use rocket::fairing::AdHoc;
+use rocket::serde::json::json;
+use rocket::serde::Deserialize;
+use rocket::tokio::{task, time};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Configuration of the mail ingestion worker, read from the `mail_ingest`
+/// table of the Rocket figment. Ingestion is disabled if the table is missing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct MailIngestConfig {
+    pub source: MailSource,
+    /// Seconds between two polls of the mailbox.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "kind", rename_all = "lowercase")]
+pub enum MailSource {
+    Imap {
+        host: String,
+        #[serde(default = "default_imap_port")]
+        port: u16,
+        username: String,
+        password: String,
+        #[serde(default = "default_mailbox")]
+        mailbox: String,
+    },
+    /// A local Maildir, mostly useful for testing. Mails are taken from `new`
+    /// and moved to `cur` once processed.
+    Maildir {
+        path: PathBuf,
+    },
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+#[derive(Debug, Clone)]
+enum MailId {
+    Imap(u32),
+    Maildir(PathBuf),
+}
+
+struct RawMail {
+    id: MailId,
+    content: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Attachment {
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct IncomingMail {
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    pub message_id: Option<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+impl MailSource {
+    fn fetch(&self) -> anyhow::Result<Vec<RawMail>> {
+        match self {
+            MailSource::Imap {
+                host,
+                port,
+                username,
+                password,
+                mailbox,
+            } => {
+                let tls = native_tls::TlsConnector::builder().build()?;
+                let client = imap::connect((host.as_str(), *port), host, &tls)?;
+                let mut session =
+                    client.login(username, password).map_err(|(err, _)| err)?;
+                session.select(mailbox)?;
+
+                let uids = session.uid_search("UNSEEN")?;
+                let mut mails = Vec::new();
+                if !uids.is_empty() {
+                    let uid_set = uids
+                        .iter()
+                        .map(|uid| uid.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    // PEEK so mails that fail to import stay unseen
+                    for fetch in
+                        session.uid_fetch(uid_set, "BODY.PEEK[]")?.iter()
+                    {
+                        if let (Some(uid), Some(body)) =
+                            (fetch.uid, fetch.body())
+                        {
+                            mails.push(RawMail {
+                                id: MailId::Imap(uid),
+                                content: body.to_vec(),
+                            });
+                        }
+                    }
+                }
+                session.logout()?;
+                Ok(mails)
+            },
+            MailSource::Maildir {
+                path,
+            } => {
+                let mut mails = Vec::new();
+                for entry in std::fs::read_dir(path.join("new"))? {
+                    let entry = entry?;
+                    if entry.file_type()?.is_file() {
+                        mails.push(RawMail {
+                            content: std::fs::read(entry.path())?,
+                            id: MailId::Maildir(entry.path()),
+                        });
+                    }
+                }
+                Ok(mails)
+            },
+        }
+    }
+
+    fn mark_seen(&self, ids: &[MailId]) -> anyhow::Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        match self {
+            MailSource::Imap {
+                host,
+                port,
+                username,
+                password,
+                mailbox,
+            } => {
+                let uid_set = ids
+                    .iter()
+                    .filter_map(|id| match id {
+                        MailId::Imap(uid) => Some(uid.to_string()),
+                        MailId::Maildir(_) => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let tls = native_tls::TlsConnector::builder().build()?;
+                let client = imap::connect((host.as_str(), *port), host, &tls)?;
+                let mut session =
+                    client.login(username, password).map_err(|(err, _)| err)?;
+                session.select(mailbox)?;
+                session.uid_store(uid_set, "+FLAGS (\\Seen)")?;
+                session.logout()?;
+                Ok(())
+            },
+            MailSource::Maildir {
+                path,
+            } => {
+                for id in ids {
+                    if let MailId::Maildir(file) = id {
+                        mark_maildir_seen(path, file)?;
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+fn mark_maildir_seen(maildir: &Path, file: &Path) -> std::io::Result<()> {
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    std::fs::rename(file, maildir.join("cur").join(format!("{}:2,S", name)))
+}
+
+/// PDFs are always taken, images only when explicitly attached so logos in
+/// mail signatures do not end up as receipts.
+fn is_receipt_attachment(part: &ParsedMail) -> bool {
+    let mimetype = part.ctype.mimetype.to_lowercase();
+    mimetype == "application/pdf"
+        || (mimetype.starts_with("image/")
+            && part.get_content_disposition().disposition
+                == DispositionType::Attachment)
+}
+
+fn attachment_filename(part: &ParsedMail) -> String {
+    part.get_content_disposition()
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned()
+        .unwrap_or_else(|| "attachment".to_string())
+}
+
+fn collect_attachments(
+    part: &ParsedMail,
+    attachments: &mut Vec<Attachment>,
+) -> Result<(), mailparse::MailParseError> {
+    if part.subparts.is_empty() {
+        if is_receipt_attachment(part) {
+            attachments.push(Attachment {
+                filename: attachment_filename(part),
+                content: part.get_body_raw()?,
+            });
+        }
+    } else {
+        for subpart in &part.subparts {
+            collect_attachments(subpart, attachments)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extracts sender, subject and all PDF or image attachments from a raw
+/// RFC 822 message.
+pub(crate) fn parse_mail(
+    raw: &[u8],
+) -> Result<IncomingMail, mailparse::MailParseError> {
+    let mail = mailparse::parse_mail(raw)?;
+    let headers = mail.get_headers();
+    let mut attachments = Vec::new();
+    collect_attachments(&mail, &mut attachments)?;
+
+    Ok(IncomingMail {
+        sender: headers.get_first_value("From"),
+        subject: headers.get_first_value("Subject"),
+        message_id: headers.get_first_value("Message-ID"),
+        attachments,
+    })
+}
+
+/// Creates one Inbox receipt per attachment of `mail`. All attachments are
+/// checked before the first receipt is created, and those stored by an
+/// earlier attempt are skipped, so retried mails create no duplicates.
+/// Returns the number of receipts created.
+async fn ingest_mail(
+    ctx: &IngestContext,
+    mail: IncomingMail,
+) -> anyhow::Result<usize> {
+    let mut accepted = Vec::new();
+    for attachment in mail.attachments {
+        match ctx.uploads.check(&attachment.content).await {
+            Ok(_) => accepted.push(attachment),
+            // retried with the next poll
+            Err(Rejection::ScanFailed(err)) => return Err(err),
+            Err(rejection) => warn!(
+                "skipping attachment {}: {}",
+                attachment.filename, rejection
+            ),
+        }
+    }
+
+    let mut created = 0;
+    for attachment in accepted {
+        let hash = sha256::digest_bytes(&attachment.content);
+        if find_by_file_hash(&ctx.sql_db, &hash).await?.is_some() {
+            info!("attachment {} is stored already", attachment.filename);
+            continue;
+        }
+        let metadata = json!({
+            "source": "mail",
+            "sender": mail.sender,
+            "subject": mail.subject,
+            "message_id": mail.message_id,
+        });
+        let receipt = create_inbox_receipt(
+            &ctx.sql_db,
+            &ctx.files_db,
+            &ctx.notifier,
+            &attachment.filename,
+            attachment.content,
+            Some(metadata),
+        )
+        .await?;
+        info!("created receipt {} from mail attachment", receipt.id);
+        created += 1;
+    }
+    Ok(created)
+}
+
+async fn poll(source: &MailSource, ctx: &IngestContext) -> anyhow::Result<()> {
+    let fetch_source = source.clone();
+    let mails = task::spawn_blocking(move || fetch_source.fetch())
+        .await
+        .map_err(|err| anyhow!("mail fetch task failed: {}", err))??;
+
+    let mut done = Vec::new();
+    for raw in mails {
+        match parse_mail(&raw.content) {
+            Ok(mail) => match ingest_mail(ctx, mail).await {
+                Ok(0) => {
+                    info!("mail {:?} has no new receipt attachments", raw.id);
+                    done.push(raw.id);
+                },
+                Ok(_) => done.push(raw.id),
+                // leave the mail unseen so the next poll retries it
+                Err(err) => {
+                    error!("could not import mail {:?}: {}", raw.id, err)
+                },
+            },
+            Err(err) => {
+                warn!("skipping unparsable mail {:?}: {}", raw.id, err);
+                done.push(raw.id);
+            },
+        }
+    }
+
+    let mark_source = source.clone();
+    task::spawn_blocking(move || mark_source.mark_seen(&done))
+        .await
+        .map_err(|err| anyhow!("mail flag task failed: {}", err))??;
+    Ok(())
+}
+
+/// Starts the mail ingestion worker on liftoff if there is a `mail_ingest`
+/// config.
+pub fn fairing(config: Option<MailIngestConfig>) -> AdHoc {
+    AdHoc::on_liftoff("Mail ingestion", |rocket| {
+        Box::pin(async move {
+            let config = match config {
+                Some(config) => config,
+                None => return,
+            };
+            let ctx = match IngestContext::from_rocket(rocket) {
+                Some(ctx) => ctx,
+                None => {
+                    error!("mail ingestion needs the databases to be attached");
+                    return;
+                },
+            };
+
+            rocket::tokio::spawn(async move {
+                let mut interval =
+                    time::interval(Duration::from_secs(config.poll_interval));
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = poll(&config.source, &ctx).await {
+                        error!("mail ingestion failed: {}", err);
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIL: &str = "From: Stadtwerke <billing@stadtwerke.example>\r
+To: bills@example.com\r
+Subject: Your invoice for July\r
+Message-ID: <1234@stadtwerke.example>\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"XYZ\"\r
+\r
+--XYZ\r
+Content-Type: text/plain; charset=utf-8\r
+\r
+Please find your invoice attached.\r
+--XYZ\r
+Content-Type: application/pdf; name=\"invoice-07.pdf\"\r
+Content-Disposition: attachment; filename=\"invoice-07.pdf\"\r
+Content-Transfer-Encoding: base64\r
+\r
+JVBERi0xLjQK\r
+--XYZ\r
+Content-Type: image/png\r
+Content-Disposition: inline; filename=\"logo.png\"\r
+Content-Transfer-Encoding: base64\r
+\r
+iVBORw0KGgo=\r
+--XYZ\r
+Content-Type: image/jpeg\r
+Content-Disposition: attachment; filename=\"scan.jpg\"\r
+Content-Transfer-Encoding: base64\r
+\r
+/9j/4AAQ\r
+--XYZ--\r
+";
+
+    #[test]
+    fn extracts_metadata_and_attachments() {
+        let mail = parse_mail(MAIL.as_bytes()).expect("valid mail");
+
+        assert_eq!(
+            mail.sender.as_deref(),
+            Some("Stadtwerke <billing@stadtwerke.example>")
+        );
+        assert_eq!(mail.subject.as_deref(), Some("Your invoice for July"));
+        assert_eq!(
+            mail.message_id.as_deref(),
+            Some("<1234@stadtwerke.example>")
+        );
+        assert_eq!(
+            mail.attachments,
+            vec![
+                Attachment {
+                    filename: "invoice-07.pdf".to_string(),
+                    content: b"%PDF-1.4\n".to_vec(),
+                },
+                Attachment {
+                    filename: "scan.jpg".to_string(),
+                    content: b"\xff\xd8\xff\xe0\x00\x10".to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_and_marks_maildir() {
+        let maildir = std::env::temp_dir()
+            .join(format!("expensebills-maildir-{}", uuid::Uuid::new_v4()));
+        for sub in ["new", "cur", "tmp"] {
+            std::fs::create_dir_all(maildir.join(sub)).unwrap();
+        }
+        std::fs::write(maildir.join("new").join("1.mail"), MAIL).unwrap();
+
+        let source = MailSource::Maildir {
+            path: maildir.clone(),
+        };
+        let mails = source.fetch().unwrap();
+        assert_eq!(mails.len(), 1);
+        assert_eq!(mails[0].content, MAIL.as_bytes());
+
+        let ids: Vec<MailId> = mails.into_iter().map(|m| m.id).collect();
+        source.mark_seen(&ids).unwrap();
+        assert!(source.fetch().unwrap().is_empty());
+        assert!(maildir.join("cur").join("1.mail:2,S").exists());
+
+        std::fs::remove_dir_all(maildir).unwrap();
+    }
+}
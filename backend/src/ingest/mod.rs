@@ -0,0 +1,36 @@
+use crate::files::FileStore;
+use crate::notifications::Notifier;
+use crate::uploads::UploadPolicy;
+use crate::{SQLDb, SledDB};
+use rocket::{Orbit, Rocket};
+use sea_orm::DatabaseConnection;
+use sea_orm_rocket::Database;
+
+pub(crate) mod folder;
+pub(crate) mod mail;
+pub(crate) mod text;
+
+/// Handles to the databases, the notifier and the upload checks for workers
+/// that create receipts outside of a request.
+#[derive(Clone)]
+pub(crate) struct IngestContext {
+    pub sql_db: DatabaseConnection,
+    pub files_db: FileStore,
+    pub notifier: Notifier,
+    pub uploads: UploadPolicy,
+}
+
+impl IngestContext {
+    pub fn from_rocket(rocket: &Rocket<Orbit>) -> Option<Self> {
+        let sql_db = SQLDb::fetch(rocket)?.conn.clone();
+        let files_db = rocket.state::<SledDB>()?.files_db.clone();
+        let notifier = rocket.state::<Notifier>()?.clone();
+        let uploads = rocket.state::<UploadPolicy>()?.clone();
+        Some(IngestContext {
+            sql_db,
+            files_db,
+            notifier,
+            uploads,
+        })
+    }
+}
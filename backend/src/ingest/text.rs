@@ -0,0 +1,51 @@
+use log::debug;
+use std::panic;
+
+/// Extracts the text of a receipt file so rules can match on it. Text files
+/// are taken as they are, PDFs are run through pdf-extract. Returns `None`
+/// for other files and files without any text, like scanned PDFs.
+pub(crate) fn extract_text(content: &[u8]) -> Option<String> {
+    let text = if content.starts_with(b"%PDF") {
+        // pdf-extract panics on some malformed files
+        let result =
+            panic::catch_unwind(|| pdf_extract::extract_text_from_mem(content));
+        match result {
+            Ok(Ok(text)) => text,
+            Ok(Err(err)) => {
+                debug!("could not extract text from pdf: {}", err);
+                return None;
+            },
+            Err(_) => {
+                debug!("pdf-extract panicked on malformed pdf");
+                return None;
+            },
+        }
+    } else if !content.contains(&0) {
+        String::from_utf8(content.to_vec()).ok()?
+    } else {
+        return None;
+    };
+
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_plain_text_only() {
+        assert_eq!(
+            extract_text(b"  Stadtwerke invoice\n").as_deref(),
+            Some("Stadtwerke invoice")
+        );
+        assert_eq!(extract_text(b"\x89PNG\r\n\x1a\n\0\0"), None);
+        assert_eq!(extract_text(b"%PDF-1.4 not really a pdf"), None);
+        assert_eq!(extract_text(b"   "), None);
+    }
+}
@@ -0,0 +1,164 @@
+mod accounting;
+pub mod backup;
+mod cors;
+pub mod files;
+mod ingest;
+pub mod migrations;
+pub mod notifications;
+pub mod pool;
+mod previews;
+mod process;
+mod reminders;
+pub mod uploads;
+pub mod v1;
+
+#[macro_use]
+extern crate rocket;
+use anyhow::Context;
+use log::{error, info};
+//use rocket_okapi::{swagger_ui::make_swagger_ui, openapi_get_routes};
+use files::{EncryptionConfig, FileStore, Keyring};
+use migrations::Migrator;
+use pool::SQLDb;
+use rocket::fairing::{self, AdHoc};
+use rocket::figment::Figment;
+use rocket::routes;
+use rocket::serde::de::DeserializeOwned;
+use rocket::Config;
+use rocket::{Build, Rocket};
+use sea_orm_migration::MigratorTrait;
+use sea_orm_rocket::Database;
+
+async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
+    let auto_migrate =
+        rocket.figment().extract_inner::<bool>("auto_migrate").unwrap_or(true);
+    if !auto_migrate {
+        info!("Skipping DB migrations, auto_migrate is disabled");
+        return Ok(rocket);
+    }
+
+    if let Some(db) = SQLDb::fetch(&rocket) {
+        // run migrations using `db`. get the inner type with &db.0.
+        match Migrator::up(&db.conn, None).await {
+            Ok(_) => {
+                info!("DB migrations suceeded");
+                Ok(rocket)
+            },
+            Err(err) => {
+                error!("DB migrations failed with: {}", err);
+                Err(rocket)
+            },
+        }
+    } else {
+        error!("No database configuration found");
+        Err(rocket)
+    }
+}
+
+/// Reads the `key` table of `figment`, or the defaults if there is none.
+/// Like Rocket's own config, a malformed table stops the launch instead of
+/// being replaced by defaults, which could e.g. turn off checks.
+fn config_or_default<T: DeserializeOwned + Default>(
+    figment: &Figment,
+    key: &str,
+) -> T {
+    match figment.extract_inner(key) {
+        Ok(config) => config,
+        Err(err) if err.missing() => T::default(),
+        Err(err) => panic!("invalid `{}` config: {}", key, err),
+    }
+}
+
+pub struct SledDB {
+    pub files_db: FileStore,
+}
+
+/// Opens the sled db holding the receipt files, which lives in a `files`
+/// directory next to the configured `temp_dir`, with the keys of the
+/// `encryption` table. sled locks the directory, so this fails while another
+/// process, usually the running server, has it open.
+pub fn open_files_db(figment: &Figment) -> anyhow::Result<FileStore> {
+    let config: Config = figment.extract()?;
+    let path = config.temp_dir.relative().parent().unwrap().join("files");
+    // a broken key config must not silently store files unencrypted
+    let encryption = figment
+        .extract_inner::<EncryptionConfig>("encryption")
+        .or_else(|err| {
+            if err.missing() {
+                Ok(Default::default())
+            } else {
+                Err(err)
+            }
+        })
+        .context("encryption config")?;
+    let keyring =
+        Keyring::from_config(&encryption).context("encryption keys")?;
+
+    let db = sled_extensions::Config::default()
+        .path(&path)
+        .open()
+        .with_context(|| {
+            format!(
+                "opening {}, stop the server if it is running",
+                path.display()
+            )
+        })?;
+    Ok(FileStore::new(db, keyring))
+}
+
+pub fn rocket() -> Rocket<Build> {
+    rocket_with(Config::figment())
+}
+
+/// Builds the application from `figment` instead of the default Rocket.toml
+/// and environment, e.g. to point it to test databases.
+pub fn rocket_with(figment: Figment) -> Rocket<Build> {
+    let rocket = rocket::custom(figment);
+    let figment = rocket.figment();
+
+    let db = open_files_db(figment)
+        .unwrap_or_else(|err| panic!("files db: {:#}", err));
+
+    let cors_config: cors::CorsConfig = config_or_default(figment, "cors");
+    let accounting_config: accounting::AccountingConfig =
+        config_or_default(figment, "accounting");
+    let preview_config: previews::PreviewConfig =
+        config_or_default(figment, "previews");
+    let upload_config: uploads::UploadConfig =
+        config_or_default(figment, "uploads");
+    // the workers only run if their table is there
+    let mail_config: Option<ingest::mail::MailIngestConfig> =
+        config_or_default(figment, "mail_ingest");
+    let folder_config: Option<ingest::folder::FolderIngestConfig> =
+        config_or_default(figment, "folder_ingest");
+    let reminder_config: Option<reminders::ReminderConfig> =
+        config_or_default(figment, "reminders");
+    let notification_config: Option<notifications::NotificationConfig> =
+        config_or_default(figment, "notifications");
+
+    rocket
+        .attach(AdHoc::config::<Config>())
+        .attach(SQLDb::init())
+        .attach(AdHoc::try_on_ignite("DB Migrations", run_migrations))
+        .attach(cors::Cors::new(cors_config))
+        .attach(ingest::mail::fairing(mail_config))
+        .attach(ingest::folder::fairing(folder_config))
+        .attach(reminders::fairing(reminder_config))
+        .attach(notifications::fairing(notification_config))
+        .manage(SledDB {
+            files_db: db,
+        })
+        .manage(notifications::Notifier::new())
+        .manage(accounting_config)
+        .manage(preview_config)
+        .manage(uploads::UploadPolicy::new(upload_config))
+        .mount("/", cors::routes())
+        .mount("/api/v1/greeting", routes![v1::greeting::hello])
+        .mount("/api/v1/receipts", v1::receipt_routes())
+        .mount("/api/v1/recurring", v1::recurring_routes())
+        .mount("/api/v1/categories", v1::category_routes())
+        .mount("/api/v1/rules", v1::rule_routes())
+        .mount("/api/v1/reports", v1::report_routes())
+        .mount("/api/v1/exports", v1::export_routes())
+    //.mount("/docs/v1", make_swagger_ui(&openapi::get_docs()))
+}
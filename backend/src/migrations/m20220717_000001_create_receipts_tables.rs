@@ -1,4 +1,6 @@
-use sea_orm::sea_query::extension::postgres::TypeCreateStatement;
+use super::ReceiptStateType;
+use sea_orm::sea_query::extension::postgres::{Type, TypeCreateStatement};
+use sea_orm::DbBackend;
 use sea_orm_migration::prelude::*;
 
 #[derive(DeriveMigrationName)]
@@ -7,21 +9,27 @@ pub struct Migration;
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        manager
-            .create_type(
-                TypeCreateStatement::new()
-                    .as_enum(ReceiptState::Type)
-                    .values(vec![
-                        ReceiptState::Inbox,
-                        ReceiptState::Valid,
-                        ReceiptState::Payed,
-                        ReceiptState::Declined,
-                        ReceiptState::Process,
-                        ReceiptState::Done,
-                    ])
-                    .to_owned(),
-            )
-            .await?;
+        let postgres = manager.get_database_backend() == DbBackend::Postgres;
+        let mut state = ColumnDef::new(Receipts::State);
+        if postgres {
+            manager
+                .create_type(
+                    TypeCreateStatement::new()
+                        .as_enum(ReceiptStateType::Type)
+                        .values(ReceiptStateType::values())
+                        .to_owned(),
+                )
+                .await?;
+            state.enumeration(
+                ReceiptStateType::Type,
+                ReceiptStateType::values(),
+            );
+        } else {
+            // Other backends have no enum types, the state is stored as the
+            // string that m20220810_000004 converts the Postgres column to.
+            state.string_len(16);
+        }
+
         manager
             .create_table(
                 Table::create()
@@ -34,21 +42,7 @@ impl MigrationTrait for Migration {
                             .primary_key(),
                     )
                     .col(ColumnDef::new(Receipts::Name).string().not_null())
-                    .col(
-                        ColumnDef::new(Receipts::State)
-                            .enumeration(
-                                ReceiptState::Type,
-                                vec![
-                                    ReceiptState::Inbox,
-                                    ReceiptState::Valid,
-                                    ReceiptState::Payed,
-                                    ReceiptState::Declined,
-                                    ReceiptState::Process,
-                                    ReceiptState::Done,
-                                ],
-                            )
-                            .not_null(),
-                    )
+                    .col(state.not_null())
                     .col(ColumnDef::new(Receipts::FileHash).string().not_null())
                     .col(ColumnDef::new(Receipts::Category).string().null())
                     .col(
@@ -64,54 +58,13 @@ impl MigrationTrait for Migration {
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
         manager
             .drop_table(Table::drop().table(Receipts::Table).to_owned())
-            .await
-    }
-}
-
-enum ReceiptState {
-    Type,
-    Inbox,
-    Valid,
-    Payed,
-    Declined,
-    Process,
-    Done,
-}
-
-impl Iden for ReceiptState {
-    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
-        write!(
-            s,
-            "{}",
-            match self {
-                ReceiptState::Inbox => "inbox",
-                ReceiptState::Valid => "valid",
-                ReceiptState::Payed => "payed",
-                ReceiptState::Declined => "declined",
-                ReceiptState::Process => "process",
-                ReceiptState::Done => "done",
-                ReceiptState::Type => "receipt_state",
-            }
-        )
-        .unwrap();
-    }
-}
-
-impl std::fmt::Display for ReceiptState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                ReceiptState::Inbox => "inbox",
-                ReceiptState::Valid => "valid",
-                ReceiptState::Payed => "payed",
-                ReceiptState::Declined => "declined",
-                ReceiptState::Process => "process",
-                ReceiptState::Done => "done",
-                ReceiptState::Type => "receipt_state",
-            }
-        )
+            .await?;
+        if manager.get_database_backend() == DbBackend::Postgres {
+            manager
+                .drop_type(Type::drop().name(ReceiptStateType::Type).to_owned())
+                .await?;
+        }
+        Ok(())
     }
 }
 
@@ -0,0 +1,30 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Receipts::Table)
+                    .add_column(
+                        ColumnDef::new(Receipts::Metadata).json().null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        super::drop_column(manager, Receipts::Table, Receipts::Metadata).await
+    }
+}
+
+#[derive(Iden)]
+enum Receipts {
+    Table,
+    Metadata,
+}
@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Recipients::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Recipients::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Recipients::ReceiptId).uuid().not_null(),
+                    )
+                    .col(ColumnDef::new(Recipients::Name).string().not_null())
+                    .col(ColumnDef::new(Recipients::Iban).string().not_null())
+                    .col(
+                        ColumnDef::new(Recipients::AddressLine1)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Recipients::AddressLine2)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Recipients::AddressLine3)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Recipients::AddressLine4)
+                            .string()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-recipients-receipt_id")
+                            .from(Recipients::Table, Recipients::ReceiptId)
+                            .to(Receipts::Table, Receipts::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Recipients::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Receipts {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Recipients {
+    Table,
+    Id,
+    ReceiptId,
+    Name,
+    Iban,
+    AddressLine1,
+    AddressLine2,
+    AddressLine3,
+    AddressLine4,
+}
@@ -0,0 +1,98 @@
+use super::ReceiptStateType;
+use sea_orm::sea_query::extension::postgres::{Type, TypeCreateStatement};
+use sea_orm::{ConnectionTrait, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+
+/// Converts the Postgres `receipt_state` enum column into a plain string
+/// column so the state is stored the same way on every backend. Other
+/// backends already create the column as a string.
+///
+/// Also turns `payment_date` from a timestamp into a date, which is what the
+/// entity reads. SQLite can't change column types, there the stored values are
+/// cut to their date instead.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let payment_date = match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                r#"ALTER TABLE "receipts" ALTER COLUMN "payment_date" TYPE date"#
+            },
+            DbBackend::MySql => {
+                "ALTER TABLE `receipts` MODIFY `payment_date` date NULL"
+            },
+            DbBackend::Sqlite => {
+                r#"UPDATE "receipts" SET "payment_date" = date("payment_date")"#
+            },
+        };
+        execute(manager, payment_date).await?;
+
+        if manager.get_database_backend() != DbBackend::Postgres {
+            return Ok(());
+        }
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                DbBackend::Postgres,
+                r#"ALTER TABLE "receipts" ALTER COLUMN "state" TYPE varchar(16) USING "state"::text"#
+                    .to_owned(),
+            ))
+            .await?;
+        manager
+            .drop_type(Type::drop().name(ReceiptStateType::Type).to_owned())
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                execute(
+                    manager,
+                    r#"ALTER TABLE "receipts" ALTER COLUMN "payment_date" TYPE timestamp"#,
+                )
+                .await?
+            },
+            DbBackend::MySql => {
+                execute(
+                    manager,
+                    "ALTER TABLE `receipts` MODIFY `payment_date` datetime NULL",
+                )
+                .await?
+            },
+            // dates are valid timestamps for SQLite
+            DbBackend::Sqlite => {},
+        }
+
+        if manager.get_database_backend() != DbBackend::Postgres {
+            return Ok(());
+        }
+        manager
+            .create_type(
+                TypeCreateStatement::new()
+                    .as_enum(ReceiptStateType::Type)
+                    .values(ReceiptStateType::values())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                DbBackend::Postgres,
+                r#"ALTER TABLE "receipts" ALTER COLUMN "state" TYPE receipt_state USING "state"::receipt_state"#
+                    .to_owned(),
+            ))
+            .await?;
+        Ok(())
+    }
+}
+
+async fn execute(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    let backend = manager.get_database_backend();
+    manager
+        .get_connection()
+        .execute(Statement::from_string(backend, sql.to_owned()))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // one column per statement, SQLite cannot add several at once
+        for column in [
+            Receipts::DueDate,
+            Receipts::DiscountDeadline,
+            Receipts::RemindedOn,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Receipts::Table)
+                        .add_column(ColumnDef::new(column).date().null())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Receipts::RemindedOn,
+            Receipts::DiscountDeadline,
+            Receipts::DueDate,
+        ] {
+            super::drop_column(manager, Receipts::Table, column).await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Receipts {
+    Table,
+    DueDate,
+    DiscountDeadline,
+    RemindedOn,
+}
@@ -0,0 +1,158 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecurringBills::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RecurringBills::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::Name)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::RecipientName)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RecurringBills::RecipientIban).string())
+                    .col(ColumnDef::new(RecurringBills::Category).string())
+                    .col(
+                        ColumnDef::new(RecurringBills::ExpectedAmount)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::Cadence)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::StartsOn)
+                            .date()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::MatchPattern)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::TolerancePercent)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::GraceDays)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecurringBills::Active)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExpectedBills::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ExpectedBills::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ExpectedBills::RecurringBillId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ExpectedBills::ExpectedOn)
+                            .date()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ExpectedBills::ReceiptId).uuid())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-expected_bills-recurring_bill_id")
+                            .from(
+                                ExpectedBills::Table,
+                                ExpectedBills::RecurringBillId,
+                            )
+                            .to(RecurringBills::Table, RecurringBills::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-expected_bills-receipt_id")
+                            .from(
+                                ExpectedBills::Table,
+                                ExpectedBills::ReceiptId,
+                            )
+                            .to(Receipts::Table, Receipts::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ExpectedBills::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RecurringBills::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Receipts {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum RecurringBills {
+    Table,
+    Id,
+    Name,
+    RecipientName,
+    RecipientIban,
+    Category,
+    ExpectedAmount,
+    Cadence,
+    StartsOn,
+    MatchPattern,
+    TolerancePercent,
+    GraceDays,
+    Active,
+}
+
+#[derive(Iden)]
+enum ExpectedBills {
+    Table,
+    Id,
+    RecurringBillId,
+    ExpectedOn,
+    ReceiptId,
+}
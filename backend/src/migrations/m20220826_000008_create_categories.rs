@@ -0,0 +1,200 @@
+use sea_orm::{ConnectionTrait, DbBackend, Statement};
+use sea_orm_migration::prelude::*;
+use std::collections::BTreeSet;
+
+/// Replaces the free-text `category` of receipts and recurring bills with a
+/// reference into the new `categories` table. Every distinct name in use
+/// becomes a top-level category.
+///
+/// SQLite cannot drop columns that are part of a foreign key, so the
+/// reference is only enforced by the database on Postgres.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum Categories {
+    Table,
+    Id,
+    Name,
+    ParentId,
+    MonthlyBudget,
+}
+
+#[derive(Iden, Clone, Copy)]
+enum Receipts {
+    Table,
+}
+
+#[derive(Iden, Clone, Copy)]
+enum RecurringBills {
+    Table,
+}
+
+#[derive(Iden)]
+enum Columns {
+    Category,
+    CategoryId,
+}
+
+async fn category_names<T: Iden + 'static>(
+    manager: &SchemaManager<'_>,
+    table: T,
+) -> Result<Vec<String>, DbErr> {
+    let select = Query::select()
+        .distinct()
+        .column(Columns::Category)
+        .from(table)
+        .and_where(Expr::col(Columns::Category).is_not_null())
+        .to_owned();
+    let backend = manager.get_database_backend();
+    manager
+        .get_connection()
+        .query_all(backend.build(&select))
+        .await?
+        .iter()
+        .map(|row| row.try_get("", &Columns::Category.to_string()))
+        .collect()
+}
+
+async fn execute(
+    manager: &SchemaManager<'_>,
+    sql: String,
+) -> Result<(), DbErr> {
+    let backend = manager.get_database_backend();
+    manager
+        .get_connection()
+        .execute(Statement::from_string(backend, sql))
+        .await?;
+    Ok(())
+}
+
+/// Adds `category_id` to `table` and points it to the category named like
+/// the old `category` column.
+async fn link_categories<T: Iden + Copy + 'static>(
+    manager: &SchemaManager<'_>,
+    table: T,
+) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(table)
+                .add_column(ColumnDef::new(Columns::CategoryId).uuid().null())
+                .to_owned(),
+        )
+        .await?;
+    if manager.get_database_backend() == DbBackend::Postgres {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(table)
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name(&format!(
+                                "fk-{}-category_id",
+                                table.to_string()
+                            ))
+                            .from_tbl(table)
+                            .from_col(Columns::CategoryId)
+                            .to_tbl(Categories::Table)
+                            .to_col(Categories::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+    }
+    execute(
+        manager,
+        format!(
+            r#"UPDATE "{0}" SET "category_id" = (SELECT "id" FROM "categories" WHERE "categories"."name" = "{0}"."category")"#,
+            table.to_string()
+        ),
+    )
+    .await?;
+    super::drop_column(manager, table, Columns::Category).await
+}
+
+/// Brings back the `category` column of `table` holding category names.
+async fn unlink_categories<T: Iden + Copy + 'static>(
+    manager: &SchemaManager<'_>,
+    table: T,
+) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(table)
+                .add_column(ColumnDef::new(Columns::Category).string().null())
+                .to_owned(),
+        )
+        .await?;
+    execute(
+        manager,
+        format!(
+            r#"UPDATE "{0}" SET "category" = (SELECT "name" FROM "categories" WHERE "categories"."id" = "{0}"."category_id")"#,
+            table.to_string()
+        ),
+    )
+    .await?;
+    super::drop_column(manager, table, Columns::CategoryId).await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Categories::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Categories::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Categories::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Categories::ParentId).uuid().null())
+                    .col(
+                        ColumnDef::new(Categories::MonthlyBudget)
+                            .big_integer()
+                            .null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-categories-parent_id")
+                            .from(Categories::Table, Categories::ParentId)
+                            .to(Categories::Table, Categories::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut names = BTreeSet::new();
+        names.extend(category_names(manager, Receipts::Table).await?);
+        names.extend(category_names(manager, RecurringBills::Table).await?);
+        for name in names {
+            let insert = Query::insert()
+                .into_table(Categories::Table)
+                .columns([Categories::Id, Categories::Name])
+                .values_panic([uuid::Uuid::new_v4().into(), name.into()])
+                .to_owned();
+            let backend = manager.get_database_backend();
+            manager.get_connection().execute(backend.build(&insert)).await?;
+        }
+
+        link_categories(manager, Receipts::Table).await?;
+        link_categories(manager, RecurringBills::Table).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        unlink_categories(manager, Receipts::Table).await?;
+        unlink_categories(manager, RecurringBills::Table).await?;
+        manager
+            .drop_table(Table::drop().table(Categories::Table).to_owned())
+            .await
+    }
+}
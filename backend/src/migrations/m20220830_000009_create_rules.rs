@@ -0,0 +1,93 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Receipts::Table)
+                    .add_column(
+                        ColumnDef::new(Receipts::ContentText).text().null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Rules::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Rules::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Rules::Name).string().not_null())
+                    .col(ColumnDef::new(Rules::Priority).integer().not_null())
+                    .col(ColumnDef::new(Rules::Enabled).boolean().not_null())
+                    .col(ColumnDef::new(Rules::RecipientIban).string())
+                    .col(ColumnDef::new(Rules::RecipientName).string())
+                    .col(ColumnDef::new(Rules::NamePattern).string())
+                    .col(ColumnDef::new(Rules::TextContains).string())
+                    .col(ColumnDef::new(Rules::MinAmount).big_integer())
+                    .col(ColumnDef::new(Rules::MaxAmount).big_integer())
+                    .col(ColumnDef::new(Rules::SetCategoryId).uuid())
+                    .col(ColumnDef::new(Rules::SetRecipientName).string())
+                    .col(ColumnDef::new(Rules::SetRecipientIban).string())
+                    .col(ColumnDef::new(Rules::AutoAccept).boolean().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-rules-set_category_id")
+                            .from(Rules::Table, Rules::SetCategoryId)
+                            .to(Categories::Table, Categories::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Rules::Table).to_owned())
+            .await?;
+        super::drop_column(manager, Receipts::Table, Receipts::ContentText)
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Receipts {
+    Table,
+    ContentText,
+}
+
+#[derive(Iden)]
+enum Categories {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Rules {
+    Table,
+    Id,
+    Name,
+    Priority,
+    Enabled,
+    RecipientIban,
+    RecipientName,
+    NamePattern,
+    TextContains,
+    MinAmount,
+    MaxAmount,
+    SetCategoryId,
+    SetRecipientName,
+    SetRecipientIban,
+    AutoAccept,
+}
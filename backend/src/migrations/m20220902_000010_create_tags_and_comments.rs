@@ -0,0 +1,134 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tags::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Tags::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Tags::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReceiptTags::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ReceiptTags::ReceiptId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ReceiptTags::TagId).uuid().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(ReceiptTags::ReceiptId)
+                            .col(ReceiptTags::TagId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-receipt_tags-receipt_id")
+                            .from(ReceiptTags::Table, ReceiptTags::ReceiptId)
+                            .to(Receipts::Table, Receipts::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-receipt_tags-tag_id")
+                            .from(ReceiptTags::Table, ReceiptTags::TagId)
+                            .to(Tags::Table, Tags::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Comments::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Comments::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Comments::ReceiptId).uuid().not_null())
+                    .col(ColumnDef::new(Comments::Author).string().not_null())
+                    .col(ColumnDef::new(Comments::Body).text().not_null())
+                    .col(
+                        ColumnDef::new(Comments::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-comments-receipt_id")
+                            .from(Comments::Table, Comments::ReceiptId)
+                            .to(Receipts::Table, Receipts::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Comments::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ReceiptTags::Table).to_owned())
+            .await?;
+        manager.drop_table(Table::drop().table(Tags::Table).to_owned()).await
+    }
+}
+
+#[derive(Iden)]
+enum Receipts {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Tags {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(Iden)]
+enum ReceiptTags {
+    Table,
+    ReceiptId,
+    TagId,
+}
+
+#[derive(Iden)]
+enum Comments {
+    Table,
+    Id,
+    ReceiptId,
+    Author,
+    Body,
+    CreatedAt,
+}
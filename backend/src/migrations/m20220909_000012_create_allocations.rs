@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Allocations::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Allocations::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Allocations::ReceiptId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Allocations::Party).string().not_null())
+                    .col(
+                        ColumnDef::new(Allocations::Amount)
+                            .big_integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(Allocations::BasisPoints)
+                            .integer()
+                            .null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-allocations-receipt_id")
+                            .from(Allocations::Table, Allocations::ReceiptId)
+                            .to(Receipts::Table, Receipts::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Receipts::Table)
+                    .add_column(
+                        ColumnDef::new(Receipts::PaidBy).string().null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        super::drop_column(manager, Receipts::Table, Receipts::PaidBy).await?;
+        manager
+            .drop_table(Table::drop().table(Allocations::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Receipts {
+    Table,
+    Id,
+    PaidBy,
+}
+
+#[derive(Iden)]
+enum Allocations {
+    Table,
+    Id,
+    ReceiptId,
+    Party,
+    Amount,
+    BasisPoints,
+}
@@ -1,12 +1,193 @@
+use entity::receipt::ReceiptState;
+use sea_orm::Iterable;
+use sea_orm::{ConnectionTrait, DbBackend, Statement};
 pub use sea_orm_migration::prelude::*;
 
 mod m20220717_000001_create_receipts_tables;
+mod m20220801_000002_add_receipt_metadata;
+mod m20220805_000003_create_recipients_table;
+mod m20220810_000004_store_receipt_state_as_string;
+mod m20220818_000005_add_receipt_due_dates;
+mod m20220822_000006_add_receipt_amount;
+mod m20220822_000007_create_recurring_bills;
+mod m20220826_000008_create_categories;
+mod m20220830_000009_create_rules;
+mod m20220902_000010_create_tags_and_comments;
+mod m20220906_000011_add_receipt_version;
+mod m20220909_000012_create_allocations;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220717_000001_create_receipts_tables::Migration)]
+        vec![
+            Box::new(m20220717_000001_create_receipts_tables::Migration),
+            Box::new(m20220801_000002_add_receipt_metadata::Migration),
+            Box::new(m20220805_000003_create_recipients_table::Migration),
+            Box::new(m20220810_000004_store_receipt_state_as_string::Migration),
+            Box::new(m20220818_000005_add_receipt_due_dates::Migration),
+            Box::new(m20220822_000006_add_receipt_amount::Migration),
+            Box::new(m20220822_000007_create_recurring_bills::Migration),
+            Box::new(m20220826_000008_create_categories::Migration),
+            Box::new(m20220830_000009_create_rules::Migration),
+            Box::new(m20220902_000010_create_tags_and_comments::Migration),
+            Box::new(m20220906_000011_add_receipt_version::Migration),
+            Box::new(m20220909_000012_create_allocations::Migration),
+        ]
+    }
+}
+
+/// The Postgres `receipt_state` enum type. Its values are named by
+/// [`ReceiptState::as_str`], like the state in urls and forms.
+pub(crate) enum ReceiptStateType {
+    Type,
+    Value(ReceiptState),
+}
+
+impl ReceiptStateType {
+    pub(crate) fn values() -> Vec<ReceiptStateType> {
+        ReceiptState::iter().map(ReceiptStateType::Value).collect()
+    }
+}
+
+impl Iden for ReceiptStateType {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                ReceiptStateType::Type => "receipt_state",
+                ReceiptStateType::Value(state) => state.as_str(),
+            }
+        )
+        .unwrap();
+    }
+}
+
+/// Drops `column` from `table`. sea-query cannot drop columns on SQLite,
+/// which supports `DROP COLUMN` since 3.35, so the statement is written by
+/// hand there.
+pub(crate) async fn drop_column<T, C>(
+    manager: &SchemaManager<'_>,
+    table: T,
+    column: C,
+) -> Result<(), DbErr>
+where
+    T: Iden + 'static,
+    C: Iden + 'static,
+{
+    if manager.get_database_backend() == DbBackend::Sqlite {
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                DbBackend::Sqlite,
+                format!(
+                    r#"ALTER TABLE "{}" DROP COLUMN "{}""#,
+                    table.to_string(),
+                    column.to_string()
+                ),
+            ))
+            .await?;
+        return Ok(());
+    }
+    manager
+        .alter_table(Table::alter().table(table).drop_column(column).to_owned())
+        .await
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use entity::{category, receipt};
+    use sea_orm::{
+        ActiveModelTrait, ColumnTrait, ConnectOptions, Database,
+        DatabaseConnection, EntityTrait, QueryFilter, Set,
+    };
+
+    async fn memory_db() -> DatabaseConnection {
+        // every connection to sqlite::memory: opens its own database
+        let mut options = ConnectOptions::new("sqlite::memory:".to_owned());
+        options.max_connections(1);
+        Database::connect(options).await.expect("in-memory sqlite")
+    }
+
+    #[rocket::async_test]
+    async fn migrations_run_on_sqlite() {
+        let db = memory_db().await;
+        Migrator::up(&db, None).await.expect("migrations up");
+
+        receipt::ActiveModel {
+            id: Set(uuid::Uuid::new_v4()),
+            name: Set("power bill".to_owned()),
+            state: Set(ReceiptState::Payed),
+            file_hash: Set("abc".to_owned()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .expect("insert receipt");
+
+        let payed = receipt::Entity::find()
+            .filter(receipt::Column::State.eq(ReceiptState::Payed))
+            .all(&db)
+            .await
+            .unwrap();
+        assert_eq!(payed.len(), 1);
+        assert_eq!(payed[0].state, ReceiptState::Payed);
+
+        Migrator::down(&db, None).await.expect("migrations down");
+        assert!(Migrator::get_applied_migrations(&db)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[rocket::async_test]
+    async fn category_names_become_categories() {
+        let db = memory_db().await;
+        let before_categories = 7;
+        Migrator::up(&db, Some(before_categories)).await.unwrap();
+
+        let id = uuid::Uuid::new_v4();
+        let insert = Query::insert()
+            .into_table(Alias::new("receipts"))
+            .columns(
+                ["id", "name", "state", "file_hash", "category"]
+                    .map(Alias::new),
+            )
+            .values_panic([
+                id.into(),
+                "lunch".into(),
+                "inbox".into(),
+                "abc".into(),
+                "Food".into(),
+            ])
+            .to_owned();
+        db.execute(DbBackend::Sqlite.build(&insert)).await.unwrap();
+
+        Migrator::up(&db, None).await.expect("migrations up");
+        let (receipt, category) = receipt::Entity::find_by_id(id)
+            .find_also_related(category::Entity)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(category.map(|category| category.name), Some("Food".into()));
+        assert!(receipt.category_id.is_some());
+
+        let after_categories =
+            Migrator::migrations().len() as u32 - before_categories;
+        Migrator::down(&db, Some(after_categories))
+            .await
+            .expect("migrations down");
+        let names = db
+            .query_all(Statement::from_string(
+                DbBackend::Sqlite,
+                r#"SELECT "category" FROM "receipts""#.to_owned(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(names[0].try_get::<String>("", "category").unwrap(), "Food");
     }
 }
@@ -0,0 +1,139 @@
+use super::Event;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rocket::serde::Deserialize;
+
+/// Mail server notifications are sent through. Point it to a local sink like
+/// mailpit (`host = "localhost"`, `port = 1025`) while developing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sender address, e.g. `expensebills <bills@example.com>`.
+    pub from: String,
+    /// Connect with TLS. Only local sinks should be used without it.
+    #[serde(default)]
+    pub tls: bool,
+}
+
+fn default_port() -> u16 {
+    25
+}
+
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(config: &SmtpConfig) -> anyhow::Result<Self> {
+        let builder = if config.tls {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &config.host,
+            )
+        };
+        let mut builder = builder.port(config.port);
+        if let (Some(username), Some(password)) =
+            (&config.username, &config.password)
+        {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.clone(),
+            ));
+        }
+
+        Ok(Mailer {
+            transport: builder.build(),
+            from: config.from.parse()?,
+        })
+    }
+
+    pub async fn send(&self, to: &str, event: &Event) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(event.subject())
+            .body(event.to_string())?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::receipt::Model as Receipt;
+    use rocket::tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use rocket::tokio::net::TcpListener;
+
+    /// Accepts a single mail on `listener` and returns its data.
+    async fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut data = String::new();
+        let mut in_data = false;
+
+        write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    write.write_all(b"250 queued\r\n").await.unwrap();
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+            let reply: &[u8] = match &line.to_uppercase()[..4] {
+                "EHLO" => b"250 sink\r\n",
+                "DATA" => {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                },
+                "QUIT" => {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                },
+                _ => b"250 ok\r\n",
+            };
+            write.write_all(reply).await.unwrap();
+        }
+        data
+    }
+
+    #[rocket::async_test]
+    async fn sends_event_to_smtp_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = rocket::tokio::spawn(smtp_sink(listener));
+
+        let mailer = Mailer::new(&SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+            from: "expensebills <bills@example.com>".to_string(),
+            tls: false,
+        })
+        .unwrap();
+        let event = Event::ReceiptCreated {
+            receipt: Receipt::new(uuid::Uuid::new_v4(), "power.pdf", ""),
+        };
+        mailer.send("alice@example.com", &event).await.unwrap();
+        drop(mailer);
+
+        let data = sink.await.unwrap();
+        assert!(data.contains("To: alice@example.com"));
+        assert!(data.contains("Subject: New receipt power.pdf"));
+        assert!(data.contains("Receipt power.pdf arrived in the Inbox."));
+    }
+}
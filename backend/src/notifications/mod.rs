@@ -0,0 +1,309 @@
+use crate::reminders::Reminder;
+use entity::receipt::{Model as Receipt, ReceiptState};
+use log::{error, warn};
+use rocket::fairing::AdHoc;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::broadcast;
+use rocket::tokio::time;
+use std::time::Duration;
+
+mod email;
+mod webhook;
+
+use email::{Mailer, SmtpConfig};
+use webhook::WebhookConfig;
+
+/// Number of events kept for slow subscribers before they start to miss
+/// some.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Longest a single mail or webhook delivery may take, as events are
+/// delivered one after another and a receiver that never answers would hold
+/// up all later ones.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest connecting to a webhook may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Something that happened to a receipt.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde", tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ReceiptCreated {
+        receipt: Receipt,
+    },
+    /// Fields of the receipt were edited.
+    ReceiptUpdated {
+        receipt: Receipt,
+    },
+    StateChanged {
+        receipt: Receipt,
+        from: ReceiptState,
+        to: ReceiptState,
+    },
+    Reminder {
+        receipt: Receipt,
+        reminder: Reminder,
+    },
+}
+
+/// Kinds of events users can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum EventKind {
+    ReceiptCreated,
+    ReceiptUpdated,
+    StateChanged,
+    Reminder,
+}
+
+impl EventKind {
+    /// Name of the kind in the configuration and in event streams.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::ReceiptCreated => "receipt_created",
+            EventKind::ReceiptUpdated => "receipt_updated",
+            EventKind::StateChanged => "state_changed",
+            EventKind::Reminder => "reminder",
+        }
+    }
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::ReceiptCreated {
+                ..
+            } => EventKind::ReceiptCreated,
+            Event::ReceiptUpdated {
+                ..
+            } => EventKind::ReceiptUpdated,
+            Event::StateChanged {
+                ..
+            } => EventKind::StateChanged,
+            Event::Reminder {
+                ..
+            } => EventKind::Reminder,
+        }
+    }
+
+    pub fn receipt(&self) -> &Receipt {
+        match self {
+            Event::ReceiptCreated {
+                receipt,
+            }
+            | Event::ReceiptUpdated {
+                receipt,
+            }
+            | Event::StateChanged {
+                receipt,
+                ..
+            }
+            | Event::Reminder {
+                receipt,
+                ..
+            } => receipt,
+        }
+    }
+
+    /// Short summary, e.g. for the subject of a mail.
+    pub fn subject(&self) -> String {
+        let name = &self.receipt().name;
+        match self {
+            Event::ReceiptCreated {
+                ..
+            } => format!("New receipt {}", name),
+            Event::ReceiptUpdated {
+                ..
+            } => format!("Receipt {} was edited", name),
+            Event::StateChanged {
+                to,
+                ..
+            } => format!("Receipt {} is {}", name, to),
+            Event::Reminder {
+                ..
+            } => format!("Reminder for receipt {}", name),
+        }
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let receipt = self.receipt();
+        match self {
+            Event::ReceiptCreated {
+                ..
+            } => write!(f, "Receipt {} arrived in the Inbox.", receipt.name)?,
+            Event::ReceiptUpdated {
+                ..
+            } => write!(f, "Receipt {} was edited.", receipt.name)?,
+            Event::StateChanged {
+                from,
+                to,
+                ..
+            } => write!(
+                f,
+                "Receipt {} moved from {} to {}.",
+                receipt.name, from, to
+            )?,
+            Event::Reminder {
+                reminder,
+                ..
+            } => write!(f, "Receipt {} is {}.", receipt.name, reminder)?,
+        }
+        write!(f, "\n\nid: {}", receipt.id)
+    }
+}
+
+/// Fans receipt events out to everyone interested in them. Cheap to clone,
+/// every clone emits into the same channel.
+#[derive(Clone)]
+pub struct Notifier {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Notifier {
+            sender,
+        }
+    }
+
+    /// Publishes `event`. Events nobody subscribed to are dropped.
+    pub fn emit(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Notifier::new()
+    }
+}
+
+/// Configuration of the notification channels, read from the
+/// `notifications` table of the Rocket figment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NotificationConfig {
+    /// Mail server used for the `email` of every user.
+    pub smtp: Option<SmtpConfig>,
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+}
+
+/// Where and about what a single user wants to be notified.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UserConfig {
+    pub name: String,
+    pub email: Option<String>,
+    pub webhook: Option<WebhookConfig>,
+    /// Events the user is notified about, all if not given.
+    #[serde(default = "all_events")]
+    pub events: Vec<EventKind>,
+}
+
+fn all_events() -> Vec<EventKind> {
+    vec![
+        EventKind::ReceiptCreated,
+        EventKind::ReceiptUpdated,
+        EventKind::StateChanged,
+        EventKind::Reminder,
+    ]
+}
+
+async fn dispatch(
+    event: &Event,
+    users: &[UserConfig],
+    mailer: Option<&Mailer>,
+    client: &reqwest::Client,
+) {
+    for user in users.iter().filter(|u| u.events.contains(&event.kind())) {
+        if let Some(address) = &user.email {
+            match mailer {
+                Some(mailer) => {
+                    let sent = time::timeout(
+                        DELIVERY_TIMEOUT,
+                        mailer.send(address, event),
+                    );
+                    match sent.await {
+                        Ok(Ok(())) => {},
+                        Ok(Err(err)) => {
+                            error!("could not mail {}: {}", user.name, err)
+                        },
+                        Err(_) => error!("mailing {} timed out", user.name),
+                    }
+                },
+                None => warn!("no smtp configured to mail {}", user.name),
+            }
+        }
+        if let Some(hook) = &user.webhook {
+            if let Err(err) = webhook::deliver(client, hook, event).await {
+                error!("webhook of {} failed: {}", user.name, err);
+            }
+        }
+    }
+}
+
+/// Starts delivering events to the configured users on liftoff if there is
+/// a `notifications` config. Unusable smtp settings stop the launch, like a
+/// malformed config table.
+pub fn fairing(config: Option<NotificationConfig>) -> AdHoc {
+    let mailer =
+        config.as_ref().and_then(|config| config.smtp.as_ref()).map(|smtp| {
+            Mailer::new(smtp)
+                .unwrap_or_else(|err| panic!("invalid smtp config: {}", err))
+        });
+    AdHoc::on_liftoff("Notifications", |rocket| {
+        Box::pin(async move {
+            let config = match config {
+                Some(config) => config,
+                None => return,
+            };
+            let mut events = match rocket.state::<Notifier>() {
+                Some(notifier) => notifier.subscribe(),
+                None => {
+                    error!("notifications need a managed Notifier");
+                    return;
+                },
+            };
+            let client = match reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+            {
+                Ok(client) => client,
+                Err(err) => {
+                    error!("could not set up webhooks: {}", err);
+                    return;
+                },
+            };
+
+            rocket::tokio::spawn(async move {
+                loop {
+                    match events.recv().await {
+                        Ok(event) => {
+                            dispatch(
+                                &event,
+                                &config.users,
+                                mailer.as_ref(),
+                                &client,
+                            )
+                            .await
+                        },
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            warn!("dropped {} notifications", missed)
+                        },
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+        })
+    })
+}
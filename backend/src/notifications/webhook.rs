@@ -0,0 +1,56 @@
+use super::Event;
+use hmac::{Hmac, Mac};
+use rocket::serde::Deserialize;
+use sha2::Sha256;
+
+/// Header carrying the hex encoded HMAC-SHA256 of the request body, keyed
+/// with the webhook secret, as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Expensebills-Signature";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Shared secret the receiver checks the signature with.
+    pub secret: String,
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("hmac takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Posts `event` as JSON to the webhook.
+pub async fn deliver(
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    event: &Event,
+) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(event)?;
+    client
+        .post(&config.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, sign(&config.secret, &body))
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_is_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c7\
+             5a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
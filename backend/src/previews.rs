@@ -0,0 +1,121 @@
+//! Small JPEG previews of receipt files for list views.
+
+use anyhow::bail;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat};
+use rocket::serde::Deserialize;
+use std::process::Command;
+use std::time::Duration;
+
+/// How long rendering a PDF may take before the renderer is killed.
+const RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How previews are rendered, read from the `previews` table of the Rocket
+/// figment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PreviewConfig {
+    /// Longest side of a preview in pixels.
+    #[serde(default = "default_size")]
+    pub size: u32,
+    /// Poppler's `pdftoppm`, which renders the first page of PDFs.
+    #[serde(default = "default_pdf_renderer")]
+    pub pdf_renderer: String,
+}
+
+fn default_size() -> u32 {
+    256
+}
+
+fn default_pdf_renderer() -> String {
+    "pdftoppm".to_string()
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig {
+            size: default_size(),
+            pdf_renderer: default_pdf_renderer(),
+        }
+    }
+}
+
+fn thumbnail(image: DynamicImage, size: u32) -> anyhow::Result<Vec<u8>> {
+    let thumbnail = image.thumbnail(size, size).to_rgb8();
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, 80).encode_image(&thumbnail)?;
+    Ok(jpeg)
+}
+
+fn render_pdf(
+    config: &PreviewConfig,
+    content: &[u8],
+) -> anyhow::Result<DynamicImage> {
+    let output = crate::process::run(
+        Command::new(&config.pdf_renderer)
+            .args(["-f", "1", "-l", "1", "-singlefile", "-png", "-scale-to"])
+            .arg(config.size.to_string())
+            .arg("-"),
+        content.to_vec(),
+        RENDER_TIMEOUT,
+    )?;
+    if !output.status.success() {
+        bail!("{} failed with {}", config.pdf_renderer, output.status);
+    }
+    Ok(image::load_from_memory_with_format(&output.stdout, ImageFormat::Png)?)
+}
+
+/// A preview of the first page of a PDF or of an image. `None` for other
+/// files and images that cannot be decoded.
+pub fn render(
+    config: &PreviewConfig,
+    content: &[u8],
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let image = if content.starts_with(b"%PDF") {
+        render_pdf(config, content)?
+    } else {
+        match image::load_from_memory(content) {
+            Ok(image) => image,
+            Err(_) => return Ok(None),
+        }
+    };
+    Ok(Some(thumbnail(image, config.size)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, ImageOutputFormat, RgbImage};
+    use std::io::Cursor;
+
+    #[test]
+    fn images_are_scaled_to_fit() {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::new(1000, 500))
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+
+        let preview = render(&PreviewConfig::default(), png.get_ref())
+            .unwrap()
+            .expect("preview of png");
+        let preview =
+            image::load_from_memory_with_format(&preview, ImageFormat::Jpeg)
+                .unwrap();
+        assert_eq!(preview.dimensions(), (256, 128));
+    }
+
+    #[test]
+    fn other_files_have_no_preview() {
+        let config = PreviewConfig::default();
+        assert_eq!(render(&config, b"plain text").unwrap(), None);
+    }
+
+    #[test]
+    fn missing_pdf_renderer_is_an_error() {
+        let config = PreviewConfig {
+            pdf_renderer: "/nonexistent/pdftoppm".to_string(),
+            ..Default::default()
+        };
+        assert!(render(&config, b"%PDF-1.4").is_err());
+    }
+}
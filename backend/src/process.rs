@@ -0,0 +1,89 @@
+//! Runs external programs, like PDF renderers and malware scanners, on file
+//! contents without letting a hanging one block a thread for good.
+
+use anyhow::{anyhow, bail, Context};
+use std::io::{Read, Write};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running program is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Runs `command` with `input` on stdin and returns its status and stdout.
+/// The program is killed if it still runs after `timeout`. Blocks, so call
+/// it from `spawn_blocking`.
+pub fn run(
+    command: &mut Command,
+    input: Vec<u8>,
+    timeout: Duration,
+) -> anyhow::Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("running {}", program))?;
+    // both pipes are served from other threads, as programs may stop
+    // reading once they know their answer or write before reading it all
+    let mut stdin = child.stdin.take().expect("piped stdin");
+    thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let mut stdout = child.stdout.take().expect("piped stdout");
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("{} timed out after {:?}", program, timeout);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let stdout = reader
+        .join()
+        .map_err(|_| anyhow!("reading the output of {} failed", program))??;
+    Ok(Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_of_the_program_is_returned() {
+        let output = run(
+            Command::new("sh").args(["-c", "tr a-z A-Z"]),
+            b"rent".to_vec(),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"RENT");
+    }
+
+    #[test]
+    fn hanging_programs_are_killed() {
+        let started = Instant::now();
+        let result = run(
+            Command::new("sleep").arg("10"),
+            Vec::new(),
+            Duration::from_millis(200),
+        );
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
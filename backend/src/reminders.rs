@@ -0,0 +1,236 @@
+use crate::notifications::{Event, Notifier};
+use crate::SQLDb;
+use chrono::{Duration as DateDuration, Local, NaiveDate};
+use entity::receipt::{self, Model as Receipt, ReceiptState};
+use log::{error, info};
+use rocket::fairing::AdHoc;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::time;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, Iterable, QueryFilter,
+};
+use sea_orm_rocket::Database;
+use std::time::Duration;
+
+/// Configuration of the reminder job, read from the `reminders` table of the
+/// Rocket figment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReminderConfig {
+    /// Days before a due date or discount deadline to remind about it.
+    #[serde(default = "default_window")]
+    pub window: i64,
+    /// Seconds between two checks for receipts to remind about.
+    #[serde(default = "default_check_interval")]
+    pub check_interval: u64,
+}
+
+fn default_window() -> i64 {
+    3
+}
+
+fn default_check_interval() -> u64 {
+    3600
+}
+
+/// What a reminder is about.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(
+    crate = "rocket::serde",
+    tag = "kind",
+    content = "date",
+    rename_all = "snake_case"
+)]
+pub enum Reminder {
+    Discount(NaiveDate),
+    Due(NaiveDate),
+    Overdue(NaiveDate),
+}
+
+impl std::fmt::Display for Reminder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reminder::Discount(date) => {
+                write!(f, "discount can be taken until {}", date)
+            },
+            Reminder::Due(date) => write!(f, "due on {}", date),
+            Reminder::Overdue(date) => write!(f, "overdue since {}", date),
+        }
+    }
+}
+
+/// Returns the reminder to send about `receipt` today, if any.
+///
+/// Every deadline is reminded about once when it enters the window and the
+/// due date once more after it passed. `reminded_on` records the last
+/// reminder, so a deadline whose window started after it is still pending.
+pub fn pending_reminder(
+    receipt: &Receipt,
+    today: NaiveDate,
+    window: i64,
+) -> Option<Reminder> {
+    if !receipt.state.is_open() {
+        return None;
+    }
+    let reminded_before = |date: NaiveDate| {
+        receipt.reminded_on.map_or(true, |reminded| reminded < date)
+    };
+
+    if let Some(due) = receipt.due_date {
+        if due < today {
+            return reminded_before(due + DateDuration::days(1))
+                .then(|| Reminder::Overdue(due));
+        }
+    }
+
+    let in_window = |date: NaiveDate| {
+        let starts = date - DateDuration::days(window);
+        date >= today && starts <= today && reminded_before(starts)
+    };
+    let discount = receipt.discount_deadline.filter(|d| in_window(*d));
+    let due = receipt.due_date.filter(|d| in_window(*d));
+    discount.map(Reminder::Discount).or_else(|| due.map(Reminder::Due))
+}
+
+/// Sends all pending reminders and records them on the receipts.
+async fn send_reminders(
+    sql_db: &DatabaseConnection,
+    notifier: &Notifier,
+    window: i64,
+) -> Result<(), DbErr> {
+    let today = Local::today().naive_local();
+    let receipts = receipt::Entity::find()
+        .filter(
+            receipt::Column::State
+                .is_in(ReceiptState::iter().filter(ReceiptState::is_open)),
+        )
+        .filter(
+            receipt::Column::DueDate
+                .is_not_null()
+                .or(receipt::Column::DiscountDeadline.is_not_null()),
+        )
+        .all(sql_db)
+        .await?;
+
+    for model in receipts {
+        if let Some(reminder) = pending_reminder(&model, today, window) {
+            info!("reminder: receipt {} is {}", model.name, reminder);
+            // not an edit, so the version stays
+            receipt::Entity::update_many()
+                .col_expr(receipt::Column::RemindedOn, Expr::value(today))
+                .filter(receipt::Column::Id.eq(model.id))
+                .exec(sql_db)
+                .await?;
+            let receipt = Receipt {
+                reminded_on: Some(today),
+                ..model
+            };
+            notifier.emit(Event::Reminder {
+                receipt,
+                reminder,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Starts the reminder job on liftoff if there is a `reminders` config.
+pub fn fairing(config: Option<ReminderConfig>) -> AdHoc {
+    AdHoc::on_liftoff("Due date reminders", |rocket| {
+        Box::pin(async move {
+            let config = match config {
+                Some(config) => config,
+                None => return,
+            };
+            let sql_db = match SQLDb::fetch(rocket) {
+                Some(db) => db.conn.clone(),
+                None => {
+                    error!("reminders need the database to be attached");
+                    return;
+                },
+            };
+            let notifier = match rocket.state::<Notifier>() {
+                Some(notifier) => notifier.clone(),
+                None => {
+                    error!("reminders need a managed Notifier");
+                    return;
+                },
+            };
+
+            rocket::tokio::spawn(async move {
+                let mut interval =
+                    time::interval(Duration::from_secs(config.check_interval));
+                loop {
+                    interval.tick().await;
+                    if let Err(err) =
+                        send_reminders(&sql_db, &notifier, config.window).await
+                    {
+                        error!("could not send reminders: {}", err);
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2022, 8, day)
+    }
+
+    fn receipt(
+        due_date: Option<NaiveDate>,
+        discount_deadline: Option<NaiveDate>,
+        reminded_on: Option<NaiveDate>,
+    ) -> Receipt {
+        Receipt {
+            state: ReceiptState::Valid,
+            due_date,
+            discount_deadline,
+            reminded_on,
+            ..Receipt::new(uuid::Uuid::new_v4(), "rent", "")
+        }
+    }
+
+    #[test]
+    fn reminds_once_per_deadline() {
+        let bill = receipt(Some(date(20)), Some(date(10)), None);
+        assert_eq!(pending_reminder(&bill, date(6), 3), None);
+        assert_eq!(
+            pending_reminder(&bill, date(7), 3),
+            Some(Reminder::Discount(date(10)))
+        );
+
+        let bill = receipt(Some(date(20)), Some(date(10)), Some(date(7)));
+        assert_eq!(pending_reminder(&bill, date(9), 3), None);
+        assert_eq!(
+            pending_reminder(&bill, date(17), 3),
+            Some(Reminder::Due(date(20)))
+        );
+
+        let bill = receipt(Some(date(20)), Some(date(10)), Some(date(17)));
+        assert_eq!(pending_reminder(&bill, date(20), 3), None);
+        assert_eq!(
+            pending_reminder(&bill, date(21), 3),
+            Some(Reminder::Overdue(date(20)))
+        );
+
+        let bill = receipt(Some(date(20)), Some(date(10)), Some(date(21)));
+        assert_eq!(pending_reminder(&bill, date(25), 3), None);
+    }
+
+    #[test]
+    fn closed_receipts_are_not_reminded() {
+        let mut bill = receipt(Some(date(20)), None, None);
+        bill.state = ReceiptState::Payed;
+        assert_eq!(pending_reminder(&bill, date(19), 3), None);
+        assert!(!bill.is_overdue(date(25)));
+
+        bill.state = ReceiptState::Inbox;
+        assert!(bill.is_overdue(date(25)));
+    }
+}
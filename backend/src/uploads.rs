@@ -0,0 +1,339 @@
+//! Checks files have to pass before they are stored as receipts: their
+//! size, their type told by magic bytes and an optional malware scanner.
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use rocket::data::{ByteUnit, ToByteUnit};
+use rocket::serde::Deserialize;
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::TcpStream;
+use rocket::tokio::time;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Longest a scanner may take for one file before the file is rejected.
+const SCAN_TIMEOUT: Duration = Duration::from_secs(60);
+/// clamd rejects chunks above its `StreamMaxLength`, which is 25 MiB by
+/// default, so files are streamed in much smaller ones.
+const CLAMD_CHUNK: usize = 64 * 1024;
+
+/// Types of files receipts can be made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum FileType {
+    Pdf,
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Tiff,
+    /// UTF-8 without NUL bytes, e.g. a bill sent as plain mail.
+    Text,
+}
+
+impl FileType {
+    /// Tells the type by the magic bytes at the start of `content`.
+    pub fn detect(content: &[u8]) -> Option<Self> {
+        let head = &content[..content.len().min(1024)];
+        // readers accept junk before the header within the first KiB
+        if head.windows(5).any(|window| window == b"%PDF-") {
+            Some(FileType::Pdf)
+        } else if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(FileType::Png)
+        } else if content.starts_with(b"\xff\xd8\xff") {
+            Some(FileType::Jpeg)
+        } else if content.starts_with(b"GIF87a")
+            || content.starts_with(b"GIF89a")
+        {
+            Some(FileType::Gif)
+        } else if content.starts_with(b"RIFF")
+            && content.get(8..12) == Some(&b"WEBP"[..])
+        {
+            Some(FileType::Webp)
+        } else if content.starts_with(b"II*\0") || content.starts_with(b"MM\0*")
+        {
+            Some(FileType::Tiff)
+        } else if !content.is_empty()
+            && !content.contains(&0)
+            && std::str::from_utf8(content).is_ok()
+        {
+            Some(FileType::Text)
+        } else {
+            None
+        }
+    }
+}
+
+/// Limits of uploaded and ingested files, read from the `uploads` table of
+/// the Rocket figment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UploadConfig {
+    /// Largest file accepted. Uploads are capped at Rocket's `limits.file`
+    /// before, so that should not be smaller.
+    #[serde(default = "default_max_size")]
+    pub max_size: ByteUnit,
+    #[serde(default = "default_allowed_types")]
+    pub allowed_types: Vec<FileType>,
+    #[serde(default)]
+    pub scanner: Option<ScannerConfig>,
+}
+
+fn default_max_size() -> ByteUnit {
+    50.mebibytes()
+}
+
+fn default_allowed_types() -> Vec<FileType> {
+    vec![
+        FileType::Pdf,
+        FileType::Png,
+        FileType::Jpeg,
+        FileType::Gif,
+        FileType::Webp,
+        FileType::Tiff,
+        FileType::Text,
+    ]
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            max_size: default_max_size(),
+            allowed_types: default_allowed_types(),
+            scanner: None,
+        }
+    }
+}
+
+/// The malware scanner files are checked with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum ScannerConfig {
+    /// Address of a clamd listening on TCP, e.g. `127.0.0.1:3310`.
+    Clamd(String),
+    /// Program and arguments of a command that reads the file from stdin
+    /// and exits with 1 if it is infected, like `clamdscan -`.
+    Command(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Clean,
+    /// Name of what was found.
+    Infected(String),
+}
+
+#[async_trait]
+pub trait Scanner: Send + Sync {
+    async fn scan(&self, content: &[u8]) -> anyhow::Result<Verdict>;
+}
+
+/// Name of the signature in clamd's `stream: <name> FOUND`.
+fn found(reply: &str) -> Option<&str> {
+    reply.lines().find_map(|line| {
+        let line = line.trim_end_matches('\0').trim();
+        let line = line.strip_prefix("stream: ").unwrap_or(line);
+        line.strip_suffix(" FOUND")
+    })
+}
+
+/// Streams files to clamd with its `INSTREAM` command.
+pub struct Clamd {
+    address: String,
+}
+
+#[async_trait]
+impl Scanner for Clamd {
+    async fn scan(&self, content: &[u8]) -> anyhow::Result<Verdict> {
+        let mut stream = TcpStream::connect(&self.address).await?;
+        stream.write_all(b"zINSTREAM\0").await?;
+        for chunk in content.chunks(CLAMD_CHUNK) {
+            stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+            stream.write_all(chunk).await?;
+        }
+        stream.write_all(&[0; 4]).await?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+
+        let reply = String::from_utf8_lossy(&reply);
+        let reply = reply.trim_end_matches('\0').trim();
+        if let Some(name) = found(reply) {
+            Ok(Verdict::Infected(name.to_string()))
+        } else if reply.ends_with("OK") {
+            Ok(Verdict::Clean)
+        } else {
+            bail!("clamd answered {:?}", reply)
+        }
+    }
+}
+
+/// Runs a command with the file on stdin.
+pub struct CommandScanner {
+    command: Vec<String>,
+}
+
+fn run_scanner(
+    command: &[String],
+    content: Vec<u8>,
+) -> anyhow::Result<Verdict> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("the scanner command is empty"))?;
+    // killed here, as dropping the scan in `check` would leave it running
+    let output = crate::process::run(
+        std::process::Command::new(program).args(args),
+        content,
+        SCAN_TIMEOUT,
+    )?;
+    match output.status.code() {
+        Some(0) => Ok(Verdict::Clean),
+        Some(1) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let name = found(&stdout).unwrap_or("malware");
+            Ok(Verdict::Infected(name.to_string()))
+        },
+        _ => bail!("{} failed with {}", program, output.status),
+    }
+}
+
+#[async_trait]
+impl Scanner for CommandScanner {
+    async fn scan(&self, content: &[u8]) -> anyhow::Result<Verdict> {
+        let command = self.command.clone();
+        let content = content.to_vec();
+        rocket::tokio::task::spawn_blocking(move || {
+            run_scanner(&command, content)
+        })
+        .await?
+    }
+}
+
+/// Why a file is not stored.
+#[derive(Error, Debug)]
+pub enum Rejection {
+    #[error("file is too large")]
+    TooLarge,
+    #[error("file type is not allowed")]
+    UnsupportedType,
+    #[error("file is infected with {0}")]
+    Infected(String),
+    /// The scanner could not tell, so the file is rejected to be safe.
+    #[error("malware scan failed")]
+    ScanFailed(anyhow::Error),
+}
+
+/// The configured checks, managed by Rocket.
+#[derive(Clone)]
+pub struct UploadPolicy {
+    config: UploadConfig,
+    scanner: Option<Arc<dyn Scanner>>,
+}
+
+impl UploadPolicy {
+    pub fn new(config: UploadConfig) -> Self {
+        let scanner: Option<Arc<dyn Scanner>> = match &config.scanner {
+            Some(ScannerConfig::Clamd(address)) => Some(Arc::new(Clamd {
+                address: address.clone(),
+            })),
+            Some(ScannerConfig::Command(command)) => {
+                Some(Arc::new(CommandScanner {
+                    command: command.clone(),
+                }))
+            },
+            None => None,
+        };
+        UploadPolicy {
+            config,
+            scanner,
+        }
+    }
+
+    /// Largest file accepted in bytes.
+    pub fn max_size(&self) -> u64 {
+        self.config.max_size.as_u64()
+    }
+
+    /// Checks `content` against the configured limits and scans it.
+    pub async fn check(&self, content: &[u8]) -> Result<FileType, Rejection> {
+        if content.len() as u64 > self.max_size() {
+            return Err(Rejection::TooLarge);
+        }
+        let file_type = FileType::detect(content)
+            .filter(|file_type| self.config.allowed_types.contains(file_type))
+            .ok_or(Rejection::UnsupportedType)?;
+        if let Some(scanner) = &self.scanner {
+            let verdict = time::timeout(SCAN_TIMEOUT, scanner.scan(content))
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("scan timed out")))
+                .map_err(Rejection::ScanFailed)?;
+            if let Verdict::Infected(name) = verdict {
+                return Err(Rejection::Infected(name));
+            }
+        }
+        Ok(file_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn types_are_told_by_magic_bytes() {
+        assert_eq!(FileType::detect(b"%PDF-1.7\n"), Some(FileType::Pdf));
+        assert_eq!(FileType::detect(b"\r\n%PDF-1.4"), Some(FileType::Pdf));
+        assert_eq!(
+            FileType::detect(b"\x89PNG\r\n\x1a\n\0\0"),
+            Some(FileType::Png)
+        );
+        assert_eq!(FileType::detect(b"\xff\xd8\xff\xe0"), Some(FileType::Jpeg));
+        assert_eq!(
+            FileType::detect(b"RIFF\x10\0\0\0WEBPVP8 "),
+            Some(FileType::Webp)
+        );
+        assert_eq!(
+            FileType::detect("Miete März".as_bytes()),
+            Some(FileType::Text)
+        );
+        assert_eq!(FileType::detect(b"MZ\x90\0\x03\0"), None);
+        assert_eq!(FileType::detect(b""), None);
+    }
+
+    #[test]
+    fn clamd_replies_name_the_signature() {
+        assert_eq!(
+            found("stream: Eicar-Signature FOUND\0"),
+            Some("Eicar-Signature")
+        );
+        assert_eq!(found("stream: OK\0"), None);
+    }
+
+    #[rocket::async_test]
+    async fn command_exit_codes_are_verdicts() {
+        let scanner = |script: &str| CommandScanner {
+            command: vec!["sh".into(), "-c".into(), script.into()],
+        };
+        let clean = scanner("cat > /dev/null");
+        assert_eq!(clean.scan(b"rent").await.unwrap(), Verdict::Clean);
+        let infected = scanner("echo 'stream: Eicar FOUND'; exit 1");
+        assert_eq!(
+            infected.scan(b"rent").await.unwrap(),
+            Verdict::Infected("Eicar".to_string())
+        );
+        assert!(scanner("exit 2").scan(b"rent").await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn files_above_the_limit_are_rejected() {
+        let policy = UploadPolicy::new(UploadConfig {
+            max_size: 4.bytes(),
+            ..Default::default()
+        });
+        assert!(policy.check(b"rent").await.is_ok());
+        assert!(matches!(
+            policy.check(b"rent!").await,
+            Err(Rejection::TooLarge)
+        ));
+    }
+}
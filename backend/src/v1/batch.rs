@@ -0,0 +1,279 @@
+use super::receipts::{
+    create_inbox_receipt, read_upload, EndpointResult, ReceiptError,
+};
+use crate::files::FileStore;
+use crate::notifications::Notifier;
+use crate::uploads::{Rejection, UploadPolicy};
+use crate::{SQLDb, SledDB};
+use entity::receipt::{self, Model as Receipt};
+use log::error;
+use rocket::data::Capped;
+use rocket::form::{Form, Strict};
+use rocket::fs::TempFile;
+use rocket::serde::{json::Json, Serialize};
+use rocket::{Config, State};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sea_orm_rocket::Connection;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// More entries than this make a ZIP look like an accident, or an attack.
+const MAX_ZIP_ENTRIES: usize = 1000;
+/// Bytes the ZIPs of one request may unpack to, which bounds the work a ZIP
+/// bomb causes. Files past it are rejected.
+const MAX_UNZIPPED_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(FromForm)]
+pub struct BatchUploadRequest<'r> {
+    files: Vec<Capped<TempFile<'r>>>,
+}
+
+/// What became of one file of a batch.
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde", tag = "status", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    Created {
+        receipt: Receipt,
+    },
+    /// A receipt with the same content exists already.
+    Duplicate {
+        receipt_id: uuid::Uuid,
+    },
+    /// The file failed validation or could not be stored.
+    Rejected {
+        error: String,
+    },
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchItem {
+    /// Name of the file, for files of a ZIP without the folders.
+    pub name: String,
+    #[serde(flatten)]
+    pub outcome: BatchOutcome,
+}
+
+fn rejected(name: &str, error: impl ToString) -> BatchItem {
+    BatchItem {
+        name: name.to_string(),
+        outcome: BatchOutcome::Rejected {
+            error: error.to_string(),
+        },
+    }
+}
+
+type Zip = ZipArchive<Cursor<Vec<u8>>>;
+
+fn open_zip(content: Vec<u8>) -> anyhow::Result<Zip> {
+    let archive = ZipArchive::new(Cursor::new(content))?;
+    if archive.len() > MAX_ZIP_ENTRIES {
+        anyhow::bail!("ZIP has more than {} files", MAX_ZIP_ENTRIES);
+    }
+    Ok(archive)
+}
+
+/// The file at `index` of a ZIP archive, `None` for folders and what macOS
+/// adds. Files are read up to one byte above `max_size`, enough for the
+/// size check to reject them without unpacking ZIP bombs.
+fn unzip_file(
+    archive: &mut Zip,
+    index: usize,
+    max_size: u64,
+) -> anyhow::Result<Option<(String, Vec<u8>)>> {
+    let entry = archive.by_index(index)?;
+    let path = entry.name().to_string();
+    let name = path.rsplit('/').next().unwrap_or_default().to_string();
+    if entry.is_dir()
+        || name.is_empty()
+        || name.starts_with('.')
+        || path.starts_with("__MACOSX/")
+    {
+        return Ok(None);
+    }
+    let mut file = Vec::new();
+    entry.take(max_size + 1).read_to_end(&mut file)?;
+    Ok(Some((name, file)))
+}
+
+/// Creates a receipt of one file unless it fails the checks or exists.
+async fn ingest(
+    sql_db: &DatabaseConnection,
+    files_db: &FileStore,
+    notifier: &Notifier,
+    policy: &UploadPolicy,
+    name: &str,
+    content: Vec<u8>,
+) -> BatchItem {
+    if let Err(rejection) = policy.check(&content).await {
+        if let Rejection::ScanFailed(err) = &rejection {
+            error!("Malware scan of {} failed: {}", name, err);
+        }
+        return rejected(name, rejection);
+    }
+    let hash = sha256::digest_bytes(&content);
+    let existing = receipt::Entity::find()
+        .filter(receipt::Column::FileHash.eq(hash))
+        .one(sql_db)
+        .await;
+    let outcome = match existing {
+        Ok(Some(receipt)) => BatchOutcome::Duplicate {
+            receipt_id: receipt.id,
+        },
+        Ok(None) => {
+            match create_inbox_receipt(
+                sql_db, files_db, notifier, name, content, None,
+            )
+            .await
+            {
+                Ok(receipt) => BatchOutcome::Created {
+                    receipt,
+                },
+                Err(err) => return failed(name, err),
+            }
+        },
+        Err(err) => return failed(name, ReceiptError::from(err)),
+    };
+    BatchItem {
+        name: name.to_string(),
+        outcome,
+    }
+}
+
+fn failed(name: &str, err: ReceiptError) -> BatchItem {
+    error!("Could not store {}: {}", name, err);
+    rejected(name, "could not be stored")
+}
+
+/// Creates one Inbox receipt per file of a multipart request with any
+/// number of `files` fields. ZIP archives among them are unpacked and
+/// every file in them counts on its own. Answers with what became of every
+/// file in order, also when some of them failed.
+///
+/// Files of a ZIP are stored one by one as they are unpacked, so only one of
+/// them is in memory at a time.
+#[post("/upload/batch", data = "<upload>")]
+pub async fn upload_batch(
+    config: &State<Config>,
+    conn: Connection<'_, SQLDb>,
+    db: &State<SledDB>,
+    notifier: &State<Notifier>,
+    policy: &State<UploadPolicy>,
+    mut upload: Form<Strict<BatchUploadRequest<'_>>>,
+) -> EndpointResult<Json<Vec<BatchItem>>> {
+    let sql_db = conn.into_inner();
+    let mut items = Vec::new();
+    // bytes unpacked from all ZIPs of the request
+    let mut unzipped = 0;
+    for file in upload.files.iter_mut() {
+        // only shown as the receipt name, never used as a path
+        let name = file
+            .raw_name()
+            .map(|name| {
+                name.dangerous_unsafe_unsanitized_raw().as_str().to_string()
+            })
+            .unwrap_or_else(|| "upload".to_string());
+        if !file.is_complete() {
+            items.push(rejected(&name, Rejection::TooLarge));
+            continue;
+        }
+        let content = match read_upload(config, file).await {
+            Ok(content) => content,
+            Err(err) => {
+                error!("Could not read {}: {}", name, err);
+                items.push(rejected(&name, "could not be read"));
+                continue;
+            },
+        };
+        let files_db = &db.files_db;
+
+        if !content.starts_with(b"PK\x03\x04") {
+            items.push(
+                ingest(sql_db, files_db, notifier, policy, &name, content)
+                    .await,
+            );
+            continue;
+        }
+        let mut archive = match open_zip(content) {
+            Ok(archive) => archive,
+            Err(err) => {
+                items.push(rejected(&name, err));
+                continue;
+            },
+        };
+        let max_size = policy.max_size();
+        for index in 0..archive.len() {
+            if unzipped > MAX_UNZIPPED_BYTES {
+                let error = format!(
+                    "the ZIPs unpack to more than {} bytes",
+                    MAX_UNZIPPED_BYTES
+                );
+                items.push(rejected(&name, error));
+                break;
+            }
+            let (returned, unpacked) =
+                rocket::tokio::task::spawn_blocking(move || {
+                    let file = unzip_file(&mut archive, index, max_size);
+                    (archive, file)
+                })
+                .await
+                .map_err(anyhow::Error::from)?;
+            archive = returned;
+            match unpacked {
+                Ok(Some((name, content))) => {
+                    unzipped += content.len() as u64;
+                    items.push(
+                        ingest(
+                            sql_db, files_db, notifier, policy, &name, content,
+                        )
+                        .await,
+                    );
+                },
+                Ok(None) => {},
+                Err(err) => {
+                    items.push(rejected(&name, err));
+                    break;
+                },
+            }
+        }
+    }
+    Ok(Json(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn zips_are_unpacked_without_folders_and_metadata() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.add_directory("2022", FileOptions::default()).unwrap();
+        let entries: [(&str, &[u8]); 4] = [
+            ("2022/power.pdf", b"%PDF-1.4 power"),
+            ("__MACOSX/2022/._power.pdf", b"resource fork"),
+            ("2022/.DS_Store", b"finder"),
+            ("rent.txt", b"rent for august"),
+        ];
+        for (path, content) in entries {
+            zip.start_file(path, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        let zip = zip.finish().unwrap().into_inner();
+
+        let mut archive = open_zip(zip).unwrap();
+        let files: Vec<_> = (0..archive.len())
+            .filter_map(|index| unzip_file(&mut archive, index, 8).unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("power.pdf".to_string(), b"%PDF-1.4 ".to_vec()),
+                ("rent.txt".to_string(), b"rent for ".to_vec()),
+            ]
+        );
+        assert!(open_zip(b"PK\x03\x04 broken".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use super::receipts::{uuid_conversion, EndpointResult, ReceiptError};
+use super::MonthParam;
+use crate::SQLDb;
+use chrono::Local;
+use entity::category::{self, Model as Category};
+use entity::receipt::{self, ReceiptState};
+use entity::recurring_bill;
+use entity::rule;
+use rocket::serde::uuid::Uuid;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr,
+    EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Set,
+};
+use sea_orm_rocket::Connection;
+use std::collections::HashMap;
+
+/// Body of requests creating or replacing a category.
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct CategoryRequest {
+    pub name: String,
+    pub parent_id: Option<uuid::Uuid>,
+    pub monthly_budget: Option<i64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct BudgetReport {
+    pub category: Category,
+    pub budget: i64,
+    /// Spent in the category and all of its children, in cents.
+    pub spent: i64,
+    pub over_budget: bool,
+}
+
+/// Checks `request` against the stored categories. `id` is the category
+/// being replaced, if any, which must not become its own ancestor.
+async fn validate(
+    db: &DatabaseConnection,
+    request: &CategoryRequest,
+    id: Option<uuid::Uuid>,
+) -> EndpointResult<()> {
+    if request.name.trim().is_empty() {
+        return Err(ReceiptError::Invalid("name must not be empty".into()));
+    }
+    if request.monthly_budget.map_or(false, |budget| budget < 0) {
+        return Err(ReceiptError::Invalid("budget cannot be negative".into()));
+    }
+    let same_name = category::Entity::find()
+        .filter(category::Column::Name.eq(request.name.as_str()))
+        .one(db)
+        .await?;
+    if same_name.map_or(false, |other| Some(other.id) != id) {
+        return Err(ReceiptError::Conflict(format!(
+            "category {} already exists",
+            request.name
+        )));
+    }
+
+    let mut parent_id = request.parent_id;
+    while let Some(current) = parent_id {
+        if Some(current) == id {
+            return Err(ReceiptError::Invalid(
+                "a category cannot be its own ancestor".into(),
+            ));
+        }
+        parent_id = match category::Entity::find_by_id(current).one(db).await? {
+            Some(parent) => parent.parent_id,
+            None => {
+                return Err(ReceiptError::Invalid(format!(
+                    "parent category {} does not exist",
+                    current
+                )))
+            },
+        };
+    }
+    Ok(())
+}
+
+/// Sums up `spent` per category into the category itself and all of its
+/// ancestors.
+fn roll_up(
+    categories: &HashMap<uuid::Uuid, Category>,
+    spent: &HashMap<uuid::Uuid, i64>,
+) -> HashMap<uuid::Uuid, i64> {
+    let mut totals = HashMap::new();
+    for (&id, &amount) in spent {
+        let mut current = Some(id);
+        // the depth limit guards against cycles written around the API
+        for _ in 0..categories.len() {
+            let category = match current.and_then(|id| categories.get(&id)) {
+                Some(category) => category,
+                None => break,
+            };
+            *totals.entry(category.id).or_insert(0) += amount;
+            current = category.parent_id;
+        }
+    }
+    totals
+}
+
+#[get("/")]
+pub async fn get_categories(
+    conn: Connection<'_, SQLDb>,
+) -> EndpointResult<Json<Vec<Category>>> {
+    let sql_db = conn.into_inner();
+    let categories = category::Entity::find()
+        .order_by_asc(category::Column::Name)
+        .all(sql_db)
+        .await?;
+    Ok(Json(categories))
+}
+
+#[get("/<id>")]
+pub async fn get_category(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<Json<Category>> {
+    let sql_db = conn.into_inner();
+    category::Entity::find_by_id(uuid_conversion(id)?)
+        .one(sql_db)
+        .await?
+        .map(Json)
+        .ok_or(ReceiptError::NotFound)
+}
+
+#[post("/", data = "<request>")]
+pub async fn create_category(
+    conn: Connection<'_, SQLDb>,
+    request: Json<CategoryRequest>,
+) -> EndpointResult<Json<Category>> {
+    let sql_db = conn.into_inner();
+    validate(sql_db, &request, None).await?;
+    let request = request.into_inner();
+
+    let category = category::ActiveModel {
+        id: Set(uuid::Uuid::new_v4()),
+        name: Set(request.name),
+        parent_id: Set(request.parent_id),
+        monthly_budget: Set(request.monthly_budget),
+    }
+    .insert(sql_db)
+    .await?;
+    Ok(Json(category))
+}
+
+#[put("/<id>", data = "<request>")]
+pub async fn update_category(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+    request: Json<CategoryRequest>,
+) -> EndpointResult<Json<Category>> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+    if category::Entity::find_by_id(id).one(sql_db).await?.is_none() {
+        return Err(ReceiptError::NotFound);
+    }
+    validate(sql_db, &request, Some(id)).await?;
+    let request = request.into_inner();
+
+    let category = category::ActiveModel {
+        id: Set(id),
+        name: Set(request.name),
+        parent_id: Set(request.parent_id),
+        monthly_budget: Set(request.monthly_budget),
+    }
+    .update(sql_db)
+    .await?;
+    Ok(Json(category))
+}
+
+/// Deletes a category nothing refers to anymore.
+#[delete("/<id>")]
+pub async fn delete_category(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<()> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+
+    let children = category::Entity::find()
+        .filter(category::Column::ParentId.eq(id))
+        .count(sql_db)
+        .await?;
+    let receipts = receipt::Entity::find()
+        .filter(receipt::Column::CategoryId.eq(id))
+        .count(sql_db)
+        .await?;
+    let recurring_bills = recurring_bill::Entity::find()
+        .filter(recurring_bill::Column::CategoryId.eq(id))
+        .count(sql_db)
+        .await?;
+    let rules = rule::Entity::find()
+        .filter(rule::Column::SetCategoryId.eq(id))
+        .count(sql_db)
+        .await?;
+    if children + receipts + recurring_bills + rules > 0 {
+        return Err(ReceiptError::Conflict(format!(
+            "category is used by {} categories, {} receipts, {} recurring \
+             bills and {} rules",
+            children, receipts, recurring_bills, rules
+        )));
+    }
+
+    let result = category::Entity::delete_by_id(id).exec(sql_db).await?;
+    if result.rows_affected == 0 {
+        return Err(ReceiptError::NotFound);
+    }
+    Ok(())
+}
+
+async fn budget_reports(
+    db: &DatabaseConnection,
+    month: MonthParam,
+) -> Result<Vec<BudgetReport>, DbErr> {
+    let (first_day, last_day) = (month.first_day, month.last_day());
+    let categories: HashMap<_, _> = category::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|category| (category.id, category))
+        .collect();
+
+    // receipts count for the month they were payed in, or are due in if
+    // they are not payed yet
+    let receipts = receipt::Entity::find()
+        .filter(receipt::Column::CategoryId.is_not_null())
+        .filter(receipt::Column::Amount.is_not_null())
+        .filter(receipt::Column::State.ne(ReceiptState::Declined))
+        .filter(
+            Condition::any()
+                .add(receipt::Column::PaymentDate.between(first_day, last_day))
+                .add(
+                    Condition::all()
+                        .add(receipt::Column::PaymentDate.is_null())
+                        .add(
+                            receipt::Column::DueDate
+                                .between(first_day, last_day),
+                        ),
+                ),
+        )
+        .all(db)
+        .await?;
+    let mut spent = HashMap::new();
+    for receipt in receipts {
+        if let (Some(id), Some(amount)) = (receipt.category_id, receipt.amount)
+        {
+            *spent.entry(id).or_insert(0) += amount;
+        }
+    }
+    let totals = roll_up(&categories, &spent);
+
+    let mut reports: Vec<BudgetReport> = categories
+        .into_values()
+        .filter_map(|category| {
+            let budget = category.monthly_budget?;
+            let spent = totals.get(&category.id).copied().unwrap_or(0);
+            Some(BudgetReport {
+                category,
+                budget,
+                spent,
+                over_budget: spent > budget,
+            })
+        })
+        .collect();
+    reports.sort_by(|a, b| a.category.name.cmp(&b.category.name));
+    Ok(reports)
+}
+
+/// Compares the spending of `month`, the current one if not given, with the
+/// budget of every category that has one.
+#[get("/budgets?<month>")]
+pub async fn get_budgets(
+    conn: Connection<'_, SQLDb>,
+    month: Option<MonthParam>,
+) -> EndpointResult<Json<Vec<BudgetReport>>> {
+    let sql_db = conn.into_inner();
+    let month = month.unwrap_or_else(|| {
+        MonthParam::containing(Local::today().naive_local())
+    });
+    Ok(Json(budget_reports(sql_db, month).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(name: &str, parent: Option<&Category>) -> Category {
+        Category {
+            id: uuid::Uuid::new_v4(),
+            name: name.to_string(),
+            parent_id: parent.map(|parent| parent.id),
+            monthly_budget: None,
+        }
+    }
+
+    #[test]
+    fn spending_rolls_up_to_ancestors() {
+        let home = category("Home", None);
+        let energy = category("Energy", Some(&home));
+        let power = category("Power", Some(&energy));
+        let food = category("Food", None);
+
+        let spent =
+            HashMap::from([(power.id, 100), (energy.id, 20), (food.id, 7)]);
+        let categories = [&home, &energy, &power, &food]
+            .into_iter()
+            .map(|category| (category.id, category.clone()))
+            .collect();
+        let totals = roll_up(&categories, &spent);
+
+        assert_eq!(totals[&power.id], 100);
+        assert_eq!(totals[&energy.id], 120);
+        assert_eq!(totals[&home.id], 120);
+        assert_eq!(totals[&food.id], 7);
+    }
+}
@@ -0,0 +1,87 @@
+use super::receipts::{uuid_conversion, EndpointResult, ReceiptError};
+use crate::SQLDb;
+use entity::comment::{self, Model as Comment};
+use entity::receipt;
+use rocket::serde::uuid::Uuid;
+use rocket::serde::{json::Json, Deserialize};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use sea_orm_rocket::Connection;
+
+/// Body of a request adding a comment to a receipt.
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NewComment {
+    pub author: String,
+    pub body: String,
+}
+
+/// Lists the discussion about a receipt, oldest comment first. Ranked after
+/// routes like `/download/<id>`, which match the same paths.
+#[get("/<id>/comments", rank = 2)]
+pub async fn get_comments(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<Json<Vec<Comment>>> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+    if receipt::Entity::find_by_id(id).one(sql_db).await?.is_none() {
+        return Err(ReceiptError::NotFound);
+    }
+
+    let comments = comment::Entity::find()
+        .filter(comment::Column::ReceiptId.eq(id))
+        .order_by_asc(comment::Column::CreatedAt)
+        .all(sql_db)
+        .await?;
+    Ok(Json(comments))
+}
+
+#[post("/<id>/comments", data = "<new>")]
+pub async fn add_comment(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+    new: Json<NewComment>,
+) -> EndpointResult<Json<Comment>> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+    if receipt::Entity::find_by_id(id).one(sql_db).await?.is_none() {
+        return Err(ReceiptError::NotFound);
+    }
+    let new = new.into_inner();
+    if new.author.trim().is_empty() || new.body.trim().is_empty() {
+        return Err(ReceiptError::Invalid(
+            "author and body must not be empty".into(),
+        ));
+    }
+
+    let comment = comment::ActiveModel {
+        id: Set(uuid::Uuid::new_v4()),
+        receipt_id: Set(id),
+        author: Set(new.author),
+        body: Set(new.body),
+        created_at: Set(chrono::Local::now().naive_local()),
+    }
+    .insert(sql_db)
+    .await?;
+    Ok(Json(comment))
+}
+
+#[delete("/<id>/comments/<comment_id>")]
+pub async fn delete_comment(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+    comment_id: Uuid,
+) -> EndpointResult<()> {
+    let sql_db = conn.into_inner();
+    let result = comment::Entity::delete_many()
+        .filter(comment::Column::Id.eq(uuid_conversion(comment_id)?))
+        .filter(comment::Column::ReceiptId.eq(uuid_conversion(id)?))
+        .exec(sql_db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ReceiptError::NotFound);
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use crate::notifications::{EventKind, Notifier};
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Shutdown, State};
+
+/// Streams receipt creations, edits and state changes as server-sent events,
+/// named after their [`EventKind`] and carrying the event as JSON.
+///
+/// Clients that fall too far behind get a `resync` event and should fetch
+/// the boxes they show again.
+#[get("/events")]
+pub fn receipt_events(
+    notifier: &State<Notifier>,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let mut events = notifier.subscribe();
+    EventStream! {
+        loop {
+            let event = select! {
+                event = events.recv() => event,
+                _ = &mut shutdown => break,
+            };
+            match event {
+                Ok(event) if event.kind() != EventKind::Reminder => {
+                    yield Event::json(&event).event(event.kind().as_str());
+                },
+                Ok(_) => {},
+                Err(RecvError::Lagged(_)) => {
+                    yield Event::empty().event("resync");
+                },
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
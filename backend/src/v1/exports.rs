@@ -0,0 +1,421 @@
+use super::receipts::{EndpointResult, ReceiptError};
+use super::DateParam;
+use crate::accounting::{self, AccountingConfig};
+use crate::files::FileStore;
+use crate::{SQLDb, SledDB};
+use chrono::NaiveDate;
+use entity::receipt::{self, ReceiptState};
+use entity::recipient;
+use entity::{category, receipt_tag, tag};
+use rocket::http::{ContentType, Header};
+use rocket::serde::Serialize;
+use rocket::tokio::fs::File;
+use rocket::{Config, State};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+use sea_orm_rocket::Connection;
+use std::collections::HashMap;
+use std::io::{Seek, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// A receipt in an export with everything the ledger lists about it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct LedgerEntry {
+    pub id: uuid::Uuid,
+    /// Path of the receipt file in the archive.
+    pub file: String,
+    pub name: String,
+    pub state: ReceiptState,
+    /// Payment date, or the due date if not payed yet.
+    pub date: NaiveDate,
+    pub due_date: Option<NaiveDate>,
+    pub payment_date: Option<NaiveDate>,
+    /// Total in cents.
+    pub amount: Option<i64>,
+    pub category: Option<String>,
+    pub recipient_name: Option<String>,
+    pub recipient_iban: Option<String>,
+    /// Tags separated by `;`.
+    pub tags: String,
+    /// Hash of the receipt file, as stored with the receipt.
+    pub sha256: String,
+}
+
+/// A file the browser saves instead of showing it.
+#[derive(Responder)]
+pub struct Download<R> {
+    content: R,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+impl<R> Download<R> {
+    fn new(content: R, content_type: ContentType, name: String) -> Self {
+        Download {
+            content,
+            content_type,
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", name),
+            ),
+        }
+    }
+}
+
+fn check_range(from: NaiveDate, until: NaiveDate) -> EndpointResult<()> {
+    if from > until {
+        return Err(ReceiptError::Invalid(
+            "from must not be after until".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces everything but letters, digits and dashes, so names are safe
+/// in file names on every system.
+fn file_name_part(name: &str) -> String {
+    let part: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    part.trim_matches('-').to_string()
+}
+
+/// `receipts/<date>_<recipient>_<category>_<id>.<extension>`. The id keeps
+/// receipts of the same day and recipient apart.
+fn archive_path(entry: &LedgerEntry) -> String {
+    let extension = Path::new(&entry.name)
+        .extension()
+        .map(|extension| file_name_part(&extension.to_string_lossy()))
+        .filter(|extension| !extension.is_empty())
+        .unwrap_or_else(|| "bin".to_string());
+    let or_default = |part: &Option<String>, default: &str| {
+        part.as_deref()
+            .map(file_name_part)
+            .filter(|part| !part.is_empty())
+            .unwrap_or_else(|| default.to_string())
+    };
+    format!(
+        "receipts/{}_{}_{}_{}.{}",
+        entry.date,
+        or_default(&entry.recipient_name, "unknown"),
+        or_default(&entry.category, "uncategorized"),
+        entry.id.simple(),
+        extension.to_lowercase()
+    )
+}
+
+/// Collects the receipts payed, or due if not payed yet, between `from` and
+/// `until`, oldest first. Declined receipts are left out.
+pub(crate) async fn ledger(
+    db: &DatabaseConnection,
+    from: NaiveDate,
+    until: NaiveDate,
+) -> Result<Vec<LedgerEntry>, DbErr> {
+    let exported = Condition::all()
+        .add(receipt::Column::State.ne(ReceiptState::Declined))
+        .add(super::reports::in_range(Some(from), Some(until)));
+    let receipts = receipt::Entity::find()
+        .filter(exported.clone())
+        .find_also_related(recipient::Entity)
+        .all(db)
+        .await?;
+    let categories: HashMap<_, _> = category::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|category| (category.id, category.name))
+        .collect();
+    let tag_names: HashMap<_, _> = tag::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|tag| (tag.id, tag.name))
+        .collect();
+    let mut tags: HashMap<_, Vec<&str>> = HashMap::new();
+    // joined instead of listing the ids, which could be more than the
+    // database takes as parameters
+    let links = receipt_tag::Entity::find()
+        .inner_join(receipt::Entity)
+        .filter(exported)
+        .order_by_asc(receipt_tag::Column::TagId)
+        .all(db)
+        .await?;
+    for link in &links {
+        if let Some(name) = tag_names.get(&link.tag_id) {
+            tags.entry(link.receipt_id).or_default().push(name);
+        }
+    }
+
+    let mut entries: Vec<LedgerEntry> = receipts
+        .into_iter()
+        .filter_map(|(receipt, recipient)| {
+            let mut receipt_tags =
+                tags.get(&receipt.id).cloned().unwrap_or_default();
+            receipt_tags.sort_unstable();
+            let mut entry = LedgerEntry {
+                id: receipt.id,
+                file: String::new(),
+                date: receipt.payment_date.or(receipt.due_date)?,
+                due_date: receipt.due_date,
+                payment_date: receipt.payment_date,
+                amount: receipt.amount,
+                category: receipt
+                    .category_id
+                    .and_then(|id| categories.get(&id).cloned()),
+                recipient_name: recipient.as_ref().map(|r| r.name.clone()),
+                recipient_iban: recipient.map(|r| r.iban),
+                tags: receipt_tags.join(";"),
+                sha256: receipt.file_hash,
+                state: receipt.state,
+                name: receipt.name,
+            };
+            entry.file = archive_path(&entry);
+            Some(entry)
+        })
+        .collect();
+    entries.sort_by(|a, b| (a.date, &a.file).cmp(&(b.date, &b.file)));
+    Ok(entries)
+}
+
+/// Writes the receipt files of `entries`, read by `file`, into a ZIP in `out`
+/// along with the ledger as CSV and JSON and a `SHA256SUMS` manifest in the
+/// format of `sha256sum`.
+pub(crate) fn build_archive<W, F>(
+    entries: &[LedgerEntry],
+    out: W,
+    mut file: F,
+) -> anyhow::Result<W>
+where
+    W: Write + Seek,
+    F: FnMut(&str) -> anyhow::Result<Vec<u8>>,
+{
+    let mut zip = ZipWriter::new(out);
+    let options =
+        FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut manifest = String::new();
+
+    for entry in entries {
+        zip.start_file(entry.file.as_str(), options)?;
+        zip.write_all(&file(&entry.sha256)?)?;
+        manifest.push_str(&format!("{}  {}\n", entry.sha256, entry.file));
+    }
+
+    let mut csv = csv::Writer::from_writer(Vec::new());
+    for entry in entries {
+        csv.serialize(entry)?;
+    }
+    let csv = csv.into_inner()?;
+    let json = serde_json::to_vec_pretty(entries)?;
+    for (path, content) in [("ledger.csv", csv), ("ledger.json", json)] {
+        zip.start_file(path, options)?;
+        zip.write_all(&content)?;
+        manifest.push_str(&format!(
+            "{}  {}\n",
+            sha256::digest_bytes(&content),
+            path
+        ));
+    }
+
+    zip.start_file("SHA256SUMS", options)?;
+    zip.write_all(manifest.as_bytes())?;
+    Ok(zip.finish()?)
+}
+
+/// Builds the archive of `entries` in a file in `temp_dir` rather than in
+/// memory, as it holds every receipt file of e.g. a whole year. The file is
+/// unlinked right away, the returned handle keeps it until it is closed.
+fn archive_file(
+    temp_dir: &Path,
+    entries: &[LedgerEntry],
+    files_db: &FileStore,
+) -> anyhow::Result<std::fs::File> {
+    let path = temp_dir.join(format!("export-{}.zip", uuid::Uuid::new_v4()));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    let mut file =
+        build_archive(entries, file, |hash| read_file(files_db, hash))?;
+    file.rewind()?;
+    Ok(file)
+}
+
+fn read_file(files_db: &FileStore, hash: &str) -> anyhow::Result<Vec<u8>> {
+    files_db
+        .get(hash)?
+        .ok_or_else(|| anyhow::anyhow!("file {} is missing", hash))
+}
+
+/// Everything an accountant needs about the receipts between `from` and
+/// `until`, e.g. of a tax year, as one ZIP.
+#[get("/tax-year?<from>&<until>")]
+pub async fn get_tax_export(
+    conn: Connection<'_, SQLDb>,
+    config: &State<Config>,
+    db: &State<SledDB>,
+    from: DateParam,
+    until: DateParam,
+) -> EndpointResult<Download<File>> {
+    check_range(from.0, until.0)?;
+    let sql_db = conn.into_inner();
+    let entries = ledger(sql_db, from.0, until.0).await?;
+
+    let temp_dir = config.temp_dir.relative();
+    let files_db = db.files_db.clone();
+    let archive = rocket::tokio::task::spawn_blocking(move || {
+        archive_file(&temp_dir, &entries, &files_db)
+    })
+    .await
+    .map_err(anyhow::Error::from)??;
+
+    Ok(Download::new(
+        File::from_std(archive),
+        ContentType::ZIP,
+        format!("receipts_{}_{}.zip", from.0, until.0),
+    ))
+}
+
+/// The receipts payed between `from` and `until` as DATEV Buchungsstapel.
+/// The range has to be within one fiscal year.
+#[get("/datev?<from>&<until>")]
+pub async fn get_datev_export(
+    conn: Connection<'_, SQLDb>,
+    config: &State<AccountingConfig>,
+    from: DateParam,
+    until: DateParam,
+) -> EndpointResult<Download<Vec<u8>>> {
+    check_range(from.0, until.0)?;
+    if config.fiscal_year_of(from.0) != config.fiscal_year_of(until.0) {
+        return Err(ReceiptError::Invalid(
+            "from and until must be in the same fiscal year".into(),
+        ));
+    }
+    let sql_db = conn.into_inner();
+    let postings =
+        accounting::postings(sql_db, config, from.0, until.0).await?;
+
+    let content = accounting::datev::buchungsstapel(
+        config,
+        &postings,
+        from.0,
+        until.0,
+        chrono::Local::now().naive_local(),
+    );
+    Ok(Download::new(
+        content,
+        ContentType::new("text", "csv")
+            .with_params(("charset", "windows-1252")),
+        format!("EXTF_Buchungsstapel_{}_{}.csv", from.0, until.0),
+    ))
+}
+
+/// The receipts payed between `from` and `until` as ledger journal.
+#[get("/ledger?<from>&<until>")]
+pub async fn get_ledger_export(
+    conn: Connection<'_, SQLDb>,
+    config: &State<AccountingConfig>,
+    from: DateParam,
+    until: DateParam,
+) -> EndpointResult<Download<Vec<u8>>> {
+    check_range(from.0, until.0)?;
+    let sql_db = conn.into_inner();
+    let postings =
+        accounting::postings(sql_db, config, from.0, until.0).await?;
+
+    let content = accounting::ledger::journal(config, &postings);
+    Ok(Download::new(
+        content.into_bytes(),
+        ContentType::Plain,
+        format!("receipts_{}_{}.journal", from.0, until.0),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    fn entry(recipient: Option<&str>, category: Option<&str>) -> LedgerEntry {
+        let mut entry = LedgerEntry {
+            id: uuid::Uuid::new_v4(),
+            file: String::new(),
+            name: "Power Bill.PDF".to_string(),
+            state: ReceiptState::Payed,
+            date: NaiveDate::from_ymd(2022, 8, 15),
+            due_date: None,
+            payment_date: Some(NaiveDate::from_ymd(2022, 8, 15)),
+            amount: Some(12000),
+            category: category.map(str::to_string),
+            recipient_name: recipient.map(str::to_string),
+            recipient_iban: None,
+            tags: String::new(),
+            sha256: sha256::digest_bytes(b"power"),
+        };
+        entry.file = archive_path(&entry);
+        entry
+    }
+
+    #[test]
+    fn files_are_named_by_date_recipient_and_category() {
+        let named = entry(Some("Stadtwerke Süd / Strom"), Some("Energy"));
+        assert_eq!(
+            named.file,
+            format!(
+                "receipts/2022-08-15_Stadtwerke-Süd---Strom_Energy_{}.pdf",
+                named.id.simple()
+            )
+        );
+        let unnamed = entry(None, None);
+        assert!(unnamed.file.contains("_unknown_uncategorized_"));
+    }
+
+    #[test]
+    fn archive_lists_files_in_manifest() {
+        let entries = [entry(Some("Stadtwerke"), None)];
+        let archive = build_archive(&entries, Cursor::new(Vec::new()), |_| {
+            Ok(b"power".to_vec())
+        })
+        .unwrap();
+
+        let mut archive = ZipArchive::new(archive).unwrap();
+        let mut names: Vec<_> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "SHA256SUMS",
+                "ledger.csv",
+                "ledger.json",
+                entries[0].file.as_str()
+            ]
+        );
+
+        let mut manifest = String::new();
+        archive
+            .by_name("SHA256SUMS")
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        assert!(manifest.starts_with(&format!(
+            "{}  {}\n",
+            entries[0].sha256, entries[0].file
+        )));
+        assert_eq!(manifest.lines().count(), 3);
+    }
+}
@@ -1,14 +1,135 @@
+use chrono::{Datelike, NaiveDate};
+use rocket::form::{self, FromFormField, ValueField};
 use rocket::Route;
 
-pub(crate) mod receipts;
-pub(crate) mod greeting; 
+pub mod batch;
+pub mod categories;
+pub mod comments;
+pub mod events;
+pub mod exports;
+pub mod receipts;
+pub mod recurring;
+pub mod reports;
+pub mod rules;
+pub mod splits;
+pub mod tags;
+pub mod greeting;
+
+/// A `YYYY-MM-DD` date in a query string.
+#[derive(Debug, Clone, Copy)]
+pub struct DateParam(pub NaiveDate);
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for DateParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        field.value.parse().map(DateParam).map_err(|err| {
+            form::Error::validation(format!("invalid date: {}", err)).into()
+        })
+    }
+}
+
+/// A `YYYY-MM` month in a query string.
+#[derive(Debug, Clone, Copy)]
+pub struct MonthParam {
+    pub first_day: NaiveDate,
+}
+
+impl MonthParam {
+    pub fn containing(date: NaiveDate) -> Self {
+        MonthParam {
+            first_day: date.with_day(1).expect("every month has a first day"),
+        }
+    }
+
+    pub fn last_day(&self) -> NaiveDate {
+        let (year, month) = match self.first_day.month() {
+            12 => (self.first_day.year() + 1, 1),
+            month => (self.first_day.year(), month + 1),
+        };
+        NaiveDate::from_ymd(year, month, 1).pred()
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for MonthParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let first_day = format!("{}-01", field.value);
+        NaiveDate::parse_from_str(&first_day, "%Y-%m-%d")
+            .map(|first_day| MonthParam {
+                first_day,
+            })
+            .map_err(|err| {
+                form::Error::validation(format!("invalid month: {}", err))
+                    .into()
+            })
+    }
+}
 
 pub fn receipt_routes() -> Vec<Route> {
     routes![
         receipts::upload_receipt,
+        batch::upload_batch,
         receipts::get_receipts,
+        receipts::get_overdue_receipts,
         receipts::post_receipt,
         receipts::get_receipt,
+        receipts::patch_receipt,
         receipts::get_receipt_file,
+        receipts::get_receipt_preview,
+        events::receipt_events,
+        tags::get_tags,
+        tags::get_receipt_tags,
+        tags::add_receipt_tag,
+        tags::remove_receipt_tag,
+        comments::get_comments,
+        comments::add_comment,
+        comments::delete_comment,
+        splits::get_split,
+        splits::put_split,
+        splits::delete_split,
+    ]
+}
+
+pub fn recurring_routes() -> Vec<Route> {
+    routes![
+        recurring::get_recurring_bills,
+        recurring::create_recurring_bill,
+        recurring::delete_recurring_bill,
+        recurring::get_forecast,
+    ]
+}
+
+pub fn category_routes() -> Vec<Route> {
+    routes![
+        categories::get_categories,
+        categories::get_category,
+        categories::create_category,
+        categories::update_category,
+        categories::delete_category,
+        categories::get_budgets,
+    ]
+}
+
+pub fn rule_routes() -> Vec<Route> {
+    routes![
+        rules::get_rules,
+        rules::get_rule,
+        rules::create_rule,
+        rules::update_rule,
+        rules::delete_rule,
+        rules::preview_new_rule,
+        rules::preview_rule,
+    ]
+}
+
+pub fn report_routes() -> Vec<Route> {
+    routes![reports::get_report, splits::get_settle_up]
+}
+
+pub fn export_routes() -> Vec<Route> {
+    routes![
+        exports::get_tax_export,
+        exports::get_datev_export,
+        exports::get_ledger_export,
     ]
-}
\ No newline at end of file
+}
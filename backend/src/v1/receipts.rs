@@ -1,7 +1,12 @@
+use crate::files::FileStore;
+use crate::notifications::{Event, Notifier};
+use crate::previews::{self, PreviewConfig};
+use crate::uploads::{Rejection, UploadPolicy};
 use crate::SQLDb;
 use crate::SledDB;
 use anyhow::anyhow;
 use chrono::NaiveDate;
+use entity::category;
 use entity::receipt::{self, Model as Receipt, ReceiptState};
 use entity::recipient::{self, Model as Recipient};
 use log::debug;
@@ -11,12 +16,19 @@ use rocket::data::Capped;
 use rocket::form::{Form, Strict};
 use rocket::fs::TempFile;
 use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Response};
 use rocket::serde::uuid::Uuid;
-use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::serde::{json::Json, Deserialize, Deserializer, Serialize};
 use rocket::{http::Status, response::Responder};
 use rocket::{Config, State};
+use sea_orm::prelude::Json as JsonValue;
 use sea_orm::ActiveModelTrait;
-use sea_orm::{ColumnTrait, EntityTrait, ModelTrait, QueryFilter, Set};
+use sea_orm::DatabaseConnection;
+use sea_orm::{
+    ColumnTrait, EntityTrait, Iterable, ModelTrait, QueryFilter, QueryOrder,
+    Set,
+};
 use sea_orm_rocket::Connection;
 use std::io::Read;
 use thiserror::Error;
@@ -27,7 +39,7 @@ pub struct ReceiptUploadRequest<'r> {
     file: Capped<TempFile<'r>>,
 }
 
-type EndpointResult<T> = Result<T, ReceiptError>;
+pub(crate) type EndpointResult<T> = Result<T, ReceiptError>;
 
 #[derive(Error, Debug)]
 pub enum ReceiptError {
@@ -41,12 +53,24 @@ pub enum ReceiptError {
     NotFound,
     #[error("uuid conversion error")]
     Uuid(#[from] uuid::Error),
+    /// The request contradicts the stored data, e.g. deleting something
+    /// still in use.
+    #[error("{0}")]
+    Conflict(String),
+    /// The request is well-formed but its values are not acceptable.
+    #[error("{0}")]
+    Invalid(String),
+    #[error("{0}")]
+    Rejected(#[from] Rejection),
+    /// The edit is based on another version than the stored one.
+    #[error("receipt was changed in the meantime")]
+    Outdated,
 }
 
 impl<'r> Responder<'r, 'static> for ReceiptError {
     fn respond_to(
         self,
-        _: &'r rocket::Request<'_>,
+        request: &'r rocket::Request<'_>,
     ) -> rocket::response::Result<'static> {
         match self {
             ReceiptError::Sled(err) => {
@@ -66,6 +90,28 @@ impl<'r> Responder<'r, 'static> for ReceiptError {
                 error!("UUID conversion error: {}", err);
                 Err(Status::BadRequest)
             },
+            ReceiptError::Conflict(message) => {
+                (Status::Conflict, message).respond_to(request)
+            },
+            ReceiptError::Invalid(message) => {
+                (Status::UnprocessableEntity, message).respond_to(request)
+            },
+            ReceiptError::Rejected(Rejection::ScanFailed(err)) => {
+                error!("Malware scan failed: {}", err);
+                Err(Status::ServiceUnavailable)
+            },
+            ReceiptError::Rejected(rejection) => {
+                let status = match rejection {
+                    Rejection::TooLarge => Status::PayloadTooLarge,
+                    Rejection::UnsupportedType => Status::UnsupportedMediaType,
+                    _ => Status::UnprocessableEntity,
+                };
+                (status, rejection.to_string()).respond_to(request)
+            },
+            ReceiptError::Outdated => {
+                (Status::PreconditionFailed, self.to_string())
+                    .respond_to(request)
+            },
         }
     }
 }
@@ -78,19 +124,105 @@ pub enum ReceiptAction {
     Pay,
     ConfirmProcessStep(String),
     SetRecipient(Recipient),
-    SetCategory(String),
+    SetCategory(uuid::Uuid),
     SetPaymentDate(NaiveDate),
+    SetDueDate(NaiveDate),
+    SetDiscountDeadline(NaiveDate),
+    /// Total of the bill in cents.
+    SetAmount(i64),
+}
+
+/// Entity tag of a receipt version.
+fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+/// The version of an `If-Match` header. Weak tags never match as edits need
+/// the strong comparison.
+fn parse_etag(tag: &str) -> Option<i32> {
+    tag.trim().strip_prefix('"')?.strip_suffix('"')?.parse().ok()
+}
+
+/// Version of a receipt an edit is based on, named by its `If-Match`
+/// header, or `None` for `If-Match: *`, which matches any version. Requests
+/// without the header fail with 428, those with a tag that is no version
+/// with 412.
+pub struct IfMatch(pub Option<i32>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = &'static str;
+
+    async fn from_request(
+        request: &'r Request<'_>,
+    ) -> Outcome<Self, Self::Error> {
+        let if_match = request.headers().get_one("If-Match");
+        if if_match.map(str::trim) == Some("*") {
+            return Outcome::Success(IfMatch(None));
+        }
+        match if_match.map(parse_etag) {
+            Some(Some(version)) => Outcome::Success(IfMatch(Some(version))),
+            Some(None) => Outcome::Failure((
+                Status::PreconditionFailed,
+                "If-Match names no receipt version",
+            )),
+            None => Outcome::Failure((
+                Status::PreconditionRequired,
+                "If-Match is missing",
+            )),
+        }
+    }
+}
+
+/// JSON answer carrying the version of a receipt as `ETag`.
+pub struct Tagged<T> {
+    version: i32,
+    inner: T,
+}
+
+impl<T> Tagged<T> {
+    pub fn new(version: i32, inner: T) -> Self {
+        Tagged {
+            version,
+            inner,
+        }
+    }
 }
 
-fn uuid_conversion(uuid: Uuid) -> Result<uuid::Uuid, uuid::Error> {
+impl<'r, T: Serialize> Responder<'r, 'static> for Tagged<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(Json(self.inner).respond_to(request)?)
+            .raw_header("ETag", etag(self.version))
+            .ok()
+    }
+}
+
+pub(crate) fn uuid_conversion(uuid: Uuid) -> Result<uuid::Uuid, uuid::Error> {
     let s = uuid.hyphenated().to_string();
     uuid::Uuid::parse_str(&s)
 }
 
-fn sled_to_anyhow<E: std::fmt::Display>(err: E) -> anyhow::Error {
+pub(crate) fn sled_to_anyhow<E: std::fmt::Display>(err: E) -> anyhow::Error {
     anyhow!("{}", err)
 }
 
+/// Reads an uploaded file, which Rocket keeps in memory or on disk, through
+/// the temp dir.
+pub(crate) async fn read_upload(
+    config: &Config,
+    file: &mut TempFile<'_>,
+) -> std::io::Result<Vec<u8>> {
+    let file_temp_id = uuid::Uuid::new_v4().as_hyphenated().to_string();
+    let tmp_file = config.temp_dir.relative().join(file_temp_id);
+    file.persist_to(&tmp_file).await?;
+    let mut file = std::fs::File::open(&tmp_file)?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    drop(file);
+    std::fs::remove_file(&tmp_file)?;
+    Ok(content)
+}
+
 // Needs https://github.com/GREsau/schemars/issues/103
 //#[openapi]
 #[post("/upload", data = "<upload>")]
@@ -98,46 +230,121 @@ pub async fn upload_receipt(
     config: &State<Config>,
     conn: Connection<'_, SQLDb>,
     db: &State<SledDB>,
+    notifier: &State<Notifier>,
+    policy: &State<UploadPolicy>,
     mut upload: Form<Strict<ReceiptUploadRequest<'_>>>,
 ) -> EndpointResult<Json<Receipt>> {
     info!("received file: {}", upload.name);
-    let hash = {
-        let file_temp_id = uuid::Uuid::new_v4().as_hyphenated().to_string();
-        let tmp_file = config.temp_dir.relative().join(file_temp_id);
-        upload.file.persist_to(&tmp_file).await?;
-        let mut file = std::fs::File::open(&tmp_file)?;
-        let mut content = Vec::new();
-        file.read_to_end(&mut content)?;
-        let hash = sha256::digest_bytes(&content);
-        db.files_db.insert(hash.as_bytes(), content).map_err(sled_to_anyhow)?;
-        drop(file);
-        std::fs::remove_file(&tmp_file)?;
-        hash
-    };
+    // Rocket stops reading at `limits.file` and hands over what it got
+    if !upload.file.is_complete() {
+        return Err(Rejection::TooLarge.into());
+    }
+    let content = read_upload(config, &mut upload.file).await?;
+    policy.check(&content).await?;
+
+    let sql_db = conn.into_inner();
+    let receipt = create_inbox_receipt(
+        sql_db,
+        &db.files_db,
+        notifier,
+        upload.name,
+        content,
+        None,
+    )
+    .await?;
+
+    Ok(Json(receipt))
+}
+
+/// The receipt of the file with the sha256 `hash`, if it was stored before.
+pub async fn find_by_file_hash(
+    sql_db: &DatabaseConnection,
+    hash: &str,
+) -> Result<Option<Receipt>, sea_orm::DbErr> {
+    receipt::Entity::find()
+        .filter(receipt::Column::FileHash.eq(hash))
+        .one(sql_db)
+        .await
+}
+
+/// Stores `content` in the files db under its sha256 hash and creates a new
+/// receipt in the Inbox pointing to it. This is the single path every
+/// ingestion source (uploads, mail, ...) uses to create receipts, so it also
+/// matches them to the expected bills of recurring bill templates, announces
+/// them and runs the rules on them.
+pub async fn create_inbox_receipt(
+    sql_db: &DatabaseConnection,
+    files_db: &FileStore,
+    notifier: &Notifier,
+    name: &str,
+    content: Vec<u8>,
+    metadata: Option<JsonValue>,
+) -> EndpointResult<Receipt> {
+    let hash = sha256::digest_bytes(&content);
+    let (content, content_text) = rocket::tokio::task::spawn_blocking(|| {
+        let text = crate::ingest::text::extract_text(&content);
+        (content, text)
+    })
+    .await
+    .map_err(anyhow::Error::from)?;
+    files_db.insert(&hash, &content)?;
 
     let receipt = receipt::ActiveModel {
-        name: Set(upload.name.to_owned()),
+        id: Set(uuid::Uuid::new_v4()),
+        name: Set(name.to_owned()),
         state: Set(receipt::ReceiptState::Inbox),
         file_hash: Set(hash),
+        metadata: Set(metadata),
+        content_text: Set(content_text),
         ..Default::default()
     };
-    let sql_db = conn.into_inner();
 
-    let receipt: Receipt = receipt.insert(sql_db).await?;
-
-    Ok(Json(receipt))
+    let receipt = receipt.insert(sql_db).await?;
+    let receipt = super::recurring::match_receipt(sql_db, receipt).await?;
+    notifier.emit(Event::ReceiptCreated {
+        receipt: receipt.clone(),
+    });
+    super::rules::apply_rules(sql_db, notifier, receipt).await
 }
 
-#[get("/box/<state>")]
+/// Lists the receipts in `state`, only those carrying every `tag` if any
+/// are given.
+#[get("/box/<state>?<tag>")]
 pub async fn get_receipts(
     conn: Connection<'_, SQLDb>,
     state: ReceiptState,
+    tag: Vec<String>,
 ) -> EndpointResult<Json<Vec<Receipt>>> {
     let sql_db = conn.into_inner();
-    debug!("Searching box {}", state);
+    debug!("Searching box {} for tags {:?}", state, tag);
 
+    let tagged = match super::tags::has_tags(sql_db, &tag).await? {
+        Some(tagged) => tagged,
+        None => return Ok(Json(Vec::new())),
+    };
     let receipts: Vec<Receipt> = receipt::Entity::find()
         .filter(receipt::Column::State.eq(state))
+        .filter(tagged)
+        .all(sql_db)
+        .await?;
+    Ok(Json(receipts))
+}
+
+/// Lists the open receipts whose due date has passed, most overdue first.
+#[get("/overdue")]
+pub async fn get_overdue_receipts(
+    conn: Connection<'_, SQLDb>,
+) -> EndpointResult<Json<Vec<Receipt>>> {
+    let sql_db = conn.into_inner();
+    let today = chrono::Local::today().naive_local();
+
+    let receipts: Vec<Receipt> = receipt::Entity::find()
+        .filter(
+            receipt::Column::State
+                .is_in(ReceiptState::iter().filter(ReceiptState::is_open)),
+        )
+        .filter(receipt::Column::DueDate.lt(today))
+        .order_by_asc(receipt::Column::DueDate)
         .all(sql_db)
         .await?;
     Ok(Json(receipts))
@@ -154,9 +361,29 @@ pub enum ActionAnswer {
     ReceiptAndRecipient((Receipt, Recipient)),
 }
 
+/// Moves `model` to `state` and tells everyone interested about it.
+pub(crate) async fn change_state(
+    sql_db: &DatabaseConnection,
+    notifier: &Notifier,
+    model: Receipt,
+    state: ReceiptState,
+) -> EndpointResult<Receipt> {
+    let from = model.state.clone();
+    let mut update_receipt: receipt::ActiveModel = model.into();
+    update_receipt.state = Set(state.clone());
+    let receipt: Receipt = update_receipt.update(sql_db).await?;
+    notifier.emit(Event::StateChanged {
+        receipt: receipt.clone(),
+        from,
+        to: state,
+    });
+    Ok(receipt)
+}
+
 #[post("/<id>", data = "<action>")]
 pub async fn post_receipt(
     conn: Connection<'_, SQLDb>,
+    notifier: &State<Notifier>,
     id: Uuid,
     action: Json<ReceiptAction>,
 ) -> EndpointResult<Json<ActionAnswer>> {
@@ -168,10 +395,13 @@ pub async fn post_receipt(
         match action.0 {
             ReceiptAction::Accept => {
                 if model.state == receipt::ReceiptState::Inbox {
-                    let mut update_receipt: receipt::ActiveModel = model.into();
-                    update_receipt.state = Set(ReceiptState::Valid);
-                    let receipt: Receipt =
-                        update_receipt.update(sql_db).await?;
+                    let receipt = change_state(
+                        sql_db,
+                        notifier,
+                        model,
+                        ReceiptState::Valid,
+                    )
+                    .await?;
                     Ok(Json(ActionAnswer::Receipt(receipt)))
                 } else {
                     Ok(Json(ActionAnswer::Error(format!(
@@ -182,10 +412,13 @@ pub async fn post_receipt(
             },
             ReceiptAction::Decline => {
                 if model.state == receipt::ReceiptState::Inbox {
-                    let mut update_receipt: receipt::ActiveModel = model.into();
-                    update_receipt.state = Set(ReceiptState::Declined);
-                    let receipt: Receipt =
-                        update_receipt.update(sql_db).await?;
+                    let receipt = change_state(
+                        sql_db,
+                        notifier,
+                        model,
+                        ReceiptState::Declined,
+                    )
+                    .await?;
                     Ok(Json(ActionAnswer::Receipt(receipt)))
                 } else {
                     Ok(Json(ActionAnswer::Error(format!(
@@ -196,9 +429,13 @@ pub async fn post_receipt(
             },
             ReceiptAction::Pay => {
                 if model.payment_date.is_some() {
-                    let mut update_receipt: receipt::ActiveModel = model.into();
-                    update_receipt.state = Set(ReceiptState::Payed);
-                    let receipt = update_receipt.update(sql_db).await?;
+                    let receipt = change_state(
+                        sql_db,
+                        notifier,
+                        model,
+                        ReceiptState::Payed,
+                    )
+                    .await?;
                     Ok(Json(ActionAnswer::Receipt(receipt)))
                 } else {
                     Ok(Json(ActionAnswer::Error(format!(
@@ -207,26 +444,52 @@ pub async fn post_receipt(
                     ))))
                 }
             },
-            ReceiptAction::ConfirmProcessStep(_) => todo!(),
+            ReceiptAction::ConfirmProcessStep(_) => {
+                Ok(Json(ActionAnswer::Error(
+                    "process steps are not supported yet".into(),
+                )))
+            },
             ReceiptAction::SetRecipient(form_recipient) => {
                 let db_recipient =
                     model.find_related(recipient::Entity).one(sql_db).await?;
-                let mut update_recipient: recipient::ActiveModel =
-                    form_recipient.into();
+                let update_recipient = recipient::ActiveModel {
+                    id: Set(db_recipient
+                        .as_ref()
+                        .map(|db_model| db_model.id)
+                        .unwrap_or_else(uuid::Uuid::new_v4)),
+                    receipt_id: Set(model.id),
+                    name: Set(form_recipient.name),
+                    iban: Set(form_recipient.iban),
+                    address_line1: Set(form_recipient.address_line1),
+                    address_line2: Set(form_recipient.address_line2),
+                    address_line3: Set(form_recipient.address_line3),
+                    address_line4: Set(form_recipient.address_line4),
+                };
 
-                if let Some(db_model) = db_recipient {
-                    update_recipient.id = Set(db_model.id);
+                let recipient = if db_recipient.is_some() {
+                    update_recipient.update(sql_db).await?
                 } else {
-                    update_recipient.receipt_id = Set(model.id);
-                }
-
-                let recipient = update_recipient.update(sql_db).await?;
+                    update_recipient.insert(sql_db).await?
+                };
+                // rules on the recipient can match now
+                let model =
+                    super::rules::apply_rules(sql_db, notifier, model).await?;
 
                 Ok(Json(ActionAnswer::ReceiptAndRecipient((model, recipient))))
             },
-            ReceiptAction::SetCategory(cat) => {
+            ReceiptAction::SetCategory(category_id) => {
+                if category::Entity::find_by_id(category_id)
+                    .one(sql_db)
+                    .await?
+                    .is_none()
+                {
+                    return Ok(Json(ActionAnswer::Error(format!(
+                        "category {} does not exist",
+                        category_id
+                    ))));
+                }
                 let mut update_receipt: receipt::ActiveModel = model.into();
-                update_receipt.category = Set(Some(cat));
+                update_receipt.category_id = Set(Some(category_id));
                 let receipt: Receipt = update_receipt.update(sql_db).await?;
                 Ok(Json(ActionAnswer::Receipt(receipt)))
             },
@@ -236,17 +499,62 @@ pub async fn post_receipt(
                 let receipt: Receipt = update_receipt.update(sql_db).await?;
                 Ok(Json(ActionAnswer::Receipt(receipt)))
             },
+            ReceiptAction::SetAmount(amount) => {
+                if amount < 0 {
+                    return Ok(Json(ActionAnswer::Error(format!(
+                        "amount of {} cannot be negative",
+                        model.name
+                    ))));
+                }
+                let mut update_receipt: receipt::ActiveModel = model.into();
+                update_receipt.amount = Set(Some(amount));
+                let receipt: Receipt = update_receipt.update(sql_db).await?;
+                // rules on the amount can match now
+                let receipt =
+                    super::rules::apply_rules(sql_db, notifier, receipt)
+                        .await?;
+                Ok(Json(ActionAnswer::Receipt(receipt)))
+            },
+            ReceiptAction::SetDueDate(date) => {
+                if model.discount_deadline.map_or(false, |d| d > date) {
+                    return Ok(Json(ActionAnswer::Error(format!(
+                        "due date of {} is before its discount deadline",
+                        model.name
+                    ))));
+                }
+                let mut update_receipt: receipt::ActiveModel = model.into();
+                update_receipt.due_date = Set(Some(date));
+                // remind again about the new date
+                update_receipt.reminded_on = Set(None);
+                let receipt: Receipt = update_receipt.update(sql_db).await?;
+                Ok(Json(ActionAnswer::Receipt(receipt)))
+            },
+            ReceiptAction::SetDiscountDeadline(date) => {
+                if model.due_date.map_or(false, |d| d < date) {
+                    return Ok(Json(ActionAnswer::Error(format!(
+                        "discount deadline of {} is after its due date",
+                        model.name
+                    ))));
+                }
+                let mut update_receipt: receipt::ActiveModel = model.into();
+                update_receipt.discount_deadline = Set(Some(date));
+                update_receipt.reminded_on = Set(None);
+                let receipt: Receipt = update_receipt.update(sql_db).await?;
+                Ok(Json(ActionAnswer::Receipt(receipt)))
+            },
         }
     } else {
         Err(ReceiptError::NotFound)
     }
 }
 
+/// The receipt with its recipient. The `ETag` names the version of the
+/// receipt edits have to be based on.
 #[get("/<id>")]
 pub async fn get_receipt(
     conn: Connection<'_, SQLDb>,
     id: Uuid,
-) -> EndpointResult<Json<(Receipt, Option<Recipient>)>> {
+) -> EndpointResult<Tagged<(Receipt, Option<Recipient>)>> {
     let sql_db = conn.into_inner();
 
     let receipt =
@@ -255,12 +563,156 @@ pub async fn get_receipt(
     if let Some(receipt) = receipt {
         let recipient =
             receipt.find_related(recipient::Entity).one(sql_db).await?;
-        Ok(Json((receipt, recipient)))
+        Ok(Tagged::new(receipt.version, (receipt, recipient)))
     } else {
         Err(ReceiptError::NotFound)
     }
 }
 
+/// Tells a `null` field, `Some(None)`, from a missing one, `None`.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Editable fields of a receipt. Missing fields stay as they are, `null`
+/// clears them. The state only changes through actions.
+#[derive(Deserialize, Debug, Default)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct ReceiptPatch {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub category_id: Option<Option<uuid::Uuid>>,
+    #[serde(default, deserialize_with = "present")]
+    pub payment_date: Option<Option<NaiveDate>>,
+    #[serde(default, deserialize_with = "present")]
+    pub due_date: Option<Option<NaiveDate>>,
+    #[serde(default, deserialize_with = "present")]
+    pub discount_deadline: Option<Option<NaiveDate>>,
+    /// Total of the bill in cents.
+    #[serde(default, deserialize_with = "present")]
+    pub amount: Option<Option<i64>>,
+}
+
+/// Checks `patch` and applies it to `model` as its next version.
+async fn apply_patch(
+    sql_db: &DatabaseConnection,
+    model: Receipt,
+    patch: ReceiptPatch,
+) -> EndpointResult<receipt::ActiveModel> {
+    if patch.name.as_ref().map_or(false, |name| name.trim().is_empty()) {
+        return Err(ReceiptError::Invalid("name cannot be empty".into()));
+    }
+    if patch.amount.flatten().map_or(false, |amount| amount < 0) {
+        return Err(ReceiptError::Invalid("amount cannot be negative".into()));
+    }
+    if let Some(Some(category_id)) = patch.category_id {
+        if category::Entity::find_by_id(category_id)
+            .one(sql_db)
+            .await?
+            .is_none()
+        {
+            return Err(ReceiptError::Invalid(format!(
+                "category {} does not exist",
+                category_id
+            )));
+        }
+    }
+    let due_date = patch.due_date.unwrap_or(model.due_date);
+    let discount_deadline =
+        patch.discount_deadline.unwrap_or(model.discount_deadline);
+    if let (Some(due), Some(discount)) = (due_date, discount_deadline) {
+        if discount > due {
+            return Err(ReceiptError::Invalid(
+                "discount deadline is after the due date".into(),
+            ));
+        }
+    }
+
+    let deadlines_changed = due_date != model.due_date
+        || discount_deadline != model.discount_deadline;
+    let version = model.version;
+    let mut update: receipt::ActiveModel = model.into();
+    if let Some(name) = patch.name {
+        update.name = Set(name);
+    }
+    if let Some(category_id) = patch.category_id {
+        update.category_id = Set(category_id);
+    }
+    if let Some(payment_date) = patch.payment_date {
+        update.payment_date = Set(payment_date);
+    }
+    if let Some(amount) = patch.amount {
+        update.amount = Set(amount);
+    }
+    if deadlines_changed {
+        update.due_date = Set(due_date);
+        update.discount_deadline = Set(discount_deadline);
+        // remind again about the new dates
+        update.reminded_on = Set(None);
+    }
+    update.version = Set(version + 1);
+    Ok(update)
+}
+
+/// Edits the fields of a receipt. `If-Match` has to name the version the
+/// edit is based on, as sent in the `ETag` of [`get_receipt`], or be `*`
+/// to edit whatever version is stored. Edits of any other version fail with
+/// 412 instead of overwriting changes made in the meantime.
+#[patch("/<id>", data = "<patch>")]
+pub async fn patch_receipt(
+    conn: Connection<'_, SQLDb>,
+    notifier: &State<Notifier>,
+    id: Uuid,
+    if_match: IfMatch,
+    patch: Json<ReceiptPatch>,
+) -> EndpointResult<Tagged<Receipt>> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+
+    let model = receipt::Entity::find_by_id(id)
+        .one(sql_db)
+        .await?
+        .ok_or(ReceiptError::NotFound)?;
+    let version = if_match.0.unwrap_or(model.version);
+    if model.version != version {
+        return Err(ReceiptError::Outdated);
+    }
+    let patch = patch.into_inner();
+    let amount_changed =
+        patch.amount.map_or(false, |amount| amount != model.amount);
+    let update = apply_patch(sql_db, model, patch).await?;
+    // a no-op if the receipt changed since it was read above
+    let updated = receipt::Entity::update_many()
+        .set(update)
+        .filter(receipt::Column::Id.eq(id))
+        .filter(receipt::Column::Version.eq(version))
+        .exec(sql_db)
+        .await?;
+    if updated.rows_affected == 0 {
+        return Err(ReceiptError::Outdated);
+    }
+
+    let receipt = receipt::Entity::find_by_id(id)
+        .one(sql_db)
+        .await?
+        .ok_or(ReceiptError::NotFound)?;
+    notifier.emit(Event::ReceiptUpdated {
+        receipt: receipt.clone(),
+    });
+    let receipt = if amount_changed {
+        // rules on the amount can match now
+        super::rules::apply_rules(sql_db, notifier, receipt).await?
+    } else {
+        receipt
+    };
+    Ok(Tagged::new(receipt.version, receipt))
+}
+
 #[get("/download/<id>")]
 pub async fn get_receipt_file(
     conn: Connection<'_, SQLDb>,
@@ -273,12 +725,8 @@ pub async fn get_receipt_file(
         receipt::Entity::find_by_id(uuid_conversion(id)?).one(sql_db).await?;
 
     if let Some(receipt) = receipt {
-        let val = db
-            .files_db
-            .get(receipt.file_hash.as_bytes())
-            .map_err(sled_to_anyhow)?;
-        if let Some(file) = val {
-            Ok((ContentType::Binary, file.to_vec()))
+        if let Some(file) = db.files_db.get(&receipt.file_hash)? {
+            Ok((ContentType::Binary, file))
         } else {
             Err(ReceiptError::NotFound)
         }
@@ -286,3 +734,50 @@ pub async fn get_receipt_file(
         Err(ReceiptError::NotFound)
     }
 }
+
+/// A JPEG preview of the receipt file for list views, rendered on the first
+/// request and cached by the hash of the file. Files without a preview,
+/// e.g. plain text, answer with 404.
+#[get("/preview/<id>")]
+pub async fn get_receipt_preview(
+    conn: Connection<'_, SQLDb>,
+    db: &State<SledDB>,
+    config: &State<PreviewConfig>,
+    id: Uuid,
+) -> EndpointResult<(ContentType, Vec<u8>)> {
+    let sql_db = conn.into_inner();
+    let receipt = receipt::Entity::find_by_id(uuid_conversion(id)?)
+        .one(sql_db)
+        .await?
+        .ok_or(ReceiptError::NotFound)?;
+    if let Some(preview) = db.files_db.get_preview(&receipt.file_hash)? {
+        return Ok((ContentType::JPEG, preview));
+    }
+
+    let files_db = db.files_db.clone();
+    let config = config.inner().clone();
+    let hash = receipt.file_hash;
+    let render = move || -> anyhow::Result<Option<Vec<u8>>> {
+        let content = match files_db.get(&hash)? {
+            Some(content) => content,
+            None => return Ok(None),
+        };
+        match previews::render(&config, &content) {
+            Ok(Some(preview)) => {
+                files_db.insert_preview(&hash, &preview)?;
+                Ok(Some(preview))
+            },
+            Ok(None) => Ok(None),
+            Err(err) => {
+                error!("No preview of file {}: {}", hash, err);
+                Ok(None)
+            },
+        }
+    };
+    let preview = rocket::tokio::task::spawn_blocking(render)
+        .await
+        .map_err(anyhow::Error::from)??;
+    preview
+        .map(|preview| (ContentType::JPEG, preview))
+        .ok_or(ReceiptError::NotFound)
+}
@@ -0,0 +1,419 @@
+use super::receipts::{uuid_conversion, EndpointResult, ReceiptError};
+use super::DateParam;
+use crate::SQLDb;
+use chrono::{Local, NaiveDate};
+use entity::category;
+use entity::expected_bill;
+use entity::receipt::{self, Model as Receipt};
+use entity::recipient;
+use entity::recurring_bill::{self, Cadence, Model as RecurringBill};
+use rocket::serde::uuid::Uuid;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, Set,
+};
+use sea_orm_rocket::Connection;
+
+/// Body of a request creating a recurring bill template.
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NewRecurringBill {
+    pub name: String,
+    pub recipient_name: String,
+    pub recipient_iban: Option<String>,
+    pub category_id: Option<uuid::Uuid>,
+    pub expected_amount: i64,
+    pub cadence: Cadence,
+    pub starts_on: NaiveDate,
+    /// Defaults to the name of the template.
+    pub match_pattern: Option<String>,
+    #[serde(default = "default_tolerance_percent")]
+    pub tolerance_percent: i32,
+    #[serde(default = "default_grace_days")]
+    pub grace_days: i32,
+}
+
+fn default_tolerance_percent() -> i32 {
+    20
+}
+
+fn default_grace_days() -> i32 {
+    7
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum BillStatus {
+    /// Not there yet, but still in time.
+    Upcoming,
+    Received,
+    /// Not there after the grace period.
+    Missing,
+    /// Received with an amount above the tolerance of the template.
+    UnusuallyLarge,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct ForecastEntry {
+    pub expected_on: NaiveDate,
+    pub recurring_bill: RecurringBill,
+    pub status: BillStatus,
+    pub receipt: Option<Receipt>,
+}
+
+fn status(
+    template: &RecurringBill,
+    expected_on: NaiveDate,
+    receipt: Option<&Receipt>,
+    today: NaiveDate,
+) -> BillStatus {
+    match receipt {
+        Some(receipt) => match receipt.amount {
+            Some(amount) if template.is_unusually_large(amount) => {
+                BillStatus::UnusuallyLarge
+            },
+            _ => BillStatus::Received,
+        },
+        None => {
+            let grace = chrono::Duration::days(template.grace_days.into());
+            if expected_on + grace < today {
+                BillStatus::Missing
+            } else {
+                BillStatus::Upcoming
+            }
+        },
+    }
+}
+
+/// Creates the expected bills of `template` up to `until`. Bills are counted
+/// from the start of the template, so running this again only adds the
+/// missing ones.
+async fn generate_expected(
+    db: &DatabaseConnection,
+    template: &RecurringBill,
+    until: NaiveDate,
+) -> Result<(), DbErr> {
+    let mut n = expected_bill::Entity::find()
+        .filter(expected_bill::Column::RecurringBillId.eq(template.id))
+        .count(db)
+        .await? as u32;
+
+    loop {
+        let expected_on = template.cadence.nth(template.starts_on, n);
+        if expected_on > until {
+            return Ok(());
+        }
+        expected_bill::ActiveModel {
+            id: Set(uuid::Uuid::new_v4()),
+            recurring_bill_id: Set(template.id),
+            expected_on: Set(expected_on),
+            receipt_id: Set(None),
+        }
+        .insert(db)
+        .await?;
+        n += 1;
+    }
+}
+
+async fn active_templates(
+    db: &DatabaseConnection,
+) -> Result<Vec<RecurringBill>, DbErr> {
+    recurring_bill::Entity::find()
+        .filter(recurring_bill::Column::Active.eq(true))
+        .all(db)
+        .await
+}
+
+/// Links a new receipt to the open expected bill closest to today of the
+/// first template matching its name, and fills in the category and
+/// recipient of the template if the receipt has none yet. Expected bills
+/// more than half a period plus the grace days away are left open, so a late
+/// upload of an old bill doesn't take the place of another one.
+pub async fn match_receipt(
+    db: &DatabaseConnection,
+    receipt: Receipt,
+) -> Result<Receipt, DbErr> {
+    let template = match active_templates(db)
+        .await?
+        .into_iter()
+        .find(|template| template.matches(&receipt.name))
+    {
+        Some(template) => template,
+        None => return Ok(receipt),
+    };
+    let today = Local::today().naive_local();
+    // bills may arrive a little early
+    generate_expected(db, &template, template.cadence.nth(today, 1)).await?;
+
+    let period = (template.cadence.nth(today, 1) - today).num_days();
+    let window = period / 2 + i64::from(template.grace_days);
+    let expected = expected_bill::Entity::find()
+        .filter(expected_bill::Column::RecurringBillId.eq(template.id))
+        .filter(expected_bill::Column::ReceiptId.is_null())
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|expected| {
+            let distance = (expected.expected_on - today).num_days().abs();
+            (distance, expected)
+        })
+        .filter(|(distance, _)| *distance <= window)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, expected)| expected);
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return Ok(receipt),
+    };
+
+    let mut update_expected: expected_bill::ActiveModel = expected.into();
+    update_expected.receipt_id = Set(Some(receipt.id));
+    update_expected.update(db).await?;
+
+    if receipt.find_related(recipient::Entity).one(db).await?.is_none() {
+        recipient::ActiveModel {
+            id: Set(uuid::Uuid::new_v4()),
+            receipt_id: Set(receipt.id),
+            name: Set(template.recipient_name.clone()),
+            iban: Set(template.recipient_iban.clone().unwrap_or_default()),
+            address_line1: Set(String::new()),
+            address_line2: Set(String::new()),
+            address_line3: Set(String::new()),
+            address_line4: Set(String::new()),
+        }
+        .insert(db)
+        .await?;
+    }
+    if receipt.category_id.is_some() || template.category_id.is_none() {
+        return Ok(receipt);
+    }
+    let mut update_receipt: receipt::ActiveModel = receipt.into();
+    update_receipt.category_id = Set(template.category_id);
+    update_receipt.update(db).await
+}
+
+#[get("/")]
+pub async fn get_recurring_bills(
+    conn: Connection<'_, SQLDb>,
+) -> EndpointResult<Json<Vec<RecurringBill>>> {
+    let sql_db = conn.into_inner();
+    let templates = recurring_bill::Entity::find()
+        .order_by_asc(recurring_bill::Column::Name)
+        .all(sql_db)
+        .await?;
+    Ok(Json(templates))
+}
+
+#[post("/", data = "<new>")]
+pub async fn create_recurring_bill(
+    conn: Connection<'_, SQLDb>,
+    new: Json<NewRecurringBill>,
+) -> EndpointResult<Json<RecurringBill>> {
+    let sql_db = conn.into_inner();
+    let new = new.into_inner();
+    if new.expected_amount < 0 {
+        return Err(ReceiptError::Invalid(
+            "expected amount cannot be negative".into(),
+        ));
+    }
+    if new.tolerance_percent < 0 {
+        return Err(ReceiptError::Invalid(
+            "tolerance percent cannot be negative".into(),
+        ));
+    }
+    if new.grace_days < 0 {
+        return Err(ReceiptError::Invalid(
+            "grace days cannot be negative".into(),
+        ));
+    }
+    if let Some(category_id) = new.category_id {
+        if category::Entity::find_by_id(category_id)
+            .one(sql_db)
+            .await?
+            .is_none()
+        {
+            return Err(ReceiptError::Invalid(format!(
+                "category {} does not exist",
+                category_id
+            )));
+        }
+    }
+
+    let template = recurring_bill::ActiveModel {
+        id: Set(uuid::Uuid::new_v4()),
+        match_pattern: Set(new
+            .match_pattern
+            .unwrap_or_else(|| new.name.clone())),
+        name: Set(new.name),
+        recipient_name: Set(new.recipient_name),
+        recipient_iban: Set(new.recipient_iban),
+        category_id: Set(new.category_id),
+        expected_amount: Set(new.expected_amount),
+        cadence: Set(new.cadence),
+        starts_on: Set(new.starts_on),
+        tolerance_percent: Set(new.tolerance_percent),
+        grace_days: Set(new.grace_days),
+        active: Set(true),
+    }
+    .insert(sql_db)
+    .await?;
+    Ok(Json(template))
+}
+
+#[delete("/<id>")]
+pub async fn delete_recurring_bill(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<()> {
+    let sql_db = conn.into_inner();
+    let result = recurring_bill::Entity::delete_by_id(uuid_conversion(id)?)
+        .exec(sql_db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ReceiptError::NotFound);
+    }
+    Ok(())
+}
+
+/// How far into the future a forecast can reach.
+const MAX_FORECAST_DAYS: i64 = 731;
+
+/// Lists the bills expected between `from` and `until` with whether they
+/// arrived. Defaults to the last and the next 90 days. Bills no receipt was
+/// expected for yet are only computed, the forecast doesn't store anything.
+#[get("/forecast?<from>&<until>")]
+pub async fn get_forecast(
+    conn: Connection<'_, SQLDb>,
+    from: Option<DateParam>,
+    until: Option<DateParam>,
+) -> EndpointResult<Json<Vec<ForecastEntry>>> {
+    let sql_db = conn.into_inner();
+    let today = Local::today().naive_local();
+    let from = from.map_or(today - chrono::Duration::days(90), |d| d.0);
+    let until = until.map_or(today + chrono::Duration::days(90), |d| d.0);
+    if from > until {
+        return Err(ReceiptError::Invalid(format!(
+            "from {} is after until {}",
+            from, until
+        )));
+    }
+    if (until - today).num_days() > MAX_FORECAST_DAYS {
+        return Err(ReceiptError::Invalid(format!(
+            "forecasts reach at most {} days ahead",
+            MAX_FORECAST_DAYS
+        )));
+    }
+
+    let expected = expected_bill::Entity::find()
+        .filter(expected_bill::Column::ExpectedOn.between(from, until))
+        .order_by_asc(expected_bill::Column::ExpectedOn)
+        .find_also_related(recurring_bill::Entity)
+        .all(sql_db)
+        .await?;
+
+    let mut forecast = Vec::with_capacity(expected.len());
+    for (expected, template) in expected {
+        let template = match template {
+            Some(template) => template,
+            None => continue,
+        };
+        let receipt = match expected.receipt_id {
+            Some(id) => receipt::Entity::find_by_id(id).one(sql_db).await?,
+            None => None,
+        };
+        forecast.push(ForecastEntry {
+            expected_on: expected.expected_on,
+            status: status(
+                &template,
+                expected.expected_on,
+                receipt.as_ref(),
+                today,
+            ),
+            recurring_bill: template,
+            receipt,
+        });
+    }
+
+    for template in active_templates(sql_db).await? {
+        let generated = expected_bill::Entity::find()
+            .filter(expected_bill::Column::RecurringBillId.eq(template.id))
+            .count(sql_db)
+            .await? as u32;
+        let upcoming = (generated..)
+            .map(|n| template.cadence.nth(template.starts_on, n))
+            .skip_while(|expected_on| *expected_on < from)
+            .take_while(|expected_on| *expected_on <= until);
+        for expected_on in upcoming {
+            forecast.push(ForecastEntry {
+                expected_on,
+                status: status(&template, expected_on, None, today),
+                recurring_bill: template.clone(),
+                receipt: None,
+            });
+        }
+    }
+    forecast.sort_by_key(|entry| entry.expected_on);
+    Ok(Json(forecast))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2022, 8, day)
+    }
+
+    fn template() -> RecurringBill {
+        RecurringBill {
+            id: uuid::Uuid::new_v4(),
+            name: "Rent".to_string(),
+            recipient_name: "Landlord".to_string(),
+            recipient_iban: None,
+            category_id: Some(uuid::Uuid::new_v4()),
+            expected_amount: 100_000,
+            cadence: Cadence::Monthly,
+            starts_on: date(1),
+            match_pattern: "rent".to_string(),
+            tolerance_percent: 10,
+            grace_days: 5,
+            active: true,
+        }
+    }
+
+    fn receipt(amount: Option<i64>) -> Receipt {
+        Receipt {
+            amount,
+            ..Receipt::new(uuid::Uuid::new_v4(), "rent-august.pdf", "")
+        }
+    }
+
+    #[test]
+    fn flags_missing_and_large_bills() {
+        let template = template();
+        assert!(template.matches("RENT-August.pdf"));
+        assert!(!template.matches("power.pdf"));
+
+        assert_eq!(
+            status(&template, date(1), None, date(6)),
+            BillStatus::Upcoming
+        );
+        assert_eq!(
+            status(&template, date(1), None, date(7)),
+            BillStatus::Missing
+        );
+        assert_eq!(
+            status(&template, date(1), Some(&receipt(None)), date(7)),
+            BillStatus::Received
+        );
+        assert_eq!(
+            status(&template, date(1), Some(&receipt(Some(110_000))), date(7)),
+            BillStatus::Received
+        );
+        assert_eq!(
+            status(&template, date(1), Some(&receipt(Some(110_001))), date(7)),
+            BillStatus::UnusuallyLarge
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use super::receipts::EndpointResult;
+use super::DateParam;
+use crate::SQLDb;
+use chrono::NaiveDate;
+use entity::receipt::{self, ReceiptState};
+use rocket::http::ContentType;
+use rocket::request::FromParam;
+use rocket::serde::{json::Json, Serialize};
+use sea_orm::sea_query::{Expr, Order};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait,
+    FromQueryResult, JoinType, QueryFilter, QueryOrder, QuerySelect,
+    RelationTrait,
+};
+use sea_orm_rocket::Connection;
+
+/// What receipts are grouped by in a report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Category,
+    Recipient,
+    /// `YYYY-MM` of the payment date, or the due date if not payed yet.
+    Month,
+    State,
+    /// `paid` or `outstanding`.
+    Payment,
+}
+
+impl Dimension {
+    /// SQL expression of the group a receipt belongs to. Works on SQLite and
+    /// Postgres alike.
+    fn group_sql(&self) -> &'static str {
+        match self {
+            Dimension::Category => r#""categories"."name""#,
+            Dimension::Recipient => r#""recipients"."name""#,
+            Dimension::Month => {
+                r#"SUBSTR(CAST(COALESCE("receipts"."payment_date", "receipts"."due_date") AS TEXT), 1, 7)"#
+            },
+            Dimension::State => r#""receipts"."state""#,
+            Dimension::Payment => {
+                r#"CASE WHEN "receipts"."state" IN ('payed', 'done') THEN 'paid' ELSE 'outstanding' END"#
+            },
+        }
+    }
+}
+
+impl<'a> FromParam<'a> for Dimension {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        match param {
+            "categories" => Ok(Dimension::Category),
+            "recipients" => Ok(Dimension::Recipient),
+            "months" => Ok(Dimension::Month),
+            "states" => Ok(Dimension::State),
+            "payment" => Ok(Dimension::Payment),
+            _ => Err(param),
+        }
+    }
+}
+
+#[derive(FromFormField, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Serialize, FromQueryResult, Debug, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct ReportRow {
+    /// Name of the group, `None` for receipts without e.g. a category.
+    pub group: Option<String>,
+    pub receipts: i64,
+    /// Sum of the amounts in cents. Receipts without amount count as zero.
+    pub total: i64,
+}
+
+#[derive(Responder)]
+pub enum Report {
+    Json(Json<Vec<ReportRow>>),
+    Csv((ContentType, String)),
+}
+
+/// Receipts count for the day they were payed, or are due if not payed yet.
+pub(crate) fn in_range(
+    from: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Condition {
+    let between = |column: receipt::Column| {
+        let mut condition = Condition::all();
+        if let Some(from) = from {
+            condition = condition.add(column.gte(from));
+        }
+        if let Some(until) = until {
+            condition = condition.add(column.lte(until));
+        }
+        condition
+    };
+    Condition::any().add(between(receipt::Column::PaymentDate)).add(
+        Condition::all()
+            .add(receipt::Column::PaymentDate.is_null())
+            .add(between(receipt::Column::DueDate)),
+    )
+}
+
+/// Groups the receipts by `dimension` in the database. Declined receipts
+/// are left out, except when grouping by state.
+pub async fn report(
+    db: &DatabaseConnection,
+    dimension: Dimension,
+    from: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<Vec<ReportRow>, DbErr> {
+    let group = dimension.group_sql();
+    let mut query = receipt::Entity::find()
+        .select_only()
+        .column_as(Expr::cust(group), "group")
+        .column_as(Expr::cust("COUNT(*)"), "receipts")
+        .column_as(
+            Expr::cust(
+                r#"CAST(COALESCE(SUM("receipts"."amount"), 0) AS BIGINT)"#,
+            ),
+            "total",
+        )
+        .group_by(Expr::cust(group));
+
+    let join = match dimension {
+        Dimension::Category => Some(receipt::Relation::Category),
+        Dimension::Recipient => Some(receipt::Relation::Recipient),
+        _ => None,
+    };
+    if let Some(relation) = join {
+        query = query.join(JoinType::LeftJoin, relation.def());
+    }
+    if dimension != Dimension::State {
+        query = query.filter(receipt::Column::State.ne(ReceiptState::Declined));
+    }
+    if from.is_some() || until.is_some() {
+        query = query.filter(in_range(from, until));
+    }
+    // months are listed in order, everything else by the money spent
+    if dimension != Dimension::Month {
+        query = query.order_by(Expr::cust(r#""total""#), Order::Desc);
+    }
+    query
+        .order_by(Expr::cust(group), Order::Asc)
+        .into_model::<ReportRow>()
+        .all(db)
+        .await
+}
+
+fn to_csv(rows: &[ReportRow]) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Totals of the receipts per `dimension`, which is one of `categories`,
+/// `recipients`, `months`, `states` or `payment`, as JSON or CSV.
+#[get("/<dimension>?<from>&<until>&<format>")]
+pub async fn get_report(
+    conn: Connection<'_, SQLDb>,
+    dimension: Dimension,
+    from: Option<DateParam>,
+    until: Option<DateParam>,
+    format: Option<ReportFormat>,
+) -> EndpointResult<Report> {
+    let sql_db = conn.into_inner();
+    let rows = report(
+        sql_db,
+        dimension,
+        from.map(|date| date.0),
+        until.map(|date| date.0),
+    )
+    .await?;
+
+    match format.unwrap_or(ReportFormat::Json) {
+        ReportFormat::Json => Ok(Report::Json(Json(rows))),
+        ReportFormat::Csv => {
+            Ok(Report::Csv((ContentType::CSV, to_csv(&rows)?)))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_has_header_and_empty_groups() {
+        let rows = [
+            ReportRow {
+                group: Some("Food, drinks".to_string()),
+                receipts: 2,
+                total: 1250,
+            },
+            ReportRow {
+                group: None,
+                receipts: 1,
+                total: 0,
+            },
+        ];
+        assert_eq!(
+            to_csv(&rows).unwrap(),
+            "group,receipts,total\n\"Food, drinks\",2,1250\n,1,0\n"
+        );
+    }
+}
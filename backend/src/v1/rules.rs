@@ -0,0 +1,486 @@
+use super::receipts::{
+    change_state, uuid_conversion, EndpointResult, ReceiptError,
+};
+use crate::notifications::Notifier;
+use crate::SQLDb;
+use entity::category;
+use entity::receipt::{self, Model as Receipt, ReceiptState};
+use entity::recipient::{self, Model as Recipient};
+use entity::rule::{self, Model as Rule};
+use rocket::serde::uuid::Uuid;
+use rocket::serde::{json::Json, Deserialize};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    Iterable, ModelTrait, QueryFilter, QueryOrder, Set,
+};
+use sea_orm_rocket::Connection;
+
+/// Body of requests creating, replacing or previewing a rule.
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct RuleRequest {
+    pub name: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub recipient_iban: Option<String>,
+    pub recipient_name: Option<String>,
+    pub name_pattern: Option<String>,
+    pub text_contains: Option<String>,
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    pub set_category_id: Option<uuid::Uuid>,
+    pub set_recipient_name: Option<String>,
+    pub set_recipient_iban: Option<String>,
+    #[serde(default)]
+    pub auto_accept: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl RuleRequest {
+    fn into_rule(self, id: uuid::Uuid) -> Rule {
+        Rule {
+            id,
+            name: self.name,
+            priority: self.priority,
+            enabled: self.enabled,
+            recipient_iban: self.recipient_iban,
+            recipient_name: self.recipient_name,
+            name_pattern: self.name_pattern,
+            text_contains: self.text_contains,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            set_category_id: self.set_category_id,
+            set_recipient_name: self.set_recipient_name,
+            set_recipient_iban: self.set_recipient_iban,
+            auto_accept: self.auto_accept,
+        }
+    }
+}
+
+fn active_model(rule: Rule) -> rule::ActiveModel {
+    rule::ActiveModel {
+        id: Set(rule.id),
+        name: Set(rule.name),
+        priority: Set(rule.priority),
+        enabled: Set(rule.enabled),
+        recipient_iban: Set(rule.recipient_iban),
+        recipient_name: Set(rule.recipient_name),
+        name_pattern: Set(rule.name_pattern),
+        text_contains: Set(rule.text_contains),
+        min_amount: Set(rule.min_amount),
+        max_amount: Set(rule.max_amount),
+        set_category_id: Set(rule.set_category_id),
+        set_recipient_name: Set(rule.set_recipient_name),
+        set_recipient_iban: Set(rule.set_recipient_iban),
+        auto_accept: Set(rule.auto_accept),
+    }
+}
+
+/// Rejects rules without conditions, which would match every receipt, and
+/// rules without actions.
+async fn validate(db: &DatabaseConnection, rule: &Rule) -> EndpointResult<()> {
+    if rule.name.trim().is_empty() {
+        return Err(ReceiptError::Invalid("name must not be empty".into()));
+    }
+    let has_condition = rule.recipient_iban.is_some()
+        || rule.recipient_name.is_some()
+        || rule.name_pattern.is_some()
+        || rule.text_contains.is_some()
+        || rule.min_amount.is_some()
+        || rule.max_amount.is_some();
+    if !has_condition {
+        return Err(ReceiptError::Invalid(
+            "a rule needs at least one condition".into(),
+        ));
+    }
+    let has_action = rule.set_category_id.is_some()
+        || rule.set_recipient_name.is_some()
+        || rule.set_recipient_iban.is_some()
+        || rule.auto_accept;
+    if !has_action {
+        return Err(ReceiptError::Invalid(
+            "a rule needs at least one action".into(),
+        ));
+    }
+    if let (Some(min), Some(max)) = (rule.min_amount, rule.max_amount) {
+        if min > max {
+            return Err(ReceiptError::Invalid(
+                "min_amount is larger than max_amount".into(),
+            ));
+        }
+    }
+    if let Some(category_id) = rule.set_category_id {
+        if category::Entity::find_by_id(category_id).one(db).await?.is_none() {
+            return Err(ReceiptError::Invalid(format!(
+                "category {} does not exist",
+                category_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn normalize_iban(iban: &str) -> String {
+    iban.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Matches `name` against `pattern`, where `*` stands for any number of
+/// characters and `?` for exactly one. Case is ignored.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the name position it was tried at
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    p = star + 1;
+                    n = tried + 1;
+                    backtrack = Some((star, n));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Whether all conditions of `rule` that are set hold for `receipt`.
+fn matches(
+    rule: &Rule,
+    receipt: &Receipt,
+    recipient: Option<&Recipient>,
+) -> bool {
+    if let Some(iban) = &rule.recipient_iban {
+        let iban = normalize_iban(iban);
+        if !recipient.map_or(false, |r| normalize_iban(&r.iban) == iban) {
+            return false;
+        }
+    }
+    if let Some(name) = &rule.recipient_name {
+        if !recipient.map_or(false, |r| contains_ignore_case(&r.name, name)) {
+            return false;
+        }
+    }
+    if let Some(pattern) = &rule.name_pattern {
+        if !wildcard_match(pattern, &receipt.name) {
+            return false;
+        }
+    }
+    if let Some(text) = &rule.text_contains {
+        let content = receipt.content_text.as_deref().unwrap_or_default();
+        if !contains_ignore_case(content, text) {
+            return false;
+        }
+    }
+    if rule.min_amount.is_some() || rule.max_amount.is_some() {
+        let amount = match receipt.amount {
+            Some(amount) => amount,
+            None => return false,
+        };
+        if rule.min_amount.map_or(false, |min| amount < min)
+            || rule.max_amount.map_or(false, |max| amount > max)
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Runs the enabled rules on `receipt` in ascending priority. The first
+/// matching rule setting a field wins, and fields the receipt already has,
+/// e.g. from a recurring bill template, are kept. Accepted receipts move from
+/// the Inbox to valid. New receipts rarely have an amount or a recipient, so
+/// the rules run again once those are set. Payed and declined receipts are
+/// left alone.
+pub async fn apply_rules(
+    db: &DatabaseConnection,
+    notifier: &Notifier,
+    receipt: Receipt,
+) -> EndpointResult<Receipt> {
+    if !receipt.state.is_open() {
+        return Ok(receipt);
+    }
+    let rules = rule::Entity::find()
+        .filter(rule::Column::Enabled.eq(true))
+        .order_by_asc(rule::Column::Priority)
+        .order_by_asc(rule::Column::Name)
+        .all(db)
+        .await?;
+    let recipient = receipt.find_related(recipient::Entity).one(db).await?;
+
+    let (mut category_id, mut recipient_name, mut recipient_iban) =
+        (None, None, None);
+    let mut accept = false;
+    for rule in
+        rules.iter().filter(|rule| matches(rule, &receipt, recipient.as_ref()))
+    {
+        category_id = category_id.or(rule.set_category_id);
+        recipient_name = recipient_name.or(rule.set_recipient_name.clone());
+        recipient_iban = recipient_iban.or(rule.set_recipient_iban.clone());
+        accept |= rule.auto_accept;
+    }
+
+    if recipient.is_none()
+        && (recipient_name.is_some() || recipient_iban.is_some())
+    {
+        recipient::ActiveModel {
+            id: Set(uuid::Uuid::new_v4()),
+            receipt_id: Set(receipt.id),
+            name: Set(recipient_name.unwrap_or_default()),
+            iban: Set(recipient_iban.unwrap_or_default()),
+            address_line1: Set(String::new()),
+            address_line2: Set(String::new()),
+            address_line3: Set(String::new()),
+            address_line4: Set(String::new()),
+        }
+        .insert(db)
+        .await?;
+    }
+
+    let category_id = category_id.filter(|_| receipt.category_id.is_none());
+    let accept = accept && receipt.state == ReceiptState::Inbox;
+    let receipt = if category_id.is_some() {
+        let mut update: receipt::ActiveModel = receipt.into();
+        update.category_id = Set(category_id);
+        update.update(db).await?
+    } else {
+        receipt
+    };
+    if accept {
+        change_state(db, notifier, receipt, ReceiptState::Valid).await
+    } else {
+        Ok(receipt)
+    }
+}
+
+/// Lists the open receipts `rule` matches, ignoring whether it is enabled.
+/// Rules leave payed and declined receipts alone, so those are not affected.
+async fn preview(
+    db: &DatabaseConnection,
+    rule: &Rule,
+) -> Result<Vec<Receipt>, DbErr> {
+    let mut candidates = receipt::Entity::find().filter(
+        receipt::Column::State
+            .is_in(ReceiptState::iter().filter(ReceiptState::is_open)),
+    );
+    if let Some(min) = rule.min_amount {
+        candidates = candidates.filter(receipt::Column::Amount.gte(min));
+    }
+    if let Some(max) = rule.max_amount {
+        candidates = candidates.filter(receipt::Column::Amount.lte(max));
+    }
+    let receipts =
+        candidates.find_also_related(recipient::Entity).all(db).await?;
+    Ok(receipts
+        .into_iter()
+        .filter(|(receipt, recipient)| {
+            matches(rule, receipt, recipient.as_ref())
+        })
+        .map(|(receipt, _)| receipt)
+        .collect())
+}
+
+#[get("/")]
+pub async fn get_rules(
+    conn: Connection<'_, SQLDb>,
+) -> EndpointResult<Json<Vec<Rule>>> {
+    let sql_db = conn.into_inner();
+    let rules = rule::Entity::find()
+        .order_by_asc(rule::Column::Priority)
+        .order_by_asc(rule::Column::Name)
+        .all(sql_db)
+        .await?;
+    Ok(Json(rules))
+}
+
+#[get("/<id>")]
+pub async fn get_rule(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<Json<Rule>> {
+    let sql_db = conn.into_inner();
+    rule::Entity::find_by_id(uuid_conversion(id)?)
+        .one(sql_db)
+        .await?
+        .map(Json)
+        .ok_or(ReceiptError::NotFound)
+}
+
+#[post("/", data = "<request>")]
+pub async fn create_rule(
+    conn: Connection<'_, SQLDb>,
+    request: Json<RuleRequest>,
+) -> EndpointResult<Json<Rule>> {
+    let sql_db = conn.into_inner();
+    let rule = request.into_inner().into_rule(uuid::Uuid::new_v4());
+    validate(sql_db, &rule).await?;
+    Ok(Json(active_model(rule).insert(sql_db).await?))
+}
+
+#[put("/<id>", data = "<request>")]
+pub async fn update_rule(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+    request: Json<RuleRequest>,
+) -> EndpointResult<Json<Rule>> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+    if rule::Entity::find_by_id(id).one(sql_db).await?.is_none() {
+        return Err(ReceiptError::NotFound);
+    }
+    let rule = request.into_inner().into_rule(id);
+    validate(sql_db, &rule).await?;
+    Ok(Json(active_model(rule).update(sql_db).await?))
+}
+
+#[delete("/<id>")]
+pub async fn delete_rule(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<()> {
+    let sql_db = conn.into_inner();
+    let result =
+        rule::Entity::delete_by_id(uuid_conversion(id)?).exec(sql_db).await?;
+    if result.rows_affected == 0 {
+        return Err(ReceiptError::NotFound);
+    }
+    Ok(())
+}
+
+/// Dry run of a rule that is not stored yet.
+#[post("/preview", data = "<request>")]
+pub async fn preview_new_rule(
+    conn: Connection<'_, SQLDb>,
+    request: Json<RuleRequest>,
+) -> EndpointResult<Json<Vec<Receipt>>> {
+    let sql_db = conn.into_inner();
+    let rule = request.into_inner().into_rule(uuid::Uuid::nil());
+    validate(sql_db, &rule).await?;
+    Ok(Json(preview(sql_db, &rule).await?))
+}
+
+/// Dry run of a stored rule.
+#[get("/<id>/preview")]
+pub async fn preview_rule(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<Json<Vec<Receipt>>> {
+    let sql_db = conn.into_inner();
+    let rule = rule::Entity::find_by_id(uuid_conversion(id)?)
+        .one(sql_db)
+        .await?
+        .ok_or(ReceiptError::NotFound)?;
+    Ok(Json(preview(sql_db, &rule).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule() -> Rule {
+        RuleRequest {
+            name: "Power".to_string(),
+            priority: 0,
+            enabled: true,
+            recipient_iban: None,
+            recipient_name: None,
+            name_pattern: None,
+            text_contains: None,
+            min_amount: None,
+            max_amount: None,
+            set_category_id: None,
+            set_recipient_name: None,
+            set_recipient_iban: None,
+            auto_accept: true,
+        }
+        .into_rule(uuid::Uuid::new_v4())
+    }
+
+    fn receipt() -> Receipt {
+        Receipt {
+            amount: Some(8_450),
+            content_text: Some("Invoice for Customer 4711".to_string()),
+            ..Receipt::new(uuid::Uuid::new_v4(), "Stadtwerke-2022-08.pdf", "")
+        }
+    }
+
+    fn recipient(receipt: &Receipt) -> Recipient {
+        Recipient {
+            id: uuid::Uuid::new_v4(),
+            receipt_id: receipt.id,
+            name: "Stadtwerke Musterstadt".to_string(),
+            iban: "DE89370400440532013000".to_string(),
+            address_line1: String::new(),
+            address_line2: String::new(),
+            address_line3: String::new(),
+            address_line4: String::new(),
+        }
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("stadtwerke-*.pdf", "Stadtwerke-2022-08.pdf"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(wildcard_match("rent-??.pdf", "rent-08.pdf"));
+        assert!(!wildcard_match("rent-??.pdf", "rent-8.pdf"));
+        assert!(!wildcard_match("*.pdf", "scan.png"));
+        assert!(!wildcard_match("a*b", "ab-"));
+    }
+
+    #[test]
+    fn all_set_conditions_must_match() {
+        let receipt = receipt();
+        let recipient = recipient(&receipt);
+
+        let mut by_iban = rule();
+        by_iban.recipient_iban = Some("de89 3704 0044 0532 0130 00".into());
+        assert!(matches(&by_iban, &receipt, Some(&recipient)));
+        assert!(!matches(&by_iban, &receipt, None));
+
+        let mut by_text = rule();
+        by_text.recipient_name = Some("stadtwerke".into());
+        by_text.text_contains = Some("customer 4711".into());
+        assert!(matches(&by_text, &receipt, Some(&recipient)));
+        by_text.text_contains = Some("customer 42".into());
+        assert!(!matches(&by_text, &receipt, Some(&recipient)));
+
+        let mut by_amount = rule();
+        by_amount.name_pattern = Some("stadtwerke-*".into());
+        by_amount.min_amount = Some(5_000);
+        by_amount.max_amount = Some(10_000);
+        assert!(matches(&by_amount, &receipt, None));
+        by_amount.max_amount = Some(8_000);
+        assert!(!matches(&by_amount, &receipt, None));
+        let without_amount = Receipt {
+            amount: None,
+            ..receipt
+        };
+        by_amount.max_amount = None;
+        assert!(!matches(&by_amount, &without_amount, None));
+    }
+}
@@ -0,0 +1,474 @@
+use super::receipts::{uuid_conversion, EndpointResult, ReceiptError};
+use super::reports::in_range;
+use super::DateParam;
+use crate::SQLDb;
+use entity::allocation::{self, Model as Allocation};
+use entity::receipt::{self, Model as Receipt, ReceiptState};
+use rocket::serde::uuid::Uuid;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use sea_orm_rocket::Connection;
+use std::collections::{BTreeMap, HashSet};
+
+/// Basis points of the whole total.
+const WHOLE: i64 = 10_000;
+
+/// Shares of `total` in cents, in the order of `allocations`. Percentages
+/// are rounded down and the cents left over go to the largest remainders,
+/// so the shares always add up to the total. Computed in `i128`, as the sum
+/// of client supplied amounts must not overflow.
+pub fn shares(
+    total: i64,
+    allocations: &[Allocation],
+) -> Result<Vec<i64>, String> {
+    let whole = i128::from(WHOLE);
+    let total = i128::from(total);
+    let mut fixed = 0;
+    let mut basis_points = 0;
+    for allocation in allocations {
+        match (allocation.amount, allocation.basis_points) {
+            (Some(amount), None) if amount >= 0 => fixed += i128::from(amount),
+            (None, Some(points))
+                if points > 0 && i64::from(points) <= WHOLE =>
+            {
+                basis_points += i128::from(points)
+            },
+            _ => {
+                return Err(format!(
+                    "share of {} needs either an amount of at least 0 or \
+                     basis points between 1 and {}",
+                    allocation.party, WHOLE
+                ))
+            },
+        }
+    }
+    if fixed * whole + total * basis_points != total * whole {
+        return Err(format!(
+            "allocations do not add up to the total of {} cents",
+            total
+        ));
+    }
+
+    let exact: Vec<i128> = allocations
+        .iter()
+        .map(|allocation| {
+            i128::from(allocation.basis_points.unwrap_or(0)) * total
+        })
+        .collect();
+    let mut shares: Vec<i128> = allocations
+        .iter()
+        .zip(&exact)
+        .map(|(allocation, exact)| {
+            allocation.amount.map_or(exact / whole, i128::from)
+        })
+        .collect();
+    let left_over = total - shares.iter().sum::<i128>();
+    let mut by_remainder: Vec<usize> = (0..allocations.len())
+        .filter(|&index| allocations[index].basis_points.is_some())
+        .collect();
+    // stable, so equal remainders are rounded up in order
+    by_remainder.sort_by_key(|&index| -(exact[index] % whole));
+    for &index in by_remainder.iter().take(left_over as usize) {
+        shares[index] += 1;
+    }
+    // as they add up to the total, every share fits
+    Ok(shares
+        .into_iter()
+        .map(|share| i64::try_from(share).expect("share above the total"))
+        .collect())
+}
+
+/// How a receipt is split, with the share of every allocation in cents.
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Split {
+    pub paid_by: Option<String>,
+    pub allocations: Vec<Share>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Share {
+    #[serde(flatten)]
+    pub allocation: Allocation,
+    /// `None` if the allocations do not add up to the amount of the receipt
+    /// anymore, e.g. after it was corrected.
+    pub share: Option<i64>,
+}
+
+fn split(receipt: &Receipt, allocations: Vec<Allocation>) -> Split {
+    let shares = receipt
+        .amount
+        .and_then(|total| shares(total, &allocations).ok())
+        .map(|shares| shares.into_iter().map(Some).collect())
+        .unwrap_or_else(|| vec![None; allocations.len()]);
+    Split {
+        paid_by: receipt.paid_by.clone(),
+        allocations: allocations
+            .into_iter()
+            .zip(shares)
+            .map(|(allocation, share)| Share {
+                allocation,
+                share,
+            })
+            .collect(),
+    }
+}
+
+async fn find_receipt(
+    sql_db: &DatabaseConnection,
+    id: uuid::Uuid,
+) -> EndpointResult<Receipt> {
+    receipt::Entity::find_by_id(id)
+        .one(sql_db)
+        .await?
+        .ok_or(ReceiptError::NotFound)
+}
+
+async fn find_allocations(
+    sql_db: &DatabaseConnection,
+    id: uuid::Uuid,
+) -> EndpointResult<Vec<Allocation>> {
+    Ok(allocation::Entity::find()
+        .filter(allocation::Column::ReceiptId.eq(id))
+        .order_by_asc(allocation::Column::Party)
+        .all(sql_db)
+        .await?)
+}
+
+/// The split of a receipt. Ranked after routes like `/download/<id>`, which
+/// match the same paths.
+#[get("/<id>/split", rank = 2)]
+pub async fn get_split(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<Json<Split>> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+    let receipt = find_receipt(sql_db, id).await?;
+    let allocations = find_allocations(sql_db, id).await?;
+    Ok(Json(split(&receipt, allocations)))
+}
+
+/// One allocation of a [`SplitRequest`], by amount or percentage.
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct AllocationRequest {
+    pub party: String,
+    /// Fixed share in cents.
+    #[serde(default)]
+    pub amount: Option<i64>,
+    /// Share of the total in hundredths of a percent.
+    #[serde(default)]
+    pub basis_points: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct SplitRequest {
+    pub paid_by: String,
+    pub allocations: Vec<AllocationRequest>,
+}
+
+/// Splits a receipt among people or cost centers, replacing how it was
+/// split before. The shares have to add up to the amount of the receipt.
+#[put("/<id>/split", data = "<request>")]
+pub async fn put_split(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+    request: Json<SplitRequest>,
+) -> EndpointResult<Json<Split>> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+    let receipt = find_receipt(sql_db, id).await?;
+    let request = request.into_inner();
+
+    let total = receipt.amount.ok_or_else(|| {
+        ReceiptError::Invalid("receipt needs an amount to be split".into())
+    })?;
+    let paid_by = request.paid_by.trim().to_string();
+    if paid_by.is_empty() || request.allocations.is_empty() {
+        return Err(ReceiptError::Invalid(
+            "split needs a payer and allocations".into(),
+        ));
+    }
+    let mut parties = HashSet::new();
+    let allocations: Vec<Allocation> = request
+        .allocations
+        .into_iter()
+        .map(|allocation| Allocation {
+            id: uuid::Uuid::new_v4(),
+            receipt_id: id,
+            party: allocation.party.trim().to_string(),
+            amount: allocation.amount,
+            basis_points: allocation.basis_points,
+        })
+        .collect();
+    for allocation in &allocations {
+        if allocation.party.is_empty() || !parties.insert(&allocation.party) {
+            return Err(ReceiptError::Invalid(format!(
+                "party {:?} is empty or allocated twice",
+                allocation.party
+            )));
+        }
+    }
+    shares(total, &allocations).map_err(ReceiptError::Invalid)?;
+
+    let txn = sql_db.begin().await?;
+    allocation::Entity::delete_many()
+        .filter(allocation::Column::ReceiptId.eq(id))
+        .exec(&txn)
+        .await?;
+    allocation::Entity::insert_many(
+        allocations.iter().cloned().map(allocation::ActiveModel::from),
+    )
+    .exec(&txn)
+    .await?;
+    let mut update: receipt::ActiveModel = receipt.into();
+    update.paid_by = Set(Some(paid_by));
+    let receipt = update.update(&txn).await?;
+    txn.commit().await?;
+
+    let allocations = find_allocations(sql_db, id).await?;
+    Ok(Json(split(&receipt, allocations)))
+}
+
+/// Stops splitting a receipt.
+#[delete("/<id>/split")]
+pub async fn delete_split(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<()> {
+    let sql_db = conn.into_inner();
+    let id = uuid_conversion(id)?;
+    let receipt = find_receipt(sql_db, id).await?;
+
+    let txn = sql_db.begin().await?;
+    allocation::Entity::delete_many()
+        .filter(allocation::Column::ReceiptId.eq(id))
+        .exec(&txn)
+        .await?;
+    let mut update: receipt::ActiveModel = receipt.into();
+    update.paid_by = Set(None);
+    update.update(&txn).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    /// Cents.
+    pub amount: i64,
+}
+
+/// Who owes whom across the payed split receipts.
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct SettleUp {
+    /// Cents every party is owed, negative if it owes money.
+    pub balances: BTreeMap<String, i64>,
+    /// The transfers that settle the balances.
+    pub transfers: Vec<Transfer>,
+    /// Split receipts left out as their allocations do not add up to their
+    /// amount anymore.
+    pub unbalanced: Vec<uuid::Uuid>,
+}
+
+/// Few transfers settling `balances`: the largest debt is always payed to
+/// the one owed most, until nothing is left.
+fn transfers(balances: &BTreeMap<String, i64>) -> Vec<Transfer> {
+    let mut creditors: Vec<(&String, i64)> = balances
+        .iter()
+        .filter(|(_, &balance)| balance > 0)
+        .map(|(party, &balance)| (party, balance))
+        .collect();
+    let mut debtors: Vec<(&String, i64)> = balances
+        .iter()
+        .filter(|(_, &balance)| balance < 0)
+        .map(|(party, &balance)| (party, -balance))
+        .collect();
+    creditors.sort_by_key(|&(_, owed)| -owed);
+    debtors.sort_by_key(|&(_, owes)| -owes);
+
+    let mut transfers = Vec::new();
+    let (mut creditor, mut debtor) = (0, 0);
+    while creditor < creditors.len() && debtor < debtors.len() {
+        let amount = creditors[creditor].1.min(debtors[debtor].1);
+        transfers.push(Transfer {
+            from: debtors[debtor].0.clone(),
+            to: creditors[creditor].0.clone(),
+            amount,
+        });
+        creditors[creditor].1 -= amount;
+        debtors[debtor].1 -= amount;
+        if creditors[creditor].1 == 0 {
+            creditor += 1;
+        }
+        if debtors[debtor].1 == 0 {
+            debtor += 1;
+        }
+    }
+    transfers
+}
+
+/// Balances and transfers of the split receipts payed between `from` and
+/// `until`.
+pub async fn settle_up(
+    sql_db: &DatabaseConnection,
+    from: Option<chrono::NaiveDate>,
+    until: Option<chrono::NaiveDate>,
+) -> EndpointResult<SettleUp> {
+    let mut query = receipt::Entity::find()
+        .find_with_related(allocation::Entity)
+        .filter(
+            receipt::Column::State
+                .is_in([ReceiptState::Payed, ReceiptState::Done]),
+        )
+        .filter(receipt::Column::PaidBy.is_not_null());
+    if from.is_some() || until.is_some() {
+        query = query.filter(in_range(from, until));
+    }
+    let receipts = query.all(sql_db).await?;
+
+    let mut balances = BTreeMap::new();
+    let mut unbalanced = Vec::new();
+    for (receipt, allocations) in receipts {
+        let (paid_by, total) = match (&receipt.paid_by, receipt.amount) {
+            (Some(paid_by), Some(total)) if !allocations.is_empty() => {
+                (paid_by, total)
+            },
+            _ => continue,
+        };
+        let shares = match shares(total, &allocations) {
+            Ok(shares) => shares,
+            Err(_) => {
+                unbalanced.push(receipt.id);
+                continue;
+            },
+        };
+        let too_large =
+            || ReceiptError::Invalid("balances are too large to add".into());
+        let balance = balances.entry(paid_by.clone()).or_insert(0i64);
+        *balance = balance.checked_add(total).ok_or_else(too_large)?;
+        for (allocation, share) in allocations.into_iter().zip(shares) {
+            let balance = balances.entry(allocation.party).or_insert(0);
+            *balance = balance.checked_sub(share).ok_or_else(too_large)?;
+        }
+    }
+    Ok(SettleUp {
+        transfers: transfers(&balances),
+        balances,
+        unbalanced,
+    })
+}
+
+/// Who owes whom for the split receipts payed in the given time, if any.
+#[get("/settle-up?<from>&<until>")]
+pub async fn get_settle_up(
+    conn: Connection<'_, SQLDb>,
+    from: Option<DateParam>,
+    until: Option<DateParam>,
+) -> EndpointResult<Json<SettleUp>> {
+    let sql_db = conn.into_inner();
+    let report =
+        settle_up(sql_db, from.map(|date| date.0), until.map(|date| date.0))
+            .await?;
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocation(
+        party: &str,
+        amount: Option<i64>,
+        basis_points: Option<i32>,
+    ) -> Allocation {
+        Allocation {
+            id: uuid::Uuid::new_v4(),
+            receipt_id: uuid::Uuid::nil(),
+            party: party.to_string(),
+            amount,
+            basis_points,
+        }
+    }
+
+    #[test]
+    fn percentages_are_rounded_to_the_total() {
+        let thirds = [
+            allocation("Anna", None, Some(3334)),
+            allocation("Ben", None, Some(3333)),
+            allocation("Household", None, Some(3333)),
+        ];
+        assert_eq!(shares(100, &thirds).unwrap(), vec![34, 33, 33]);
+        assert_eq!(shares(200, &thirds).unwrap(), vec![67, 67, 66]);
+
+        let mixed = [
+            allocation("Anna", Some(1000), None),
+            allocation("Ben", None, Some(7500)),
+        ];
+        assert_eq!(shares(4000, &mixed).unwrap(), vec![1000, 3000]);
+    }
+
+    #[test]
+    fn allocations_have_to_add_up() {
+        let halves = [
+            allocation("Anna", None, Some(5000)),
+            allocation("Ben", None, None),
+        ];
+        assert!(shares(100, &halves).is_err());
+        let short = [
+            allocation("Anna", Some(40), None),
+            allocation("Ben", None, Some(5000)),
+        ];
+        assert!(shares(100, &short).is_err());
+        let both = [allocation("Anna", Some(100), Some(10_000))];
+        assert!(shares(100, &both).is_err());
+    }
+
+    #[test]
+    fn huge_amounts_do_not_overflow() {
+        let huge = [
+            allocation("Anna", Some(i64::MAX), None),
+            allocation("Ben", Some(i64::MAX), None),
+        ];
+        assert!(shares(100, &huge).is_err());
+        let half = [
+            allocation("Anna", None, Some(5000)),
+            allocation("Ben", None, Some(5000)),
+        ];
+        assert_eq!(
+            shares(i64::MAX, &half).unwrap(),
+            vec![i64::MAX / 2 + 1, i64::MAX / 2]
+        );
+    }
+
+    #[test]
+    fn largest_debts_are_settled_first() {
+        let balances: BTreeMap<String, i64> =
+            [("Anna", 6000), ("Ben", -4000), ("Cleo", -1500), ("Dan", -500)]
+                .into_iter()
+                .map(|(party, balance)| (party.to_string(), balance))
+                .collect();
+        let transfer = |from: &str, amount| Transfer {
+            from: from.to_string(),
+            to: "Anna".to_string(),
+            amount,
+        };
+        assert_eq!(
+            transfers(&balances),
+            vec![
+                transfer("Ben", 4000),
+                transfer("Cleo", 1500),
+                transfer("Dan", 500)
+            ]
+        );
+    }
+}
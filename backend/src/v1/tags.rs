@@ -0,0 +1,158 @@
+use super::receipts::{uuid_conversion, EndpointResult, ReceiptError};
+use crate::SQLDb;
+use entity::receipt::{self, Model as Receipt};
+use entity::receipt_tag;
+use entity::tag::{self, Model as Tag};
+use rocket::serde::json::Json;
+use rocket::serde::uuid::Uuid;
+use sea_orm::sea_query::Query;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr,
+    EntityTrait, ModelTrait, QueryFilter, QueryOrder, Set,
+};
+use sea_orm_rocket::Connection;
+
+/// Tags are compared without surrounding whitespace and case.
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Condition selecting the receipts carrying all `names`, or `None` if one
+/// of the tags does not exist, so no receipt can match.
+pub(crate) async fn has_tags(
+    db: &DatabaseConnection,
+    names: &[String],
+) -> Result<Option<Condition>, DbErr> {
+    let mut condition = Condition::all();
+    for name in names {
+        let tag = tag::Entity::find()
+            .filter(tag::Column::Name.eq(normalize(name)))
+            .one(db)
+            .await?;
+        let tag_id = match tag {
+            Some(tag) => tag.id,
+            None => return Ok(None),
+        };
+        condition = condition.add(
+            receipt::Column::Id.in_subquery(
+                Query::select()
+                    .column(receipt_tag::Column::ReceiptId)
+                    .from(receipt_tag::Entity)
+                    .and_where(receipt_tag::Column::TagId.eq(tag_id))
+                    .to_owned(),
+            ),
+        );
+    }
+    Ok(Some(condition))
+}
+
+async fn find_receipt(
+    db: &DatabaseConnection,
+    id: Uuid,
+) -> EndpointResult<Receipt> {
+    receipt::Entity::find_by_id(uuid_conversion(id)?)
+        .one(db)
+        .await?
+        .ok_or(ReceiptError::NotFound)
+}
+
+async fn tags_of(
+    db: &DatabaseConnection,
+    receipt: &Receipt,
+) -> Result<Vec<Tag>, DbErr> {
+    receipt
+        .find_related(tag::Entity)
+        .order_by_asc(tag::Column::Name)
+        .all(db)
+        .await
+}
+
+/// Lists every tag in use.
+#[get("/tags")]
+pub async fn get_tags(
+    conn: Connection<'_, SQLDb>,
+) -> EndpointResult<Json<Vec<Tag>>> {
+    let sql_db = conn.into_inner();
+    let tags =
+        tag::Entity::find().order_by_asc(tag::Column::Name).all(sql_db).await?;
+    Ok(Json(tags))
+}
+
+/// The tags of a receipt. Ranked after routes like `/download/<id>`, which
+/// match the same paths.
+#[get("/<id>/tags", rank = 2)]
+pub async fn get_receipt_tags(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+) -> EndpointResult<Json<Vec<Tag>>> {
+    let sql_db = conn.into_inner();
+    let receipt = find_receipt(sql_db, id).await?;
+    Ok(Json(tags_of(sql_db, &receipt).await?))
+}
+
+/// Tags a receipt, creating the tag if it is new. Tagging twice is fine.
+#[put("/<id>/tags/<name>")]
+pub async fn add_receipt_tag(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+    name: &str,
+) -> EndpointResult<Json<Vec<Tag>>> {
+    let sql_db = conn.into_inner();
+    let receipt = find_receipt(sql_db, id).await?;
+    let name = normalize(name);
+    if name.is_empty() {
+        return Err(ReceiptError::Invalid("tag must not be empty".into()));
+    }
+
+    let tag = tag::Entity::find()
+        .filter(tag::Column::Name.eq(name.as_str()))
+        .one(sql_db)
+        .await?;
+    let tag = match tag {
+        Some(tag) => tag,
+        None => {
+            tag::ActiveModel {
+                id: Set(uuid::Uuid::new_v4()),
+                name: Set(name),
+            }
+            .insert(sql_db)
+            .await?
+        },
+    };
+    let tagged = receipt_tag::Entity::find_by_id((receipt.id, tag.id))
+        .one(sql_db)
+        .await?;
+    if tagged.is_none() {
+        receipt_tag::ActiveModel {
+            receipt_id: Set(receipt.id),
+            tag_id: Set(tag.id),
+        }
+        .insert(sql_db)
+        .await?;
+    }
+    Ok(Json(tags_of(sql_db, &receipt).await?))
+}
+
+/// Removes a tag from a receipt. The tag itself stays for other receipts.
+#[delete("/<id>/tags/<name>")]
+pub async fn remove_receipt_tag(
+    conn: Connection<'_, SQLDb>,
+    id: Uuid,
+    name: &str,
+) -> EndpointResult<Json<Vec<Tag>>> {
+    let sql_db = conn.into_inner();
+    let receipt = find_receipt(sql_db, id).await?;
+    let tag = tag::Entity::find()
+        .filter(tag::Column::Name.eq(normalize(name)))
+        .one(sql_db)
+        .await?
+        .ok_or(ReceiptError::NotFound)?;
+
+    let result = receipt_tag::Entity::delete_by_id((receipt.id, tag.id))
+        .exec(sql_db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ReceiptError::NotFound);
+    }
+    Ok(Json(tags_of(sql_db, &receipt).await?))
+}
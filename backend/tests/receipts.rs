@@ -0,0 +1,1356 @@
+//! End to end tests of the receipts routes against an in-process Rocket.
+//!
+//! Every test boots its own instance with a fresh sled directory and an
+//! in-memory SQLite database, so run them with
+//! `cargo test -p backend --features sqlite`. Set
+//! `EXPENSEBILLS_TEST_DATABASE_URL` to run them against another database.
+#![cfg(feature = "sqlite")]
+
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::serde::json::{json, Value};
+use rocket::tokio::io::AsyncReadExt;
+use std::io::Write;
+use std::path::PathBuf;
+
+const BOUNDARY: &str = "expensebills-test-boundary";
+
+struct TestApp {
+    client: Client,
+    root: PathBuf,
+}
+
+impl TestApp {
+    async fn new() -> Self {
+        Self::with(|figment| figment).await
+    }
+
+    /// Boots an instance with the configuration changed by `configure`.
+    async fn with(configure: impl FnOnce(Figment) -> Figment) -> Self {
+        let root = std::env::temp_dir()
+            .join(format!("expensebills-test-{}", uuid::Uuid::new_v4()));
+        let temp_dir = root.join("tempdir");
+        std::fs::create_dir_all(&temp_dir).expect("create temp dir");
+
+        let url = std::env::var("EXPENSEBILLS_TEST_DATABASE_URL")
+            .unwrap_or_else(|_| "sqlite::memory:".to_string());
+        let figment = rocket::Config::figment()
+            .merge(("temp_dir", temp_dir))
+            .merge(("databases.sea_orm.url", url))
+            // sqlite::memory: opens a new database for every connection
+            .merge(("databases.sea_orm.max_connections", 1))
+            .merge(("auto_migrate", true));
+
+        let client = Client::tracked(backend::rocket_with(configure(figment)))
+            .await
+            .expect("valid rocket instance");
+        TestApp {
+            client,
+            root,
+        }
+    }
+
+    async fn upload(&self, name: &str, content: &[u8]) -> LocalResponse<'_> {
+        let mut body = Vec::new();
+        write!(
+            body,
+            "--{b}\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\n\
+             {name}\r\n--{b}\r\nContent-Disposition: form-data; \
+             name=\"file\"; filename=\"{name}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            b = BOUNDARY,
+            name = name
+        )
+        .unwrap();
+        body.extend_from_slice(content);
+        write!(body, "\r\n--{}--\r\n", BOUNDARY).unwrap();
+
+        self.client
+            .post("/api/v1/receipts/upload")
+            .header(
+                ContentType::new("multipart", "form-data")
+                    .with_params(("boundary", BOUNDARY)),
+            )
+            .body(body)
+            .dispatch()
+            .await
+    }
+
+    /// Uploads the files of a batch and returns what became of them.
+    async fn upload_batch(&self, files: &[(&str, &[u8])]) -> Vec<Value> {
+        let mut body = Vec::new();
+        for (name, content) in files {
+            write!(
+                body,
+                "--{}\r\nContent-Disposition: form-data; name=\"files\"; \
+                 filename=\"{}\"\r\n\
+                 Content-Type: application/octet-stream\r\n\r\n",
+                BOUNDARY, name
+            )
+            .unwrap();
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        write!(body, "--{}--\r\n", BOUNDARY).unwrap();
+
+        let response = self
+            .client
+            .post("/api/v1/receipts/upload/batch")
+            .header(
+                ContentType::new("multipart", "form-data")
+                    .with_params(("boundary", BOUNDARY)),
+            )
+            .body(body)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let items: Value = response.into_json().await.expect("batch json");
+        items.as_array().expect("list of items").clone()
+    }
+
+    /// Uploads a file and returns the created receipt.
+    async fn create(&self, name: &str, content: &[u8]) -> Value {
+        let response = self.upload(name, content).await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().await.expect("receipt json")
+    }
+
+    async fn act(&self, id: &Value, action: Value) -> Value {
+        let response = self
+            .client
+            .post(format!("/api/v1/receipts/{}", id.as_str().unwrap()))
+            .header(ContentType::JSON)
+            .body(action.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().await.expect("action answer json")
+    }
+
+    async fn get_json(&self, uri: String) -> Value {
+        let response = self.client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().await.expect("json body")
+    }
+
+    async fn create_category(&self, category: Value) -> Value {
+        let response = self
+            .client
+            .post("/api/v1/categories")
+            .header(ContentType::JSON)
+            .body(category.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().await.expect("category json")
+    }
+
+    async fn box_ids(&self, state: &str) -> Vec<Value> {
+        self.get_json(format!("/api/v1/receipts/box/{}", state))
+            .await
+            .as_array()
+            .expect("list of receipts")
+            .iter()
+            .map(|receipt| receipt["id"].clone())
+            .collect()
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[rocket::async_test]
+async fn upload_creates_inbox_receipt() {
+    let app = TestApp::new().await;
+    let receipt = app.create("power.pdf", b"%PDF-1.4 power bill").await;
+
+    assert_eq!(receipt["name"], "power.pdf");
+    assert_eq!(receipt["state"], "Inbox");
+    assert_eq!(
+        receipt["file_hash"],
+        sha256::digest_bytes(b"%PDF-1.4 power bill")
+    );
+    assert!(app.box_ids("inbox").await.contains(&receipt["id"]));
+}
+
+#[rocket::async_test]
+async fn upload_without_file_is_rejected() {
+    let app = TestApp::new().await;
+    let response = app
+        .client
+        .post("/api/v1/receipts/upload")
+        .header(ContentType::Form)
+        .body("name=missing")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn download_returns_uploaded_file() {
+    let app = TestApp::new().await;
+    let receipt = app.create("scan.png", b"\x89PNG\r\n\x1a\n scan").await;
+
+    let response = app
+        .client
+        .get(format!(
+            "/api/v1/receipts/download/{}",
+            receipt["id"].as_str().unwrap()
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::Binary));
+    assert_eq!(
+        response.into_bytes().await.unwrap(),
+        b"\x89PNG\r\n\x1a\n scan".to_vec()
+    );
+}
+
+#[rocket::async_test]
+async fn previews_are_served_for_images() {
+    let app = TestApp::new().await;
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(800, 600))
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .unwrap();
+    let scan = app.create("scan.png", png.get_ref()).await;
+    let note = app.create("note.txt", b"call the landlord").await;
+    let preview = |receipt: &Value| {
+        format!("/api/v1/receipts/preview/{}", receipt["id"].as_str().unwrap())
+    };
+
+    // the second request is answered from the cache
+    for _ in 0..2 {
+        let response = app.client.get(preview(&scan)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JPEG));
+        let jpeg = response.into_bytes().await.unwrap();
+        let thumbnail = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 192));
+    }
+
+    let response = app.client.get(preview(&note)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    let response = app
+        .client
+        .get(format!("/api/v1/receipts/preview/{}", uuid::Uuid::new_v4()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn uploads_are_checked_before_storing() {
+    let app = TestApp::with(|figment| {
+        figment.merge(("uploads.max_size", 16)).merge(("limits.file", 32))
+    })
+    .await;
+
+    let response = app.upload("bill.exe", b"MZ\x90\0\x03\0").await;
+    assert_eq!(response.status(), Status::UnsupportedMediaType);
+    let response = app.upload("long.txt", &[b'a'; 20]).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    // cut off by Rocket before it reaches the size check
+    let response = app.upload("longer.txt", &[b'a'; 64]).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    assert!(app.box_ids("inbox").await.is_empty());
+    app.create("rent.txt", b"rent").await;
+}
+
+/// Answers like clamd, finding the EICAR test string.
+async fn clamd_stub() -> std::net::SocketAddr {
+    use rocket::tokio::io::AsyncWriteExt;
+    use rocket::tokio::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    rocket::tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut command = [0; 10];
+            stream.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+            let mut content = Vec::new();
+            loop {
+                let len = stream.read_u32().await.unwrap() as usize;
+                if len == 0 {
+                    break;
+                }
+                let mut chunk = vec![0; len];
+                stream.read_exact(&mut chunk).await.unwrap();
+                content.extend(chunk);
+            }
+            let reply: &[u8] = if content.starts_with(b"X5O!P%@AP") {
+                b"stream: Eicar-Signature FOUND\0"
+            } else {
+                b"stream: OK\0"
+            };
+            stream.write_all(reply).await.unwrap();
+        }
+    });
+    address
+}
+
+#[rocket::async_test]
+async fn infected_uploads_are_rejected() {
+    let clamd = clamd_stub().await;
+    let app = TestApp::with(|figment| {
+        figment.merge(("uploads.scanner.clamd", clamd.to_string()))
+    })
+    .await;
+
+    let response =
+        app.upload("eicar.txt", b"X5O!P%@AP[4\\PZX54(P^)7CC)7}").await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(
+        response.into_string().await.unwrap(),
+        "file is infected with Eicar-Signature"
+    );
+    app.create("rent.txt", b"rent").await;
+    assert_eq!(app.box_ids("inbox").await.len(), 1);
+}
+
+#[rocket::async_test]
+async fn batches_of_files_and_zips_are_uploaded() {
+    use zip::write::{FileOptions, ZipWriter};
+
+    let app = TestApp::new().await;
+    let rent = app.create("rent.pdf", b"%PDF-1.4 rent").await;
+
+    let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let entries: [(&str, &[u8]); 3] = [
+        ("2022/water.txt", b"water for august"),
+        ("__MACOSX/2022/._water.txt", b"resource fork"),
+        ("gas.png", b"\x89PNG\r\n\x1a\n\0\0"),
+    ];
+    for (path, content) in entries {
+        zip.start_file(path, FileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    let zip = zip.finish().unwrap().into_inner();
+
+    let items = app
+        .upload_batch(&[
+            ("power.pdf", b"%PDF-1.4 power"),
+            ("rent again.pdf", b"%PDF-1.4 rent"),
+            ("bill.exe", b"MZ\x90\0\x03\0"),
+            ("scans.zip", &zip),
+        ])
+        .await;
+    let outcomes: Vec<_> = items
+        .iter()
+        .map(|item| (item["name"].clone(), item["status"].clone()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (json!("power.pdf"), json!("created")),
+            (json!("rent again.pdf"), json!("duplicate")),
+            (json!("bill.exe"), json!("rejected")),
+            (json!("water.txt"), json!("created")),
+            (json!("gas.png"), json!("created")),
+        ]
+    );
+    assert_eq!(items[0]["receipt"]["state"], "Inbox");
+    assert_eq!(items[1]["receipt_id"], rent["id"]);
+    assert_eq!(items[2]["error"], "file type is not allowed");
+    assert_eq!(app.box_ids("inbox").await.len(), 4);
+}
+
+#[rocket::async_test]
+async fn get_receipt_with_and_without_recipient() {
+    let app = TestApp::new().await;
+    let receipt = app.create("rent.pdf", b"rent").await;
+    let id = receipt["id"].as_str().unwrap().to_string();
+
+    let shown = app.get_json(format!("/api/v1/receipts/{}", id)).await;
+    assert_eq!(shown[0]["id"], receipt["id"]);
+    assert_eq!(shown[1], Value::Null);
+
+    let recipient = json!({
+        "id": uuid::Uuid::new_v4(),
+        "receipt_id": id,
+        "name": "Landlord",
+        "iban": "DE89370400440532013000",
+        "address_line1": "Main Street 1",
+        "address_line2": "12345 Town",
+        "address_line3": "",
+        "address_line4": "",
+    });
+    let answer =
+        app.act(&receipt["id"], json!({ "SetRecipient": recipient })).await;
+    assert_eq!(answer["data"][1]["name"], "Landlord");
+
+    let shown = app.get_json(format!("/api/v1/receipts/{}", id)).await;
+    assert_eq!(shown[1]["iban"], "DE89370400440532013000");
+
+    // setting it again updates the existing recipient
+    let mut changed = recipient.clone();
+    changed["name"] = json!("New Landlord");
+    app.act(&receipt["id"], json!({ "SetRecipient": changed })).await;
+    let shown = app.get_json(format!("/api/v1/receipts/{}", id)).await;
+    assert_eq!(shown[1]["name"], "New Landlord");
+    assert_eq!(shown[1]["id"], answer["data"][1]["id"]);
+}
+
+#[rocket::async_test]
+async fn accept_moves_inbox_receipt_to_valid() {
+    let app = TestApp::new().await;
+    let receipt = app.create("water.pdf", b"water").await;
+
+    let answer = app.act(&receipt["id"], json!("Accept")).await;
+    assert_eq!(answer["data"]["state"], "Valid");
+    assert!(app.box_ids("valid").await.contains(&receipt["id"]));
+    assert!(!app.box_ids("inbox").await.contains(&receipt["id"]));
+
+    let answer = app.act(&receipt["id"], json!("Accept")).await;
+    assert!(answer["error"].is_string());
+}
+
+#[rocket::async_test]
+async fn decline_only_from_inbox() {
+    let app = TestApp::new().await;
+    let receipt = app.create("spam.pdf", b"spam").await;
+
+    let answer = app.act(&receipt["id"], json!("Decline")).await;
+    assert_eq!(answer["data"]["state"], "Declined");
+
+    let answer = app.act(&receipt["id"], json!("Decline")).await;
+    assert!(answer["error"].is_string());
+}
+
+#[rocket::async_test]
+async fn pay_needs_payment_date() {
+    let app = TestApp::new().await;
+    let receipt = app.create("phone.pdf", b"phone").await;
+
+    let answer = app.act(&receipt["id"], json!("Pay")).await;
+    assert!(answer["error"].is_string());
+
+    let answer = app
+        .act(&receipt["id"], json!({ "SetPaymentDate": "2022-08-01" }))
+        .await;
+    assert_eq!(answer["data"]["payment_date"], "2022-08-01");
+
+    let answer = app.act(&receipt["id"], json!("Pay")).await;
+    assert_eq!(answer["data"]["state"], "Payed");
+}
+
+#[rocket::async_test]
+async fn set_category() {
+    let app = TestApp::new().await;
+    let receipt = app.create("lunch.jpg", b"lunch").await;
+    let food = app.create_category(json!({ "name": "Food" })).await;
+
+    let answer =
+        app.act(&receipt["id"], json!({ "SetCategory": food["id"] })).await;
+    assert_eq!(answer["data"]["category_id"], food["id"]);
+
+    let answer = app
+        .act(&receipt["id"], json!({ "SetCategory": uuid::Uuid::new_v4() }))
+        .await;
+    assert!(answer["error"].is_string());
+}
+
+#[rocket::async_test]
+async fn edits_are_based_on_the_latest_version() {
+    let app = TestApp::new().await;
+    let receipt = app.create("scan-0042.pdf", b"%PDF-1.4 power").await;
+    let uri = format!("/api/v1/receipts/{}", receipt["id"].as_str().unwrap());
+    let patch = |version: Option<&str>, body: Value| {
+        let mut request = app
+            .client
+            .patch(uri.clone())
+            .header(ContentType::JSON)
+            .body(body.to_string());
+        if let Some(version) = version {
+            request.add_header(Header::new("If-Match", version.to_string()));
+        }
+        request.dispatch()
+    };
+
+    let response = app.client.get(uri.clone()).dispatch().await;
+    assert_eq!(response.headers().get_one("ETag"), Some("\"1\""));
+    let response = patch(None, json!({ "name": "power.pdf" })).await;
+    assert_eq!(response.status(), Status::PreconditionRequired);
+
+    let response =
+        patch(Some("\"1\""), json!({ "name": "power.pdf", "amount": 4200 }))
+            .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("ETag"), Some("\"2\""));
+    let edited: Value = response.into_json().await.unwrap();
+    assert_eq!(edited["name"], "power.pdf");
+    assert_eq!(edited["amount"], 4200);
+
+    // a second edit based on the first version
+    let response = patch(Some("\"1\""), json!({ "name": "gas.pdf" })).await;
+    assert_eq!(response.status(), Status::PreconditionFailed);
+    let response = patch(Some("W/\"2\""), json!({ "name": "gas.pdf" })).await;
+    assert_eq!(response.status(), Status::PreconditionFailed);
+    let response = patch(Some("\"2\""), json!({ "state": "payed" })).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let response = patch(Some("\"2\""), json!({ "amount": -1 })).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    // actions count as changes too
+    app.act(&receipt["id"], json!({ "SetPaymentDate": "2022-09-01" })).await;
+    let response = patch(Some("\"2\""), json!({ "amount": null })).await;
+    assert_eq!(response.status(), Status::PreconditionFailed);
+    let response = patch(Some("\"3\""), json!({ "amount": null })).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let edited = app.get_json(uri.clone()).await[0].clone();
+    assert_eq!(edited["name"], "power.pdf");
+    assert_eq!(edited["amount"], Value::Null);
+    assert_eq!(edited["payment_date"], "2022-09-01");
+    assert_eq!(edited["version"], 4);
+
+    // `*` matches whatever version is stored
+    let response = patch(Some("*"), json!({ "name": "gas.pdf" })).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("ETag"), Some("\"5\""));
+}
+
+#[rocket::async_test]
+async fn confirm_process_step_is_not_supported() {
+    let app = TestApp::new().await;
+    let receipt = app.create("tax.pdf", b"tax").await;
+
+    let answer = app
+        .act(&receipt["id"], json!({ "ConfirmProcessStep": "approved" }))
+        .await;
+    assert!(answer["error"].is_string());
+}
+
+#[rocket::async_test]
+async fn unknown_receipts_are_not_found() {
+    let app = TestApp::new().await;
+    let id = uuid::Uuid::new_v4();
+
+    for uri in [
+        format!("/api/v1/receipts/{}", id),
+        format!("/api/v1/receipts/download/{}", id),
+    ] {
+        let response = app.client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    let response = app
+        .client
+        .post(format!("/api/v1/receipts/{}", id))
+        .header(ContentType::JSON)
+        .body(r#""Accept""#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn malformed_requests_are_rejected() {
+    let app = TestApp::new().await;
+    let receipt = app.create("gas.pdf", b"gas").await;
+
+    let response =
+        app.client.get("/api/v1/receipts/box/unknown").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = app
+        .client
+        .post(format!("/api/v1/receipts/{}", receipt["id"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .body(r#""Explode""#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn boxes_only_list_their_state() {
+    let app = TestApp::new().await;
+    let inbox = app.create("inbox.pdf", b"inbox").await;
+    let valid = app.create("valid.pdf", b"valid").await;
+    let declined = app.create("declined.pdf", b"declined").await;
+    app.act(&valid["id"], json!("Accept")).await;
+    app.act(&declined["id"], json!("Decline")).await;
+
+    for (state, expected) in [
+        ("inbox", vec![&inbox["id"]]),
+        ("valid", vec![&valid["id"]]),
+        ("declined", vec![&declined["id"]]),
+        ("payed", vec![]),
+        ("process", vec![]),
+        ("done", vec![]),
+    ] {
+        let ids = app.box_ids(state).await;
+        assert_eq!(ids.iter().collect::<Vec<_>>(), expected, "box {}", state);
+    }
+}
+
+#[rocket::async_test]
+async fn due_dates_and_overdue_receipts() {
+    let app = TestApp::new().await;
+    let late = app.create("late.pdf", b"late").await;
+    let payed = app.create("payed.pdf", b"payed").await;
+    let later = app.create("later.pdf", b"later").await;
+
+    let answer = app
+        .act(&late["id"], json!({ "SetDiscountDeadline": "2022-08-10" }))
+        .await;
+    assert_eq!(answer["data"]["discount_deadline"], "2022-08-10");
+    let answer =
+        app.act(&late["id"], json!({ "SetDueDate": "2022-08-01" })).await;
+    assert!(answer["error"].is_string());
+    let answer =
+        app.act(&late["id"], json!({ "SetDueDate": "2022-08-20" })).await;
+    assert_eq!(answer["data"]["due_date"], "2022-08-20");
+
+    app.act(&payed["id"], json!({ "SetDueDate": "2022-08-01" })).await;
+    app.act(&payed["id"], json!({ "SetPaymentDate": "2022-07-30" })).await;
+    app.act(&payed["id"], json!("Pay")).await;
+    app.act(&later["id"], json!({ "SetDueDate": "9999-12-31" })).await;
+
+    let overdue = app.get_json("/api/v1/receipts/overdue".to_string()).await;
+    let ids: Vec<_> = overdue
+        .as_array()
+        .expect("list of receipts")
+        .iter()
+        .map(|receipt| &receipt["id"])
+        .collect();
+    assert_eq!(ids, vec![&late["id"]]);
+}
+
+/// Reads from a server-sent events response until `count` events arrived.
+async fn read_events(response: &mut LocalResponse<'_>, count: usize) -> String {
+    let mut received = String::new();
+    let mut buffer = [0; 1024];
+    while received.matches("\n\n").count() < count {
+        let read = rocket::tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            response.read(&mut buffer),
+        )
+        .await
+        .expect("event in time")
+        .expect("readable stream");
+        assert!(read > 0, "stream ended");
+        received.push_str(std::str::from_utf8(&buffer[..read]).unwrap());
+    }
+    received
+}
+
+#[rocket::async_test]
+async fn events_stream_creations_edits_and_state_changes() {
+    let app = TestApp::new().await;
+    let mut events = app.client.get("/api/v1/receipts/events").dispatch().await;
+    assert_eq!(events.status(), Status::Ok);
+    assert_eq!(events.content_type(), Some(ContentType::EventStream));
+
+    let receipt = app.create("stream.pdf", b"stream").await;
+    app.act(&receipt["id"], json!("Accept")).await;
+    let response = app
+        .client
+        .patch(format!("/api/v1/receipts/{}", receipt["id"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", "*"))
+        .body(json!({ "amount": 1200 }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let received = read_events(&mut events, 3).await;
+    let fields: Vec<(&str, &str)> = received
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(field, value)| (field, value.trim_start()))
+        .collect();
+    assert_eq!(fields[0], ("event", "receipt_created"));
+    let created: Value = serde_json::from_str(fields[1].1).unwrap();
+    assert_eq!(created["receipt"]["id"], receipt["id"]);
+    assert_eq!(fields[2], ("event", "state_changed"));
+    let changed: Value = serde_json::from_str(fields[3].1).unwrap();
+    assert_eq!(changed["from"], "Inbox");
+    assert_eq!(changed["to"], "Valid");
+    assert_eq!(fields[4], ("event", "receipt_updated"));
+    let updated: Value = serde_json::from_str(fields[5].1).unwrap();
+    assert_eq!(updated["receipt"]["amount"], 1200);
+}
+
+#[rocket::async_test]
+async fn uploads_are_matched_to_recurring_bills() {
+    let app = TestApp::new().await;
+    let today = chrono::Local::today().naive_local();
+    let housing = app.create_category(json!({ "name": "Housing" })).await;
+    let response = app
+        .client
+        .post("/api/v1/recurring")
+        .header(ContentType::JSON)
+        .body(
+            json!({
+                "name": "Rent",
+                "recipient_name": "Landlord",
+                "recipient_iban": "DE89370400440532013000",
+                "category_id": housing["id"],
+                "expected_amount": 100000,
+                "cadence": "monthly",
+                "starts_on": today,
+            })
+            .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let receipt = app.create("rent-this-month.pdf", b"rent").await;
+    assert_eq!(receipt["category_id"], housing["id"]);
+    let shown = app
+        .get_json(format!(
+            "/api/v1/receipts/{}",
+            receipt["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(shown[1]["name"], "Landlord");
+
+    let forecast = app.get_json("/api/v1/recurring/forecast".into()).await;
+    let entries = forecast.as_array().expect("forecast entries");
+    assert_eq!(entries[0]["status"], "received");
+    assert_eq!(entries[0]["receipt"]["id"], receipt["id"]);
+    assert!(entries[1..].iter().all(|entry| entry["status"] == "upcoming"));
+
+    app.act(&receipt["id"], json!({ "SetAmount": 150000 })).await;
+    let forecast = app.get_json("/api/v1/recurring/forecast".into()).await;
+    assert_eq!(forecast[0]["status"], "unusually_large");
+
+    for query in ["from=2022-09-01&until=2022-08-01", "until=2999-01-01"] {
+        let response = app
+            .client
+            .get(format!("/api/v1/recurring/forecast?{}", query))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    app.act(&receipt["id"], json!({ "SetAmount": i64::MAX })).await;
+    let forecast = app.get_json("/api/v1/recurring/forecast".into()).await;
+    assert_eq!(forecast[0]["status"], "unusually_large");
+
+    for field in ["expected_amount", "tolerance_percent", "grace_days"] {
+        let mut template = json!({
+            "name": "Insurance",
+            "recipient_name": "Insurer",
+            "expected_amount": 5000,
+            "cadence": "yearly",
+            "starts_on": today,
+        });
+        template[field] = json!(-1);
+        let response = app
+            .client
+            .post("/api/v1/recurring")
+            .header(ContentType::JSON)
+            .body(template.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+}
+
+#[rocket::async_test]
+async fn categories_form_a_tree() {
+    let app = TestApp::new().await;
+    let home = app.create_category(json!({ "name": "Home" })).await;
+    let energy = app
+        .create_category(json!({ "name": "Energy", "parent_id": home["id"] }))
+        .await;
+
+    let response = app
+        .client
+        .post("/api/v1/categories")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Home" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    // Home cannot move below its own child
+    let response = app
+        .client
+        .put(format!("/api/v1/categories/{}", home["id"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Home", "parent_id": energy["id"] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = app
+        .client
+        .delete(format!("/api/v1/categories/{}", home["id"].as_str().unwrap()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let categories = app.get_json("/api/v1/categories".into()).await;
+    assert_eq!(categories.as_array().unwrap().len(), 2);
+}
+
+#[rocket::async_test]
+async fn budgets_include_child_categories() {
+    let app = TestApp::new().await;
+    let home = app
+        .create_category(json!({ "name": "Home", "monthly_budget": 50000 }))
+        .await;
+    let energy = app
+        .create_category(json!({
+            "name": "Energy",
+            "parent_id": home["id"],
+            "monthly_budget": 10000,
+        }))
+        .await;
+
+    for (name, category, amount) in
+        [("rent.pdf", &home, 40000), ("power.pdf", &energy, 12000)]
+    {
+        let receipt = app.create(name, name.as_bytes()).await;
+        app.act(&receipt["id"], json!({ "SetCategory": category["id"] })).await;
+        app.act(&receipt["id"], json!({ "SetAmount": amount })).await;
+        app.act(&receipt["id"], json!({ "SetDueDate": "2022-08-15" })).await;
+    }
+
+    let budgets =
+        app.get_json("/api/v1/categories/budgets?month=2022-08".into()).await;
+    assert_eq!(budgets[0]["category"]["name"], "Energy");
+    assert_eq!(budgets[0]["spent"], 12000);
+    assert_eq!(budgets[0]["over_budget"], true);
+    assert_eq!(budgets[1]["category"]["name"], "Home");
+    assert_eq!(budgets[1]["spent"], 52000);
+    assert_eq!(budgets[1]["over_budget"], true);
+
+    let budgets =
+        app.get_json("/api/v1/categories/budgets?month=2022-09".into()).await;
+    assert_eq!(budgets[1]["spent"], 0);
+}
+
+#[rocket::async_test]
+async fn rules_categorize_and_accept_new_receipts() {
+    let app = TestApp::new().await;
+    let energy = app.create_category(json!({ "name": "Energy" })).await;
+    let rule = json!({
+        "name": "Power",
+        "text_contains": "stadtwerke",
+        "set_category_id": energy["id"],
+        "set_recipient_name": "Stadtwerke",
+        "auto_accept": true,
+    });
+
+    let old = app.create("august.txt", b"Stadtwerke invoice").await;
+    app.create("groceries.txt", b"Supermarket").await;
+    let declined = app.create("july.txt", b"Stadtwerke reminder").await;
+    app.act(&declined["id"], json!("Decline")).await;
+    let response = app
+        .client
+        .post("/api/v1/rules/preview")
+        .header(ContentType::JSON)
+        .body(rule.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let preview: Value = response.into_json().await.unwrap();
+    assert_eq!(preview.as_array().unwrap().len(), 1);
+    assert_eq!(preview[0]["id"], old["id"]);
+
+    let response = app
+        .client
+        .post("/api/v1/rules")
+        .header(ContentType::JSON)
+        .body(rule.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let rule: Value = response.into_json().await.unwrap();
+
+    let receipt = app.create("september.txt", b"STADTWERKE invoice").await;
+    assert_eq!(receipt["state"], "Valid");
+    assert_eq!(receipt["category_id"], energy["id"]);
+    let shown = app
+        .get_json(format!(
+            "/api/v1/receipts/{}",
+            receipt["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(shown[1]["name"], "Stadtwerke");
+    let other = app.create("october.txt", b"Supermarket").await;
+    assert_eq!(other["state"], "Inbox");
+
+    let preview = app
+        .get_json(format!(
+            "/api/v1/rules/{}/preview",
+            rule["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(preview.as_array().unwrap().len(), 2);
+
+    let response = app
+        .client
+        .post("/api/v1/rules")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Everything", "auto_accept": true }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn rules_run_again_once_the_amount_is_set() {
+    let app = TestApp::new().await;
+    let rule = json!({
+        "name": "Small bills",
+        "max_amount": 5000,
+        "auto_accept": true,
+    });
+    let response = app
+        .client
+        .post("/api/v1/rules")
+        .header(ContentType::JSON)
+        .body(rule.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let small = app.create("small.pdf", b"small").await;
+    assert_eq!(small["state"], "Inbox");
+    let answer = app.act(&small["id"], json!({ "SetAmount": 4200 })).await;
+    assert_eq!(answer["data"]["state"], "Valid");
+    let large = app.create("large.pdf", b"large").await;
+    let answer = app.act(&large["id"], json!({ "SetAmount": 42000 })).await;
+    assert_eq!(answer["data"]["state"], "Inbox");
+}
+
+#[rocket::async_test]
+async fn receipts_are_tagged_and_discussed() {
+    let app = TestApp::new().await;
+    let power = app.create("power.pdf", b"power").await;
+    let rent = app.create("rent.pdf", b"rent").await;
+    let tag = |receipt: &Value, name: &str| {
+        format!(
+            "/api/v1/receipts/{}/tags/{}",
+            receipt["id"].as_str().unwrap(),
+            name
+        )
+    };
+
+    for (receipt, name) in
+        [(&power, "Tax"), (&power, "home"), (&rent, "HOME"), (&power, "tax")]
+    {
+        let response = app.client.put(tag(receipt, name)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let tags = app.get_json("/api/v1/receipts/tags".into()).await;
+    assert_eq!(tags.as_array().unwrap().len(), 2);
+    let tags = app
+        .get_json(format!(
+            "/api/v1/receipts/{}/tags",
+            power["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(tags[0]["name"], "home");
+    assert_eq!(tags[1]["name"], "tax");
+
+    let tagged = app
+        .get_json("/api/v1/receipts/box/inbox?tag=home&tag=tax".into())
+        .await;
+    assert_eq!(tagged.as_array().unwrap().len(), 1);
+    assert_eq!(tagged[0]["id"], power["id"]);
+    let tagged =
+        app.get_json("/api/v1/receipts/box/inbox?tag=home".into()).await;
+    assert_eq!(tagged.as_array().unwrap().len(), 2);
+    let tagged =
+        app.get_json("/api/v1/receipts/box/inbox?tag=car".into()).await;
+    assert!(tagged.as_array().unwrap().is_empty());
+
+    let response = app.client.delete(tag(&rent, "home")).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = app.client.delete(tag(&rent, "home")).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let comments =
+        format!("/api/v1/receipts/{}/comments", power["id"].as_str().unwrap());
+    for (author, body) in [("alice", "Is this the new tariff?"), ("bob", "Yes")]
+    {
+        let response = app
+            .client
+            .post(comments.as_str())
+            .header(ContentType::JSON)
+            .body(json!({ "author": author, "body": body }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let response = app
+        .client
+        .post(comments.as_str())
+        .header(ContentType::JSON)
+        .body(json!({ "author": "alice", "body": " " }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let thread = app.get_json(comments.clone()).await;
+    assert_eq!(thread.as_array().unwrap().len(), 2);
+    assert_eq!(thread[0]["author"], "alice");
+    assert_eq!(thread[1]["body"], "Yes");
+
+    let first = format!("{}/{}", comments, thread[0]["id"].as_str().unwrap());
+    let response = app.client.delete(first).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let thread = app.get_json(comments).await;
+    assert_eq!(thread.as_array().unwrap().len(), 1);
+}
+
+#[rocket::async_test]
+async fn reports_group_spending() {
+    let app = TestApp::new().await;
+    let energy = app.create_category(json!({ "name": "Energy" })).await;
+
+    let rent = app.create("rent.pdf", b"rent").await;
+    app.act(&rent["id"], json!({ "SetAmount": 40000 })).await;
+    app.act(&rent["id"], json!({ "SetPaymentDate": "2022-08-01" })).await;
+    app.act(&rent["id"], json!("Pay")).await;
+    for (name, amount, due_date) in [
+        ("power.pdf", 12000, "2022-08-15"),
+        ("water.pdf", 3000, "2022-09-10"),
+        ("gas.pdf", 99999, "2022-08-20"),
+    ] {
+        let receipt = app.create(name, name.as_bytes()).await;
+        app.act(&receipt["id"], json!({ "SetCategory": energy["id"] })).await;
+        app.act(&receipt["id"], json!({ "SetAmount": amount })).await;
+        app.act(&receipt["id"], json!({ "SetDueDate": due_date })).await;
+        if name == "gas.pdf" {
+            app.act(&receipt["id"], json!("Decline")).await;
+        }
+    }
+
+    let report = app.get_json("/api/v1/reports/categories".into()).await;
+    assert_eq!(
+        report,
+        json!([
+            { "group": null, "receipts": 1, "total": 40000 },
+            { "group": "Energy", "receipts": 2, "total": 15000 },
+        ])
+    );
+
+    let report = app.get_json("/api/v1/reports/months".into()).await;
+    assert_eq!(report[0]["group"], "2022-08");
+    assert_eq!(report[0]["total"], 52000);
+    assert_eq!(report[1]["group"], "2022-09");
+
+    let report = app
+        .get_json(
+            "/api/v1/reports/payment?from=2022-08-01&until=2022-08-31".into(),
+        )
+        .await;
+    assert_eq!(
+        report,
+        json!([
+            { "group": "paid", "receipts": 1, "total": 40000 },
+            { "group": "outstanding", "receipts": 1, "total": 12000 },
+        ])
+    );
+
+    let report = app.get_json("/api/v1/reports/states".into()).await;
+    assert_eq!(report[0]["group"], "declined");
+
+    let response = app
+        .client
+        .get("/api/v1/reports/categories?from=2022-09-01&format=csv")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    assert_eq!(
+        response.into_string().await.unwrap(),
+        "group,receipts,total\nEnergy,1,3000\n"
+    );
+
+    let response = app.client.get("/api/v1/reports/colors").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn split_receipts_are_settled_up() {
+    let app = TestApp::new().await;
+    let put_split = |id: &Value, split: Value| {
+        app.client
+            .put(format!("/api/v1/receipts/{}/split", id.as_str().unwrap()))
+            .header(ContentType::JSON)
+            .body(split.to_string())
+            .dispatch()
+    };
+    let thirds = json!({
+        "paid_by": "Anna",
+        "allocations": [
+            { "party": "Anna", "basis_points": 3334 },
+            { "party": "Ben", "basis_points": 3333 },
+            { "party": "Cleo", "basis_points": 3333 },
+        ],
+    });
+
+    let groceries = app.create("groceries.pdf", b"groceries").await;
+    let response = put_split(&groceries["id"], thirds.clone()).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    app.act(&groceries["id"], json!({ "SetAmount": 9000 })).await;
+    let response = put_split(&groceries["id"], thirds).await;
+    assert_eq!(response.status(), Status::Ok);
+    let split: Value = response.into_json().await.unwrap();
+    let shares: Vec<_> = split["allocations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|allocation| allocation["share"].clone())
+        .collect();
+    assert_eq!(shares, vec![json!(3000), json!(3000), json!(3000)]);
+
+    let power = app.create("power.pdf", b"power").await;
+    app.act(&power["id"], json!({ "SetAmount": 4000 })).await;
+    let short = json!({
+        "paid_by": "Ben",
+        "allocations": [{ "party": "Anna", "basis_points": 5000 }],
+    });
+    let response = put_split(&power["id"], short).await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let response = put_split(
+        &power["id"],
+        json!({
+            "paid_by": "Ben",
+            "allocations": [
+                { "party": "Anna", "basis_points": 7500 },
+                { "party": "Ben", "amount": 1000 },
+            ],
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // only payed receipts are settled
+    for receipt in [&groceries, &power] {
+        app.act(&receipt["id"], json!({ "SetPaymentDate": "2022-09-01" }))
+            .await;
+        app.act(&receipt["id"], json!("Pay")).await;
+    }
+    let settle_up = app.get_json("/api/v1/reports/settle-up".into()).await;
+    assert_eq!(
+        settle_up["balances"],
+        json!({ "Anna": 3000, "Ben": 0, "Cleo": -3000 })
+    );
+    assert_eq!(
+        settle_up["transfers"],
+        json!([{ "from": "Cleo", "to": "Anna", "amount": 3000 }])
+    );
+
+    // a corrected amount needs a new split
+    app.act(&power["id"], json!({ "SetAmount": 4200 })).await;
+    let settle_up = app.get_json("/api/v1/reports/settle-up".into()).await;
+    assert_eq!(settle_up["unbalanced"], json!([power["id"]]));
+    assert_eq!(
+        settle_up["transfers"],
+        json!([
+            { "from": "Ben", "to": "Anna", "amount": 3000 },
+            { "from": "Cleo", "to": "Anna", "amount": 3000 },
+        ])
+    );
+    let response = app
+        .client
+        .delete(format!(
+            "/api/v1/receipts/{}/split",
+            power["id"].as_str().unwrap()
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let split = app
+        .get_json(format!(
+            "/api/v1/receipts/{}/split",
+            power["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(split, json!({ "paid_by": null, "allocations": [] }));
+}
+
+#[rocket::async_test]
+async fn tax_year_export_contains_files_and_ledger() {
+    use std::io::Read;
+
+    let app = TestApp::new().await;
+    let housing = app.create_category(json!({ "name": "Housing" })).await;
+    let rent = app.create("rent.pdf", b"rent").await;
+    app.act(&rent["id"], json!({ "SetCategory": housing["id"] })).await;
+    app.act(&rent["id"], json!({ "SetPaymentDate": "2022-03-01" })).await;
+    let tag =
+        format!("/api/v1/receipts/{}/tags/tax", rent["id"].as_str().unwrap());
+    app.client.put(tag).dispatch().await;
+    let power = app.create("power.pdf", b"power").await;
+    app.act(&power["id"], json!({ "SetDueDate": "2023-01-05" })).await;
+
+    let response = app
+        .client
+        .get("/api/v1/exports/tax-year?from=2022-01-01&until=2022-12-31")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("attachment; filename=\"receipts_2022-01-01_2022-12-31.zip\"")
+    );
+    let archive = response.into_bytes().await.unwrap();
+    let mut archive =
+        zip::ZipArchive::new(std::io::Cursor::new(archive)).unwrap();
+    assert_eq!(archive.len(), 4);
+
+    let id = rent["id"].as_str().unwrap().replace('-', "");
+    let path = format!("receipts/2022-03-01_unknown_Housing_{}.pdf", id);
+    let mut file = Vec::new();
+    archive.by_name(&path).unwrap().read_to_end(&mut file).unwrap();
+    assert_eq!(file, b"rent");
+
+    let mut ledger = String::new();
+    archive.by_name("ledger.csv").unwrap().read_to_string(&mut ledger).unwrap();
+    assert_eq!(ledger.lines().count(), 2);
+    assert!(ledger.contains(",Housing,,,tax,"));
+
+    let mut manifest = String::new();
+    archive
+        .by_name("SHA256SUMS")
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    assert!(manifest.contains(&format!(
+        "{}  {}",
+        rent["file_hash"].as_str().unwrap(),
+        path
+    )));
+}
+
+#[rocket::async_test]
+async fn payed_receipts_export_to_datev_and_ledger() {
+    let app = TestApp::new().await;
+    let home = app.create_category(json!({ "name": "Home" })).await;
+    let energy = app
+        .create_category(json!({ "name": "Energy", "parent_id": home["id"] }))
+        .await;
+    let power = app.create("power.pdf", b"power").await;
+    app.act(&power["id"], json!({ "SetCategory": energy["id"] })).await;
+    app.act(&power["id"], json!({ "SetAmount": 12050 })).await;
+    app.act(&power["id"], json!({ "SetPaymentDate": "2022-08-05" })).await;
+    app.act(&power["id"], json!("Pay")).await;
+    // not payed yet, so not booked
+    let water = app.create("water.pdf", b"water").await;
+    app.act(&water["id"], json!({ "SetAmount": 3000 })).await;
+    app.act(&water["id"], json!({ "SetPaymentDate": "2022-08-06" })).await;
+
+    let response = app
+        .client
+        .get("/api/v1/exports/ledger?from=2022-01-01&until=2022-12-31")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let journal = response.into_string().await.unwrap();
+    assert!(journal.starts_with("2022-08-05 Unknown | power.pdf\n"));
+    assert!(journal.contains("    Expenses:Home:Energy  EUR 120.50\n"));
+    assert!(!journal.contains("water.pdf"));
+
+    let response = app
+        .client
+        .get("/api/v1/exports/datev?from=2022-01-01&until=2022-12-31")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let file = response.into_bytes().await.unwrap();
+    let lines: Vec<_> = file.split(|&b| b == b'\n').collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[2].starts_with(b"120,50;\"S\";\"EUR\";;;;4900;1200;"));
+
+    let response = app
+        .client
+        .get("/api/v1/exports/datev?from=2022-12-01&until=2023-01-31")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn backups_restore_into_an_empty_installation() {
+    use backend::pool::SQLDb;
+    use backend::SledDB;
+    use sea_orm_rocket::Database;
+
+    let app = TestApp::new().await;
+    let home = app.create_category(json!({ "name": "Home" })).await;
+    let energy = app
+        .create_category(json!({ "name": "Energy", "parent_id": home["id"] }))
+        .await;
+    let power = app.create("power.pdf", b"power").await;
+    app.act(&power["id"], json!({ "SetCategory": energy["id"] })).await;
+    app.act(&power["id"], json!({ "SetAmount": 12050 })).await;
+    let id = power["id"].as_str().unwrap();
+    let response = app
+        .client
+        .put(format!("/api/v1/receipts/{}/tags/tax", id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = app
+        .client
+        .post(format!("/api/v1/receipts/{}/comments", id))
+        .header(ContentType::JSON)
+        .body(json!({ "author": "alice", "body": "Paid" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let parts = |app: &TestApp| {
+        let rocket = app.client.rocket();
+        let db = &SQLDb::fetch(rocket).expect("database").conn;
+        let files_db = &rocket.state::<SledDB>().expect("files db").files_db;
+        (db.clone(), files_db.clone())
+    };
+    let (db, files_db) = parts(&app);
+    let mut archive = std::io::Cursor::new(Vec::new());
+    let manifest = backend::backup::backup(&db, &files_db, &mut archive)
+        .await
+        .expect("backup");
+    assert_eq!(manifest.tables["receipts"], 1);
+    assert_eq!(manifest.tables["categories"], 2);
+    assert_eq!(manifest.files, 1);
+
+    // the source is not empty, so nothing is restored into it
+    archive.set_position(0);
+    assert!(backend::backup::restore(&db, &files_db, &mut archive)
+        .await
+        .is_err());
+
+    // the backup brings the new database to its migrations itself
+    let restored =
+        TestApp::with(|figment| figment.merge(("auto_migrate", false))).await;
+    let (db, files_db) = parts(&restored);
+    archive.set_position(0);
+    backend::backup::restore(&db, &files_db, &mut archive)
+        .await
+        .expect("restore");
+
+    let receipt = restored.get_json(format!("/api/v1/receipts/{}", id)).await;
+    assert_eq!(receipt["category_id"], energy["id"]);
+    assert_eq!(receipt["amount"], 12050);
+    let tags = restored.get_json(format!("/api/v1/receipts/{}/tags", id)).await;
+    assert_eq!(tags[0]["name"], "tax");
+    let comments =
+        restored.get_json(format!("/api/v1/receipts/{}/comments", id)).await;
+    assert_eq!(comments[0]["body"], "Paid");
+    let response = restored
+        .client
+        .get(format!("/api/v1/receipts/download/{}", id))
+        .dispatch()
+        .await;
+    assert_eq!(response.into_bytes().await.unwrap(), b"power".to_vec());
+}
@@ -0,0 +1,289 @@
+use chrono::NaiveDate;
+use reqwest::blocking::{multipart, Client, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The server refused the request, with the reason it answered.
+    #[error("server answered {0}{}", reason(.1))]
+    Status(reqwest::StatusCode, String),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("{0}")]
+    Action(String),
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Escapes `segment` for a url path, as tags may contain characters like
+/// `/`, `?` or `#`.
+fn path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                char::from(byte).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+fn reason(body: &str) -> String {
+    match body.trim() {
+        "" => String::new(),
+        reason => format!(": {}", reason),
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum ReceiptState {
+    Inbox,
+    Valid,
+    Payed,
+    Declined,
+    Process,
+    Done,
+}
+
+impl ReceiptState {
+    /// Name of the state as used in `/box/<state>` urls.
+    pub fn as_param(&self) -> &'static str {
+        match self {
+            ReceiptState::Inbox => "inbox",
+            ReceiptState::Valid => "valid",
+            ReceiptState::Payed => "payed",
+            ReceiptState::Declined => "declined",
+            ReceiptState::Process => "process",
+            ReceiptState::Done => "done",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Receipt {
+    pub id: Uuid,
+    pub name: String,
+    pub state: ReceiptState,
+    pub file_hash: String,
+    #[serde(default)]
+    pub category_id: Option<Uuid>,
+    pub payment_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub due_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub discount_deadline: Option<NaiveDate>,
+    /// Total in cents.
+    #[serde(default)]
+    pub amount: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Recipient {
+    pub id: Uuid,
+    pub receipt_id: Uuid,
+    pub name: String,
+    pub iban: String,
+    pub address_line1: String,
+    pub address_line2: String,
+    pub address_line3: String,
+    pub address_line4: String,
+}
+
+/// Mirrors `ReceiptAction` of the backend.
+#[derive(Serialize, Debug)]
+pub enum ReceiptAction {
+    Accept,
+    Decline,
+    Pay,
+    ConfirmProcessStep(String),
+    SetRecipient(Recipient),
+    SetCategory(Uuid),
+    SetPaymentDate(NaiveDate),
+    SetDueDate(NaiveDate),
+    SetDiscountDeadline(NaiveDate),
+    SetAmount(i64),
+}
+
+#[derive(Deserialize, Debug)]
+enum ActionAnswer {
+    #[serde(rename = "data")]
+    Data(serde_json::Value),
+    #[serde(rename = "error")]
+    Error(String),
+}
+
+pub struct ApiClient {
+    base_url: String,
+    client: Client,
+}
+
+impl ApiClient {
+    pub fn new(base_url: &str) -> Self {
+        ApiClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/receipts/{}", self.base_url, path)
+    }
+
+    fn check(response: Response) -> ApiResult<Response> {
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            let status = response.status();
+            Err(ApiError::Status(status, response.text().unwrap_or_default()))
+        }
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str) -> ApiResult<T> {
+        let response = self
+            .client
+            .get(self.url(path))
+            .header("Accept", "application/json")
+            .send()?;
+        Ok(Self::check(response)?.json()?)
+    }
+
+    pub fn upload(&self, file: &Path, name: &str) -> ApiResult<Receipt> {
+        let form = multipart::Form::new()
+            .text("name", name.to_string())
+            .file("file", file)?;
+        let response = self
+            .client
+            .post(self.url("upload"))
+            .header("Accept", "application/json")
+            .multipart(form)
+            .send()?;
+        Ok(Self::check(response)?.json()?)
+    }
+
+    /// Lists the receipts in `state` carrying all `tags`.
+    pub fn list(
+        &self,
+        state: ReceiptState,
+        tags: &[String],
+    ) -> ApiResult<Vec<serde_json::Value>> {
+        let query: Vec<_> = tags.iter().map(|tag| ("tag", tag)).collect();
+        let response = self
+            .client
+            .get(self.url(&format!("box/{}", state.as_param())))
+            .header("Accept", "application/json")
+            .query(&query)
+            .send()?;
+        Ok(Self::check(response)?.json()?)
+    }
+
+    pub fn tag(
+        &self,
+        id: Uuid,
+        tag: &str,
+    ) -> ApiResult<Vec<serde_json::Value>> {
+        let response = self
+            .client
+            .put(self.url(&format!("{}/tags/{}", id, path_segment(tag))))
+            .header("Accept", "application/json")
+            .send()?;
+        Ok(Self::check(response)?.json()?)
+    }
+
+    pub fn untag(
+        &self,
+        id: Uuid,
+        tag: &str,
+    ) -> ApiResult<Vec<serde_json::Value>> {
+        let response = self
+            .client
+            .delete(self.url(&format!("{}/tags/{}", id, path_segment(tag))))
+            .header("Accept", "application/json")
+            .send()?;
+        Ok(Self::check(response)?.json()?)
+    }
+
+    pub fn comments(&self, id: Uuid) -> ApiResult<Vec<serde_json::Value>> {
+        self.get_json(&format!("{}/comments", id))
+    }
+
+    pub fn comment(
+        &self,
+        id: Uuid,
+        author: &str,
+        body: &str,
+    ) -> ApiResult<serde_json::Value> {
+        let response = self
+            .client
+            .post(self.url(&format!("{}/comments", id)))
+            .header("Accept", "application/json")
+            .json(&serde_json::json!({ "author": author, "body": body }))
+            .send()?;
+        Ok(Self::check(response)?.json()?)
+    }
+
+    pub fn overdue(&self) -> ApiResult<Vec<serde_json::Value>> {
+        self.get_json("overdue")
+    }
+
+    pub fn show(&self, id: Uuid) -> ApiResult<serde_json::Value> {
+        self.get_json(&id.to_string())
+    }
+
+    pub fn apply(
+        &self,
+        id: Uuid,
+        action: &ReceiptAction,
+    ) -> ApiResult<serde_json::Value> {
+        let response = self
+            .client
+            .post(self.url(&id.to_string()))
+            .header("Accept", "application/json")
+            .json(action)
+            .send()?;
+        match Self::check(response)?.json()? {
+            ActionAnswer::Data(data) => Ok(data),
+            ActionAnswer::Error(err) => Err(ApiError::Action(err)),
+        }
+    }
+
+    pub fn download(&self, id: Uuid) -> ApiResult<Vec<u8>> {
+        let response =
+            self.client.get(self.url(&format!("download/{}", id))).send()?;
+        Ok(Self::check(response)?.bytes()?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refusals_show_the_reason_of_the_server() {
+        let status = reqwest::StatusCode::UNPROCESSABLE_ENTITY;
+        let refused =
+            ApiError::Status(status, "amount cannot be negative\n".into());
+        assert_eq!(
+            refused.to_string(),
+            "server answered 422 Unprocessable Entity: amount cannot be \
+             negative"
+        );
+        assert_eq!(
+            ApiError::Status(status, String::new()).to_string(),
+            "server answered 422 Unprocessable Entity"
+        );
+    }
+
+    #[test]
+    fn tags_are_escaped_in_urls() {
+        assert_eq!(path_segment("tax-2022_q1.~"), "tax-2022_q1.~");
+        assert_eq!(path_segment("a/b?c#d%e f"), "a%2Fb%3Fc%23d%25e%20f");
+        assert_eq!(path_segment("Büro"), "B%C3%BCro");
+    }
+}
@@ -0,0 +1,396 @@
+mod api;
+mod output;
+
+use anyhow::{anyhow, Context};
+use api::{ApiClient, ReceiptAction, ReceiptState, Recipient};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use output::OutputFormat;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Script uploads and state changes of receipts without the web UI.
+#[derive(Parser, Debug)]
+#[clap(name = "expensebills-cli", version, about)]
+struct Cli {
+    /// Base url of the API
+    #[clap(
+        long,
+        env = "EXPENSEBILLS_URL",
+        default_value = "http://127.0.0.1:8000/api/v1"
+    )]
+    server: String,
+    #[clap(long, short, value_enum, default_value = "table")]
+    output: OutputFormat,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum StateArg {
+    Inbox,
+    Valid,
+    Payed,
+    Declined,
+    Process,
+    Done,
+}
+
+impl From<StateArg> for ReceiptState {
+    fn from(state: StateArg) -> Self {
+        match state {
+            StateArg::Inbox => ReceiptState::Inbox,
+            StateArg::Valid => ReceiptState::Valid,
+            StateArg::Payed => ReceiptState::Payed,
+            StateArg::Declined => ReceiptState::Declined,
+            StateArg::Process => ReceiptState::Process,
+            StateArg::Done => ReceiptState::Done,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Upload files as new receipts into the Inbox
+    Upload {
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+        /// Name of the receipt, defaults to the file name
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// List all receipts in a state
+    List {
+        #[clap(value_enum)]
+        state: StateArg,
+        /// Only list receipts with this tag, can be repeated
+        #[clap(long)]
+        tag: Vec<String>,
+    },
+    /// Show a receipt and its recipient
+    Show {
+        id: Uuid,
+    },
+    /// Apply an action to a receipt
+    Action {
+        id: Uuid,
+        #[clap(subcommand)]
+        action: ActionCommand,
+    },
+    /// List open receipts whose due date has passed
+    Overdue,
+    /// Add a tag to a receipt
+    Tag {
+        id: Uuid,
+        tag: String,
+    },
+    /// Remove a tag from a receipt
+    Untag {
+        id: Uuid,
+        tag: String,
+    },
+    /// Show the comments on a receipt, or add one
+    Comment {
+        id: Uuid,
+        /// Text of the new comment
+        text: Option<String>,
+        #[clap(long, env = "USER", default_value = "cli")]
+        author: String,
+    },
+    /// Download the file of a receipt
+    Download {
+        id: Uuid,
+        /// File to write to, stdout if not given
+        #[clap(long, short = 'f')]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ActionCommand {
+    /// Move a receipt from the Inbox to valid
+    Accept,
+    /// Move a receipt from the Inbox to declined
+    Decline,
+    /// Mark a receipt with a payment date as payed
+    Pay,
+    /// Confirm a step of the receipt's process
+    ConfirmProcessStep {
+        step: String,
+    },
+    /// Set the category of a receipt
+    SetCategory {
+        /// Id of the category
+        category: Uuid,
+    },
+    /// Set the date the receipt was or will be payed
+    SetPaymentDate {
+        /// Date in the form YYYY-MM-DD
+        date: NaiveDate,
+    },
+    /// Set the date the receipt has to be payed by
+    SetDueDate {
+        /// Date in the form YYYY-MM-DD
+        date: NaiveDate,
+    },
+    /// Set the last day an early payment discount can be taken
+    SetDiscountDeadline {
+        /// Date in the form YYYY-MM-DD
+        date: NaiveDate,
+    },
+    /// Set the total of the bill
+    SetAmount {
+        /// Amount in cents
+        cents: i64,
+    },
+    /// Set who the receipt is payed to
+    SetRecipient {
+        #[clap(long)]
+        name: String,
+        #[clap(long)]
+        iban: String,
+        /// Up to four address lines
+        #[clap(long = "address", max_values = 4)]
+        address: Vec<String>,
+    },
+}
+
+impl ActionCommand {
+    fn into_action(self, receipt_id: Uuid) -> ReceiptAction {
+        match self {
+            ActionCommand::Accept => ReceiptAction::Accept,
+            ActionCommand::Decline => ReceiptAction::Decline,
+            ActionCommand::Pay => ReceiptAction::Pay,
+            ActionCommand::ConfirmProcessStep {
+                step,
+            } => ReceiptAction::ConfirmProcessStep(step),
+            ActionCommand::SetCategory {
+                category,
+            } => ReceiptAction::SetCategory(category),
+            ActionCommand::SetPaymentDate {
+                date,
+            } => ReceiptAction::SetPaymentDate(date),
+            ActionCommand::SetDueDate {
+                date,
+            } => ReceiptAction::SetDueDate(date),
+            ActionCommand::SetDiscountDeadline {
+                date,
+            } => ReceiptAction::SetDiscountDeadline(date),
+            ActionCommand::SetAmount {
+                cents,
+            } => ReceiptAction::SetAmount(cents),
+            ActionCommand::SetRecipient {
+                name,
+                iban,
+                address,
+            } => {
+                let line =
+                    |i: usize| address.get(i).cloned().unwrap_or_default();
+                ReceiptAction::SetRecipient(Recipient {
+                    id: Uuid::new_v4(),
+                    receipt_id,
+                    name,
+                    iban,
+                    address_line1: line(0),
+                    address_line2: line(1),
+                    address_line3: line(2),
+                    address_line4: line(3),
+                })
+            },
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let client = ApiClient::new(&cli.server);
+
+    match cli.command {
+        Command::Upload {
+            files,
+            name,
+        } => {
+            let mut receipts = Vec::new();
+            for file in files {
+                let name = match &name {
+                    Some(name) => name.clone(),
+                    None => file
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .ok_or_else(|| {
+                            anyhow!("{} is not a file", file.display())
+                        })?,
+                };
+                let receipt = client
+                    .upload(&file, &name)
+                    .with_context(|| format!("uploading {}", file.display()))?;
+                receipts.push(receipt);
+            }
+            output::print(cli.output, &serde_json::to_value(receipts)?);
+        },
+        Command::List {
+            state,
+            tag,
+        } => {
+            let receipts = client.list(state.into(), &tag)?;
+            output::print(cli.output, &serde_json::Value::Array(receipts));
+        },
+        Command::Overdue => {
+            let receipts = client.overdue()?;
+            output::print(cli.output, &serde_json::Value::Array(receipts));
+        },
+        Command::Tag {
+            id,
+            tag,
+        } => {
+            let tags = client.tag(id, &tag)?;
+            output::print_list(cli.output, &output::TAG_COLUMNS, &tags);
+        },
+        Command::Untag {
+            id,
+            tag,
+        } => {
+            let tags = client.untag(id, &tag)?;
+            output::print_list(cli.output, &output::TAG_COLUMNS, &tags);
+        },
+        Command::Comment {
+            id,
+            text,
+            author,
+        } => {
+            if let Some(text) = text {
+                client.comment(id, &author, &text)?;
+            }
+            let comments = client.comments(id)?;
+            output::print_list(cli.output, &output::COMMENT_COLUMNS, &comments);
+        },
+        Command::Show {
+            id,
+        } => {
+            output::print(cli.output, &client.show(id)?);
+        },
+        Command::Action {
+            id,
+            action,
+        } => {
+            let result = client.apply(id, &action.into_action(id))?;
+            output::print(cli.output, &result);
+        },
+        Command::Download {
+            id,
+            file,
+        } => {
+            let content = client.download(id)?;
+            match file {
+                Some(path) => std::fs::write(&path, content)
+                    .with_context(|| format!("writing {}", path.display()))?,
+                None => {
+                    use std::io::Write;
+                    std::io::stdout().write_all(&content)?;
+                },
+            }
+        },
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use serde_json::json;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        let base = ["expensebills-cli", "--server", "http://bills/api/v1"];
+        Cli::try_parse_from(base.iter().chain(args))
+    }
+
+    fn action(args: &[&str]) -> (Uuid, ReceiptAction) {
+        let id = Uuid::new_v4().to_string();
+        let mut all = vec!["action", id.as_str()];
+        all.extend(args);
+        match parse(&all).expect("valid action").command {
+            Command::Action {
+                id,
+                action,
+            } => (id, action.into_action(id)),
+            other => panic!("parsed {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arguments_are_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn actions_are_sent_as_the_api_expects() {
+        let (_, set_amount) = action(&["set-amount", "4200"]);
+        assert_eq!(
+            serde_json::to_value(set_amount).unwrap(),
+            json!({ "SetAmount": 4200 })
+        );
+        let (_, due) = action(&["set-due-date", "2022-09-30"]);
+        assert_eq!(
+            serde_json::to_value(due).unwrap(),
+            json!({ "SetDueDate": "2022-09-30" })
+        );
+        let (_, pay) = action(&["pay"]);
+        assert_eq!(serde_json::to_value(pay).unwrap(), json!("Pay"));
+    }
+
+    #[test]
+    fn recipient_addresses_fill_the_lines_in_order() {
+        let (id, recipient) = action(&[
+            "set-recipient",
+            "--name",
+            "Stadtwerke",
+            "--iban",
+            "DE02120300000000202051",
+            "--address",
+            "Hauptstr. 1",
+            "--address",
+            "12345 Musterstadt",
+        ]);
+        let recipient = serde_json::to_value(recipient).unwrap();
+        let recipient = &recipient["SetRecipient"];
+        assert_eq!(recipient["receipt_id"], json!(id));
+        assert_eq!(recipient["address_line1"], "Hauptstr. 1");
+        assert_eq!(recipient["address_line2"], "12345 Musterstadt");
+        assert_eq!(recipient["address_line4"], "");
+    }
+
+    #[test]
+    fn list_and_output_options() {
+        let cli = parse(&["-o", "json", "list", "payed", "--tag", "rent"])
+            .expect("valid list");
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert_eq!(cli.server, "http://bills/api/v1");
+        match cli.command {
+            Command::List {
+                state,
+                tag,
+            } => {
+                assert_eq!(ReceiptState::from(state), ReceiptState::Payed);
+                assert_eq!(tag, vec!["rent".to_string()]);
+            },
+            other => panic!("parsed {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        let id = Uuid::new_v4().to_string();
+        for args in [
+            vec!["upload"],
+            vec!["list", "paid"],
+            vec!["show", "not-a-uuid"],
+            vec!["action", id.as_str(), "set-due-date", "2022-13-01"],
+            vec!["action", id.as_str(), "set-amount", "42.00"],
+            vec!["--output", "yaml", "overdue"],
+        ] {
+            assert!(parse(&args).is_err(), "{:?} was accepted", args);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use clap::ValueEnum;
+use serde_json::Value;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Table,
+}
+
+const RECEIPT_COLUMNS: [&str; 7] = [
+    "id",
+    "name",
+    "state",
+    "category_id",
+    "amount",
+    "due_date",
+    "payment_date",
+];
+const RECIPIENT_COLUMNS: [&str; 4] = ["id", "name", "iban", "address_line1"];
+pub const TAG_COLUMNS: [&str; 1] = ["name"];
+pub const COMMENT_COLUMNS: [&str; 4] = ["id", "created_at", "author", "body"];
+
+fn cell(value: &Value, column: &str) -> String {
+    match value.get(column) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => "-".to_string(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn table(columns: &[&str], rows: &[&Value]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| columns.iter().map(|c| cell(row, c)).collect())
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            cells.iter().map(|row| row[i].len()).max().unwrap_or(0).max(c.len())
+        })
+        .collect();
+
+    let header: Vec<String> = columns
+        .iter()
+        .zip(&widths)
+        .map(|(c, w)| format!("{:<w$}", c.to_uppercase(), w = w))
+        .collect();
+    let mut table = format!("{}\n", header.join("  ").trim_end());
+    for row in cells {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{:<w$}", c, w = w))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Splits a server answer into the receipts and recipients it contains. The
+/// API answers with single receipts, lists of receipts or
+/// `[receipt, recipient]` pairs.
+fn collect<'a>(
+    value: &'a Value,
+    receipts: &mut Vec<&'a Value>,
+    recipients: &mut Vec<&'a Value>,
+) {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                collect(value, receipts, recipients);
+            }
+        },
+        Value::Object(map) if map.contains_key("iban") => {
+            recipients.push(value)
+        },
+        Value::Object(_) => receipts.push(value),
+        _ => {},
+    }
+}
+
+fn render(format: OutputFormat, value: &Value) -> String {
+    match format {
+        OutputFormat::Json => format!(
+            "{}\n",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        ),
+        OutputFormat::Table => {
+            let mut receipts = Vec::new();
+            let mut recipients = Vec::new();
+            collect(value, &mut receipts, &mut recipients);
+            let mut output = table(&RECEIPT_COLUMNS, &receipts);
+            if !recipients.is_empty() {
+                output.push('\n');
+                output.push_str(&table(&RECIPIENT_COLUMNS, &recipients));
+            }
+            output
+        },
+    }
+}
+
+fn render_list(
+    format: OutputFormat,
+    columns: &[&str],
+    values: &[Value],
+) -> String {
+    match format {
+        OutputFormat::Json => render(format, &Value::Array(values.to_vec())),
+        OutputFormat::Table => {
+            table(columns, &values.iter().collect::<Vec<_>>())
+        },
+    }
+}
+
+pub fn print(format: OutputFormat, value: &Value) {
+    print!("{}", render(format, value));
+}
+
+/// Prints a list of other things than receipts, like tags, with `columns`
+/// as the table.
+pub fn print_list(format: OutputFormat, columns: &[&str], values: &[Value]) {
+    print!("{}", render_list(format, columns, values));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tables_align_columns_and_mark_missing_values() {
+        let receipts = json!([
+            {
+                "id": "a1",
+                "name": "power.pdf",
+                "state": "Inbox",
+                "amount": 4200,
+                "due_date": null,
+            },
+            { "id": "b2", "name": "rent.pdf", "state": "Payed" },
+        ]);
+        assert_eq!(
+            render(OutputFormat::Table, &receipts),
+            "ID  NAME       STATE  CATEGORY_ID  \
+             AMOUNT  DUE_DATE  PAYMENT_DATE\n\
+             a1  power.pdf  Inbox  -            4200    -         -\n\
+             b2  rent.pdf   Payed  -            -       -         -\n"
+        );
+    }
+
+    #[test]
+    fn recipients_get_their_own_table() {
+        let answer = json!([
+            { "id": "a1", "name": "power.pdf", "state": "Valid" },
+            {
+                "id": "r1",
+                "name": "Stadtwerke",
+                "iban": "DE02120300000000202051",
+                "address_line1": "",
+            },
+        ]);
+        let output = render(OutputFormat::Table, &answer);
+        let tables: Vec<&str> = output.split("\n\n").collect();
+        assert_eq!(tables.len(), 2);
+        assert!(tables[0].contains("power.pdf"));
+        assert!(tables[1].starts_with("ID  NAME        IBAN"));
+        assert!(tables[1].contains("Stadtwerke  DE02120300000000202051"));
+    }
+
+    #[test]
+    fn json_is_printed_as_is() {
+        let tags = [json!({ "name": "household" })];
+        assert_eq!(
+            render_list(OutputFormat::Json, &TAG_COLUMNS, &tags),
+            "[\n  {\n    \"name\": \"household\"\n  }\n]\n"
+        );
+        assert_eq!(
+            render_list(OutputFormat::Table, &TAG_COLUMNS, &tags),
+            "NAME\nhousehold\n"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+/// The share of a receipt one person or cost center bears. Either `amount`
+/// or `basis_points` is set.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize,
+)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "allocations")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub receipt_id: Uuid,
+    /// Name of the person or cost center.
+    pub party: String,
+    /// Fixed share in cents.
+    pub amount: Option<i64>,
+    /// Share of the total in hundredths of a percent, 2500 for a quarter.
+    pub basis_points: Option<i32>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::receipt::Entity",
+        from = "Column::ReceiptId",
+        to = "super::receipt::Column::Id"
+    )]
+    Receipt,
+}
+
+impl Related<super::receipt::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Receipt.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
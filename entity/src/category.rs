@@ -0,0 +1,41 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+/// Category receipts are booked on. Categories form a tree through
+/// `parent_id`.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize,
+)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "categories")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    #[sea_orm(unique)]
+    pub name: String,
+    pub parent_id: Option<Uuid>,
+    /// Amount that may be spent per month in this category and all of its
+    /// children, in cents.
+    pub monthly_budget: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "Entity",
+        from = "Column::ParentId",
+        to = "Column::Id"
+    )]
+    Parent,
+    #[sea_orm(has_many = "super::receipt::Entity")]
+    Receipt,
+}
+
+impl Related<super::receipt::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Receipt.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,36 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+/// A message in the discussion about a receipt.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize,
+)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "comments")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub receipt_id: Uuid,
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::receipt::Entity",
+        from = "Column::ReceiptId",
+        to = "super::receipt::Column::Id"
+    )]
+    Receipt,
+}
+
+impl Related<super::receipt::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Receipt.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
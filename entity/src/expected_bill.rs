@@ -0,0 +1,49 @@
+use chrono::NaiveDate;
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+/// A bill a [`super::recurring_bill`] says should arrive on `expected_on`,
+/// linked to the receipt that arrived for it once one did.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize,
+)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "expected_bills")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub recurring_bill_id: Uuid,
+    pub expected_on: NaiveDate,
+    pub receipt_id: Option<Uuid>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::recurring_bill::Entity",
+        from = "Column::RecurringBillId",
+        to = "super::recurring_bill::Column::Id"
+    )]
+    RecurringBill,
+    #[sea_orm(
+        belongs_to = "super::receipt::Entity",
+        from = "Column::ReceiptId",
+        to = "super::receipt::Column::Id"
+    )]
+    Receipt,
+}
+
+impl Related<super::recurring_bill::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecurringBill.def()
+    }
+}
+
+impl Related<super::receipt::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Receipt.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
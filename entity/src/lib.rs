@@ -1,2 +1,10 @@
+pub mod allocation;
+pub mod category;
+pub mod comment;
+pub mod expected_bill;
 pub mod receipt;
-pub mod recipient;
\ No newline at end of file
+pub mod receipt_tag;
+pub mod recipient;
+pub mod recurring_bill;
+pub mod rule;
+pub mod tag;
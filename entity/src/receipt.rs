@@ -1,4 +1,5 @@
 use chrono::NaiveDate;
+use rocket::form::ValueField;
 use rocket::serde::{Deserialize, Serialize};
 use sea_orm::entity::prelude::*;
 use thiserror::Error;
@@ -10,20 +11,74 @@ use uuid::Uuid;
 #[serde(crate = "rocket::serde")]
 #[sea_orm(table_name = "receipts")]
 pub struct Model {
-    #[sea_orm(primary_key)]
+    #[sea_orm(primary_key, auto_increment = false)]
     pub id: Uuid,
     pub name: String,
     pub state: ReceiptState,
     pub file_hash: String,
-    pub category: Option<String>,
+    pub category_id: Option<Uuid>,
     pub payment_date: Option<NaiveDate>,
+    /// Free-form information about where the receipt came from, e.g. the
+    /// sender and subject of the mail it was attached to.
+    pub metadata: Option<Json>,
+    /// Date the bill has to be payed by.
+    pub due_date: Option<NaiveDate>,
+    /// Last day an early payment discount can be taken.
+    pub discount_deadline: Option<NaiveDate>,
+    /// Day the last reminder about this receipt was sent.
+    pub reminded_on: Option<NaiveDate>,
+    /// Total of the bill in cents.
+    pub amount: Option<i64>,
+    /// Text extracted from the file for searching and rules. Left out of
+    /// API answers as it can be large.
+    #[serde(skip)]
+    pub content_text: Option<String>,
+    /// Counts the changes of the receipt, starting at 1. Edits name the
+    /// version they are based on to detect concurrent changes.
+    #[serde(default = "first_version")]
+    pub version: i32,
+    /// Person who payed the bill, owed the shares of the others by its
+    /// allocations.
+    pub paid_by: Option<String>,
+}
+
+/// Version of the receipts in backups made before receipts had one.
+fn first_version() -> i32 {
+    1
+}
+
+impl Model {
+    /// A receipt in the Inbox without any details yet, as uploads create it.
+    pub fn new(id: Uuid, name: &str, file_hash: &str) -> Self {
+        Model {
+            id,
+            name: name.to_string(),
+            state: ReceiptState::Inbox,
+            file_hash: file_hash.to_string(),
+            category_id: None,
+            payment_date: None,
+            metadata: None,
+            due_date: None,
+            discount_deadline: None,
+            reminded_on: None,
+            amount: None,
+            content_text: None,
+            version: 1,
+            paid_by: None,
+        }
+    }
+
+    /// An open receipt is overdue once its due date has passed.
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        self.state.is_open() && self.due_date.map_or(false, |due| due < today)
+    }
 }
 
 #[derive(
     Clone, Debug, PartialEq, Deserialize, Serialize, EnumIter, DeriveActiveEnum,
 )]
 #[serde(crate = "rocket::serde")]
-#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "receipt_state")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
 pub enum ReceiptState {
     #[sea_orm(string_value = "inbox")]
     Inbox,
@@ -39,38 +94,75 @@ pub enum ReceiptState {
     Done,
 }
 
-impl std::fmt::Display for ReceiptState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ReceiptState {
+    /// Name of the state in urls, forms and the database. Every other
+    /// conversion from and to strings is built on this.
+    pub fn as_str(&self) -> &'static str {
         match self {
-            ReceiptState::Inbox => write!(f, "inbox"),
-            ReceiptState::Valid => write!(f, "valid"),
-            ReceiptState::Payed => write!(f, "payed"),
-            ReceiptState::Declined => write!(f, "declined"),
-            ReceiptState::Process => write!(f, "process"),
-            ReceiptState::Done => write!(f, "done"),
+            ReceiptState::Inbox => "inbox",
+            ReceiptState::Valid => "valid",
+            ReceiptState::Payed => "payed",
+            ReceiptState::Declined => "declined",
+            ReceiptState::Process => "process",
+            ReceiptState::Done => "done",
         }
     }
+
+    /// Whether the receipt still waits to be payed or declined.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            ReceiptState::Inbox | ReceiptState::Valid | ReceiptState::Process
+        )
+    }
+
+    fn names() -> String {
+        ReceiptState::iter()
+            .map(|state| state.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl std::fmt::Display for ReceiptState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum ParseError {
-    #[error("could not parse {0} as Receipt state accepted are inbox, valid, payed, declined, process and done")]
+    #[error(
+        "could not parse {0} as Receipt state accepted are {}",
+        ReceiptState::names()
+    )]
     ReceiptState(String),
 }
 
+impl std::str::FromStr for ReceiptState {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ReceiptState::iter()
+            .find(|state| state.as_str() == s)
+            .ok_or_else(|| ParseError::ReceiptState(s.to_string()))
+    }
+}
+
 impl<'a> rocket::request::FromParam<'a> for ReceiptState {
     type Error = ParseError;
 
     fn from_param(param: &'a str) -> Result<Self, Self::Error> {
-        match param {
-            "inbox" => Ok(Self::Inbox),
-            "valid" => Ok(Self::Valid),
-            "payed" => Ok(Self::Valid),
-            "declined" => Ok(Self::Valid),
-            "process" => Ok(Self::Valid),
-            "done" => Ok(Self::Valid),
-            x => Err(ParseError::ReceiptState(x.to_string())),
-        }
+        param.parse()
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> rocket::form::FromFormField<'v> for ReceiptState {
+    fn from_value(field: ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse().map_err(|err: ParseError| {
+            rocket::form::Error::validation(err.to_string()).into()
+        })
     }
 }
 
@@ -78,6 +170,16 @@ impl<'a> rocket::request::FromParam<'a> for ReceiptState {
 pub enum Relation {
     #[sea_orm(has_one = "super::recipient::Entity")]
     Recipient,
+    #[sea_orm(has_many = "super::comment::Entity")]
+    Comment,
+    #[sea_orm(has_many = "super::allocation::Entity")]
+    Allocation,
+    #[sea_orm(
+        belongs_to = "super::category::Entity",
+        from = "Column::CategoryId",
+        to = "super::category::Column::Id"
+    )]
+    Category,
 }
 
 impl Related<super::recipient::Entity> for Entity {
@@ -86,4 +188,119 @@ impl Related<super::recipient::Entity> for Entity {
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
+impl Related<super::category::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Category.def()
+    }
+}
+
+impl Related<super::comment::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Comment.def()
+    }
+}
+
+impl Related<super::allocation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Allocation.def()
+    }
+}
+
+impl Related<super::tag::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::receipt_tag::Relation::Tag.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::receipt_tag::Relation::Receipt.def().rev())
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    /// Every update of a loaded receipt counts as a new version.
+    fn before_save(mut self, insert: bool) -> Result<Self, DbErr> {
+        if !insert {
+            if let ActiveValue::Unchanged(version) | ActiveValue::Set(version) =
+                self.version
+            {
+                self.version = ActiveValue::Set(version + 1);
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::form::Form;
+    use rocket::request::FromParam;
+
+    #[derive(rocket::FromForm)]
+    struct Filter {
+        state: ReceiptState,
+    }
+
+    #[test]
+    fn string_conversions_round_trip() {
+        for state in ReceiptState::iter() {
+            let name = state.to_string();
+            assert_eq!(name, state.as_str());
+            assert_eq!(name.parse::<ReceiptState>(), Ok(state.clone()));
+            assert_eq!(ReceiptState::from_param(&name), Ok(state.clone()));
+
+            let form = Form::<Filter>::parse(&format!("state={}", name))
+                .expect("valid form");
+            assert_eq!(form.state, state);
+        }
+    }
+
+    #[test]
+    fn database_values_match_names() {
+        for state in ReceiptState::iter() {
+            assert_eq!(state.to_value(), state.as_str());
+            assert_eq!(
+                ReceiptState::try_from_value(&state.to_value()).ok(),
+                Some(state)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        for name in ["", "Inbox", "paid", "done ", "receipt_state"] {
+            assert_eq!(
+                name.parse::<ReceiptState>(),
+                Err(ParseError::ReceiptState(name.to_string()))
+            );
+            assert!(ReceiptState::from_param(name).is_err());
+            assert!(Form::<Filter>::parse(&format!("state={}", name)).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_error_lists_every_state() {
+        let message = "x".parse::<ReceiptState>().unwrap_err().to_string();
+        for state in ReceiptState::iter() {
+            assert!(message.contains(state.as_str()));
+        }
+    }
+
+    #[test]
+    fn updates_count_as_new_versions() {
+        let receipt = Model {
+            version: 3,
+            ..Model::new(Uuid::nil(), "rent", "")
+        };
+        let update = ActiveModel::from(receipt).before_save(false).unwrap();
+        assert_eq!(update.version, ActiveValue::Set(4));
+        let insert = ActiveModel {
+            version: ActiveValue::Set(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            insert.before_save(true).unwrap().version,
+            ActiveValue::Set(1)
+        );
+    }
+}
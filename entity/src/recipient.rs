@@ -7,7 +7,7 @@ use uuid::Uuid;
 #[serde(crate = "rocket::serde")]
 #[sea_orm(table_name = "recipients")]
 pub struct Model {
-    #[sea_orm(primary_key)]
+    #[sea_orm(primary_key, auto_increment = false)]
     pub id: Uuid,
     pub receipt_id: Uuid,
     pub name: String,
@@ -0,0 +1,160 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+/// Template of a bill that arrives regularly, e.g. rent or insurance.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize,
+)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "recurring_bills")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub name: String,
+    pub recipient_name: String,
+    pub recipient_iban: Option<String>,
+    pub category_id: Option<Uuid>,
+    /// Amount the bill usually has, in cents.
+    pub expected_amount: i64,
+    pub cadence: Cadence,
+    /// Date the first bill is expected on. Later ones follow the cadence.
+    pub starts_on: NaiveDate,
+    /// Case-insensitive part of the file name incoming receipts are matched
+    /// on.
+    pub match_pattern: String,
+    /// Percent a bill may exceed `expected_amount` before it is flagged.
+    pub tolerance_percent: i32,
+    /// Days after the expected date before a bill counts as missing.
+    pub grace_days: i32,
+    pub active: bool,
+}
+
+impl Model {
+    /// Whether an incoming file called `name` belongs to this template.
+    pub fn matches(&self, name: &str) -> bool {
+        !self.match_pattern.is_empty()
+            && name.to_lowercase().contains(&self.match_pattern.to_lowercase())
+    }
+
+    /// Whether `amount` is more than the tolerance above the expected
+    /// amount. Compared in `i128`, as neither amount is bounded.
+    pub fn is_unusually_large(&self, amount: i64) -> bool {
+        i128::from(amount) * 100
+            > i128::from(self.expected_amount)
+                * (100 + i128::from(self.tolerance_percent))
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Deserialize,
+    Serialize,
+    EnumIter,
+    DeriveActiveEnum,
+)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum Cadence {
+    #[sea_orm(string_value = "weekly")]
+    Weekly,
+    #[sea_orm(string_value = "monthly")]
+    Monthly,
+    #[sea_orm(string_value = "quarterly")]
+    Quarterly,
+    #[sea_orm(string_value = "yearly")]
+    Yearly,
+}
+
+/// Adds `months` to `date`, clamping the day to the end of shorter months.
+fn add_months(date: NaiveDate, months: u32) -> NaiveDate {
+    let month0 = date.month0() + months;
+    let year = date.year() + (month0 / 12) as i32;
+    let month = month0 % 12 + 1;
+    (0..4)
+        .find_map(|back| {
+            NaiveDate::from_ymd_opt(year, month, date.day() - back)
+        })
+        .expect("every month has at least 28 days")
+}
+
+impl Cadence {
+    /// Date of the `n`th bill of a series starting on `start`. Counting from
+    /// the start instead of the previous bill keeps e.g. the 31st from
+    /// drifting to the 28th after February.
+    pub fn nth(&self, start: NaiveDate, n: u32) -> NaiveDate {
+        match self {
+            Cadence::Weekly => start + Duration::weeks(i64::from(n)),
+            Cadence::Monthly => add_months(start, n),
+            Cadence::Quarterly => add_months(start, 3 * n),
+            Cadence::Yearly => add_months(start, 12 * n),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::expected_bill::Entity")]
+    ExpectedBill,
+}
+
+impl Related<super::expected_bill::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ExpectedBill.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(year, month, day)
+    }
+
+    #[test]
+    fn cadence_keeps_day_of_month() {
+        let start = date(2022, 1, 31);
+        assert_eq!(Cadence::Monthly.nth(start, 0), start);
+        assert_eq!(Cadence::Monthly.nth(start, 1), date(2022, 2, 28));
+        assert_eq!(Cadence::Monthly.nth(start, 2), date(2022, 3, 31));
+        assert_eq!(Cadence::Monthly.nth(start, 13), date(2023, 2, 28));
+        assert_eq!(Cadence::Quarterly.nth(start, 1), date(2022, 4, 30));
+        assert_eq!(
+            Cadence::Yearly.nth(date(2020, 2, 29), 1),
+            date(2021, 2, 28)
+        );
+        assert_eq!(Cadence::Weekly.nth(start, 1), date(2022, 2, 7));
+    }
+
+    #[test]
+    fn huge_amounts_are_compared_without_overflow() {
+        let template = Model {
+            id: Uuid::nil(),
+            name: "rent".into(),
+            recipient_name: "Landlord".into(),
+            recipient_iban: None,
+            category_id: None,
+            expected_amount: i64::MAX,
+            cadence: Cadence::Monthly,
+            starts_on: date(2022, 1, 1),
+            match_pattern: "rent".into(),
+            tolerance_percent: 10,
+            grace_days: 5,
+            active: true,
+        };
+        assert!(!template.is_unusually_large(i64::MAX));
+        let template = Model {
+            expected_amount: 100_000,
+            ..template
+        };
+        assert!(template.is_unusually_large(i64::MAX));
+        assert!(!template.is_unusually_large(110_000));
+    }
+}
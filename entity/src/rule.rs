@@ -0,0 +1,55 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+/// A user-defined rule that fills in new receipts. A rule matches if all of
+/// its conditions that are set match, and then applies all of its actions
+/// that are set.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize,
+)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "rules")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub name: String,
+    /// Rules run in ascending priority. The first rule setting a field wins.
+    pub priority: i32,
+    pub enabled: bool,
+    /// IBAN of the recipient, compared without spaces and case.
+    pub recipient_iban: Option<String>,
+    /// Case-insensitive part of the name of the recipient.
+    pub recipient_name: Option<String>,
+    /// Pattern of the receipt name with `*` and `?` wildcards.
+    pub name_pattern: Option<String>,
+    /// Case-insensitive part of the text extracted from the file.
+    pub text_contains: Option<String>,
+    /// Smallest matching amount in cents.
+    pub min_amount: Option<i64>,
+    /// Largest matching amount in cents.
+    pub max_amount: Option<i64>,
+    pub set_category_id: Option<Uuid>,
+    pub set_recipient_name: Option<String>,
+    pub set_recipient_iban: Option<String>,
+    /// Moves matching receipts from the Inbox to valid.
+    pub auto_accept: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::category::Entity",
+        from = "Column::SetCategoryId",
+        to = "super::category::Column::Id"
+    )]
+    Category,
+}
+
+impl Related<super::category::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Category.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,41 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+/// A label receipts can carry any number of, next to their single category.
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Deserialize, Serialize,
+)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "tags")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    /// Trimmed and lowercase, so tags differing only in case are the same.
+    #[sea_orm(unique)]
+    pub name: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::receipt_tag::Entity")]
+    ReceiptTag,
+}
+
+impl Related<super::receipt_tag::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReceiptTag.def()
+    }
+}
+
+impl Related<super::receipt::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::receipt_tag::Relation::Receipt.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::receipt_tag::Relation::Tag.def().rev())
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}